@@ -0,0 +1,112 @@
+use criterion::{Criterion, criterion_group, criterion_main};
+use monkeycc::{
+    ast::Program, compiler::Compiler, evaluator::Interpreter, lexer::Lexer, object::Object,
+    parser::Parser, vm,
+};
+use std::hint::black_box;
+
+fn parse(src: &str) -> Program {
+    let mut p = Parser::new(Lexer::new(src));
+    let program = p.parse_program().expect("benchmark source should parse");
+    assert!(p.errors().is_empty(), "{:?}", p.errors());
+    program
+}
+
+// 只测量求值 解析和解释器的创建放在计时之外 不加载标准库
+fn bench_eval(c: &mut Criterion, name: &str, src: &str) {
+    let program = parse(src);
+    let mut interp = Interpreter::new().prelude(false);
+    if let Object::Error(msg) = interp.eval(&program) {
+        panic!("benchmark source should evaluate: {}", msg);
+    }
+    c.bench_function(name, |b| b.iter(|| interp.eval(black_box(&program))));
+}
+
+fn bench_arithmetic(c: &mut Criterion) {
+    let src = (0..2_000)
+        .map(|i| format!("(5 + 10 * 2 + 15 / 3) * 2 + -{i};"))
+        .collect::<String>();
+    bench_eval(c, "evaluator/arithmetic", &src);
+}
+
+fn bench_conditionals(c: &mut Criterion) {
+    let src = (0..2_000)
+        .map(|i| {
+            format!("if ({i} > 1000) {{ if ({i} < 1500) {{ 1 }} else {{ 2 }} }} else {{ 3 }};")
+        })
+        .collect::<String>();
+    bench_eval(c, "evaluator/conditionals", &src);
+}
+
+const FIB: &str = r"
+    let fib = fn(n) {
+        if (n < 2) { return n; }
+        fib(n - 1) + fib(n - 2)
+    };
+    fib(20);";
+
+fn bench_fib(c: &mut Criterion) {
+    bench_eval(c, "evaluator/fib", FIB);
+}
+
+// 闭包多层嵌套 变量大多从外层函数里取
+const CLOSURES: &str = r"
+    let compose = fn(f, g) { fn(x) { g(f(x)) } };
+    let adder = fn(a) { fn(b) { fn(c) { a + b + c } } };
+    let run = fn(n, acc) {
+        if (n == 0) { return acc; }
+        let inc = compose(adder(n)(1), adder(acc)(2));
+        run(n - 1, inc(0) - acc - n - 3)
+    };
+    run(200, 0);";
+
+fn bench_closures(c: &mut Criterion) {
+    bench_eval(c, "evaluator/closures", CLOSURES);
+}
+
+// 数组和 hash: push 建数组 按元素建索引 再逐个查回来
+const COLLECTIONS: &str = r"
+    let build = fn(xs, i, n) {
+        if (i == n) { return xs; }
+        build(push(xs, i * 2), i + 1, n)
+    };
+    let xs = build([], 0, 2000);
+    let index = fn(h, i) {
+        if (i == len(xs)) { return h; }
+        h[xs[i]] = i;
+        index(h, i + 1)
+    };
+    let h = index({}, 0);
+    let sum = fn(i, acc) {
+        if (i == len(xs)) { return acc; }
+        sum(i + 1, acc + h[xs[i]])
+    };
+    sum(0, 0);";
+
+fn bench_collections(c: &mut Criterion) {
+    bench_eval(c, "evaluator/collections", COLLECTIONS);
+}
+
+// 同样的程序走字节码后端 编译放在计时之外
+fn bench_vm_fib(c: &mut Criterion) {
+    let program = parse(FIB);
+    let mut compiler = Compiler::new();
+    compiler
+        .compile(&program)
+        .expect("benchmark source should compile");
+    let bytecode = compiler.bytecode();
+    c.bench_function("vm/fib", |b| {
+        b.iter(|| vm::Vm::new(black_box(bytecode.clone())).run())
+    });
+}
+
+criterion_group!(
+    benches,
+    bench_arithmetic,
+    bench_conditionals,
+    bench_fib,
+    bench_closures,
+    bench_collections,
+    bench_vm_fib
+);
+criterion_main!(benches);
@@ -0,0 +1,35 @@
+use criterion::{Criterion, Throughput, criterion_group, criterion_main};
+use monkeycc::{lexer::Lexer, token::TokenType};
+use std::hint::black_box;
+
+// 生成一段足够大的monkey源码 覆盖标识符 数字 关键字和各类运算符
+fn generate_source(lines: usize) -> String {
+    let mut src = String::new();
+    for i in 0..lines {
+        src.push_str(&format!(
+            "let value = fn(x, y) {{ if (x <= y) {{ return x * {i} + y; }} else {{ !(x != y) }} }};\n"
+        ));
+    }
+    src
+}
+
+fn bench_lexer(c: &mut Criterion) {
+    let src = generate_source(10_000);
+
+    let mut group = c.benchmark_group("lexer");
+    group.throughput(Throughput::Bytes(src.len() as u64));
+    group.bench_function("next_token", |b| {
+        b.iter(|| {
+            let mut l = Lexer::new(black_box(&src));
+            let mut count = 0usize;
+            while l.next_token().token_type != TokenType::Eof {
+                count += 1;
+            }
+            count
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_lexer);
+criterion_main!(benches);
@@ -0,0 +1,91 @@
+use criterion::{BenchmarkId, Criterion, Throughput, criterion_group, criterion_main};
+use monkeycc::{
+    lexer::Lexer,
+    parser::{Parser, Precedence},
+};
+use std::hint::black_box;
+
+// 生成 depth 层嵌套的括号表达式: (((1 + 1) * 2) - 3) ...
+fn nested_expression(depth: usize) -> String {
+    let ops = ["+", "*", "-", "/"];
+    let mut src = "1".to_string();
+    for i in 0..depth {
+        src = format!("({} {} {})", src, ops[i % ops.len()], i + 1);
+    }
+    src
+}
+
+// 生成一条很长的扁平中缀链 用于测试优先级折叠
+fn flat_expression(terms: usize) -> String {
+    let ops = [" + ", " * ", " - ", " < ", " == "];
+    let mut src = String::new();
+    for i in 0..terms {
+        if i > 0 {
+            src.push_str(ops[i % ops.len()]);
+        }
+        src.push_str(&(i + 1).to_string());
+    }
+    src
+}
+
+fn bench_parse_expression(c: &mut Criterion) {
+    let mut group = c.benchmark_group("parser/parse_expression");
+    // 深度都在 MAX_NESTING_DEPTH (128) 以内 测的是正常解析
+    for depth in [16, 64, 120] {
+        let src = nested_expression(depth);
+        let mut p = Parser::new(Lexer::new(&src));
+        p.parse_expression(Precedence::Lowest);
+        assert!(
+            p.errors().is_empty(),
+            "depth {} hit the nesting limit",
+            depth
+        );
+
+        group.throughput(Throughput::Bytes(src.len() as u64));
+        group.bench_with_input(BenchmarkId::new("nested", depth), &src, |b, src| {
+            b.iter(|| {
+                let mut p = Parser::new(Lexer::new(black_box(src)));
+                p.parse_expression(Precedence::Lowest)
+            })
+        });
+    }
+    // 超过上限: 报一个错误后跳过剩下的输入 单独测这条拒绝路径
+    let src = nested_expression(512);
+    group.throughput(Throughput::Bytes(src.len() as u64));
+    group.bench_with_input(BenchmarkId::new("nested_rejected", 512), &src, |b, src| {
+        b.iter(|| {
+            let mut p = Parser::new(Lexer::new(black_box(src)));
+            p.parse_expression(Precedence::Lowest)
+        })
+    });
+    for terms in [1_000, 10_000] {
+        let src = flat_expression(terms);
+        group.throughput(Throughput::Bytes(src.len() as u64));
+        group.bench_with_input(BenchmarkId::new("flat", terms), &src, |b, src| {
+            b.iter(|| {
+                let mut p = Parser::new(Lexer::new(black_box(src)));
+                p.parse_expression(Precedence::Lowest)
+            })
+        });
+    }
+    group.finish();
+}
+
+fn bench_parse_program(c: &mut Criterion) {
+    let mut src = String::new();
+    for i in 0..5_000 {
+        src.push_str(&format!(
+            "let x = fn(a, b) {{ if (a < b) {{ return a * {i}; }} else {{ b - a }} }};\n"
+        ));
+    }
+
+    let mut group = c.benchmark_group("parser");
+    group.throughput(Throughput::Bytes(src.len() as u64));
+    group.bench_function("parse_program", |b| {
+        b.iter(|| Parser::new(Lexer::new(black_box(&src))).parse_program())
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_parse_expression, bench_parse_program);
+criterion_main!(benches);
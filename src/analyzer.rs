@@ -0,0 +1,493 @@
+use std::fmt;
+
+use ahash::{AHashMap as HashMap, AHashSet as HashSet};
+
+use crate::{
+    ast::{Expr, Function, MatchArm, Program, Statement, StringPart},
+    builtins, stdlib,
+    token::Position,
+};
+
+// 静态检查发现的问题 位置是所在语句的起点
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub pos: Position,
+    pub message: String,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.pos, self.message)
+    }
+}
+
+// 一个作用域: 顶层或者一个函数体 (monkey 的 if block 不开新作用域)
+#[derive(Debug, Default)]
+struct Scope {
+    // 执行到当前语句时已经绑定的名字 值是直接绑定的函数字面量的参数个数
+    declared: HashMap<String, Option<usize>>,
+    // 整个作用域里所有 let 的名字 内层函数稍后才执行 可以看到它们
+    hoisted: HashMap<String, Option<usize>>,
+}
+
+#[derive(Debug)]
+pub struct Analyzer {
+    allow_top_level_return: bool,
+    // 标准库的 prelude 会不会被加载 (解释器默认会)
+    prelude: bool,
+    globals: HashSet<String>,
+    scopes: Vec<Scope>,
+    // 当前位置嵌套了几层函数 0 表示在顶层
+    fn_depth: usize,
+    // 当前语句的位置 表达式没有自己的位置 报错时用它
+    pos: Position,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Default for Analyzer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/* ========== public entry ========== */
+
+pub fn analyze(program: &Program) -> Vec<Diagnostic> {
+    Analyzer::new().analyze(program)
+}
+
+impl Analyzer {
+    pub fn new() -> Self {
+        Analyzer {
+            allow_top_level_return: true,
+            prelude: true,
+            globals: HashSet::new(),
+            scopes: Vec::new(),
+            fn_depth: 0,
+            pos: Position::default(),
+            diagnostics: Vec::new(),
+        }
+    }
+
+    // 顶层 return 会直接结束程序 脚本可以选择禁止它
+    pub fn allow_top_level_return(mut self, allow: bool) -> Self {
+        self.allow_top_level_return = allow;
+        self
+    }
+
+    // 关掉之后 map filter 这些 prelude 里的名字算未定义
+    pub fn prelude(mut self, prelude: bool) -> Self {
+        self.prelude = prelude;
+        self
+    }
+
+    // 已经存在的全局名字 (比如 REPL 之前输入里 let 过的)
+    pub fn with_globals<I, S>(mut self, names: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.globals.extend(names.into_iter().map(Into::into));
+        self
+    }
+
+    pub fn analyze(mut self, program: &Program) -> Vec<Diagnostic> {
+        self.push_scope(&program.statements);
+        for stmt in &program.statements {
+            self.statement(stmt);
+        }
+        self.scopes.pop();
+
+        self.diagnostics.sort_by_key(|d| d.pos);
+        self.diagnostics
+    }
+
+    /* ========== statements ========== */
+
+    fn statement(&mut self, stmt: &Statement) {
+        if let Some(pos) = stmt.pos() {
+            self.pos = pos;
+        }
+        match stmt {
+            Statement::Let { pattern, value, .. } => {
+                self.expr(value);
+                // if 分支里的 let 不一定执行 参数个数和之前不一致时就不再检查
+                // 解构出来的名字不知道是什么值
+                let arity = pattern.as_ident().and_then(|_| fn_arity(value));
+                let declared = &mut self.current().declared;
+                for name in pattern.idents() {
+                    let arity = match declared.get(&name.0) {
+                        Some(prev) if *prev != arity => None,
+                        _ => arity,
+                    };
+                    declared.insert(name.0.clone(), arity);
+                }
+            }
+            Statement::Import { name, .. } => {
+                self.current().declared.insert(name.0.clone(), None);
+            }
+            Statement::Return(r) => {
+                if self.fn_depth == 0 && !self.allow_top_level_return {
+                    self.error("return outside of a function".to_string());
+                }
+                self.expr(&r.return_value);
+            }
+            Statement::Expression(e) => self.expr(&e.expression),
+            Statement::Block(block) => {
+                for s in &block.statements {
+                    self.statement(s);
+                }
+            }
+            Statement::None => {}
+        }
+    }
+
+    /* ========== expressions ========== */
+
+    fn expr(&mut self, e: &Expr) {
+        match e {
+            Expr::Ident(ident) => {
+                if !self.is_bound(&ident.0) {
+                    self.error(format!("undefined identifier: {}", ident.0));
+                }
+            }
+            // 只能给已经绑定的名字赋值 内建函数不在环境里 也不能赋值
+            Expr::Assign { target, value, .. } => {
+                match &**target {
+                    Expr::Ident(ident) => {
+                        if self.lookup(&ident.0).is_some()
+                            || self.globals.contains(&ident.0)
+                            || (self.prelude && stdlib::is_prelude(&ident.0))
+                        {
+                            self.forget_arity(&ident.0);
+                        } else {
+                            self.error(format!("assignment to undeclared variable: {}", ident.0));
+                        }
+                    }
+                    target => self.expr(target),
+                }
+                self.expr(value);
+            }
+            Expr::Prefix { right, .. } => self.expr(right),
+            Expr::Infix { left, right, .. } | Expr::Coalesce { left, right } => {
+                self.expr(left);
+                self.expr(right);
+            }
+            Expr::Conditional {
+                condition,
+                consequence,
+                alternative,
+            } => {
+                self.expr(condition);
+                self.expr(consequence);
+                self.expr(alternative);
+            }
+            Expr::IfExpression {
+                condition,
+                consequence,
+                alternative,
+            } => {
+                self.expr(condition);
+                self.statement(consequence);
+                if let Some(alternative) = alternative {
+                    self.statement(alternative);
+                }
+            }
+            Expr::Match { subject, arms } => {
+                self.expr(subject);
+                for arm in arms {
+                    self.arm(arm);
+                }
+            }
+            Expr::Fn(func) => self.function(func),
+            Expr::Call {
+                function,
+                arguments,
+            } => {
+                self.expr(function);
+                for a in arguments {
+                    self.expr(a);
+                }
+                self.call(function, arguments.len());
+            }
+            Expr::Array(elements) => {
+                for e in elements {
+                    self.expr(e);
+                }
+            }
+            Expr::Hash(pairs) => {
+                for (k, v) in pairs {
+                    self.expr(k);
+                    self.expr(v);
+                }
+            }
+            Expr::Index { left, index, .. } => {
+                self.expr(left);
+                self.expr(index);
+            }
+            // 模块导出了哪些名字要运行时加载了才知道
+            Expr::Member { object, .. } => self.expr(object),
+            Expr::Interpolated(parts) => {
+                for part in parts {
+                    if let StringPart::Expr(e) = part {
+                        self.expr(e);
+                    }
+                }
+            }
+            Expr::Integer(_) | Expr::Float(_) | Expr::Boolean(_) | Expr::String(_) | Expr::None => {
+            }
+        }
+    }
+
+    fn function(&mut self, func: &Function) {
+        let body: &[Statement] = match &*func.body {
+            Statement::Block(block) => &block.statements,
+            other => std::slice::from_ref(other),
+        };
+        self.push_scope(body);
+
+        for name in func.parameters.iter().flat_map(|p| p.pattern.idents()) {
+            if self
+                .current()
+                .declared
+                .insert(name.0.clone(), None)
+                .is_some()
+            {
+                self.error(format!("duplicate parameter: {}", name.0));
+            }
+        }
+
+        let saved = self.pos;
+        self.fn_depth += 1;
+        for stmt in body {
+            self.statement(stmt);
+        }
+        self.fn_depth -= 1;
+        self.pos = saved;
+
+        self.scopes.pop();
+    }
+
+    // 分支有自己的作用域: 模式绑定的名字 守卫和表达式里的 let
+    fn arm(&mut self, arm: &MatchArm) {
+        let mut scope = Scope::default();
+        if let Some(guard) = &arm.guard {
+            collect_expr_lets(guard, &mut scope.hoisted);
+        }
+        collect_expr_lets(&arm.body, &mut scope.hoisted);
+        for name in arm.pattern.idents() {
+            scope.declared.insert(name.0.clone(), None);
+        }
+        self.scopes.push(scope);
+        if let Some(guard) = &arm.guard {
+            self.expr(guard);
+        }
+        self.expr(&arm.body);
+        self.scopes.pop();
+    }
+
+    // 调用: 字面量显然不是函数 直接绑定的函数检查参数个数
+    fn call(&mut self, function: &Expr, argc: usize) {
+        let want = match function {
+            Expr::Integer(_)
+            | Expr::Float(_)
+            | Expr::Boolean(_)
+            | Expr::String(_)
+            | Expr::Interpolated(_)
+            | Expr::Array(_)
+            | Expr::Hash(_)
+            | Expr::Prefix { .. }
+            | Expr::Infix { .. } => {
+                self.error(format!("cannot call non-function {}", function.string()));
+                return;
+            }
+            Expr::Fn(func) => Some(func.parameters.len()),
+            Expr::Ident(ident) => self.arity_of(&ident.0),
+            _ => None,
+        };
+
+        if let Some(want) = want
+            && want != argc
+        {
+            self.error(format!(
+                "wrong number of arguments to {}: want={}, got={}",
+                function.string(),
+                want,
+                argc
+            ));
+        }
+    }
+
+    /* ========== scopes ========== */
+
+    fn push_scope(&mut self, body: &[Statement]) {
+        let mut scope = Scope::default();
+        collect_lets(body, &mut scope.hoisted);
+        self.scopes.push(scope);
+    }
+
+    fn current(&mut self) -> &mut Scope {
+        self.scopes.last_mut().expect("no scope")
+    }
+
+    // 当前作用域按执行顺序检查 外层作用域 (函数稍后才会被调用) 看整个作用域
+    fn lookup(&self, name: &str) -> Option<Option<usize>> {
+        let (innermost, outer) = self.scopes.split_last()?;
+        if let Some(arity) = innermost.declared.get(name) {
+            return Some(*arity);
+        }
+        for scope in outer.iter().rev() {
+            if let Some(arity) = scope.hoisted.get(name).or(scope.declared.get(name)) {
+                return Some(*arity);
+            }
+        }
+        None
+    }
+
+    // 被重新赋值过的名字不知道现在是几个参数的函数了 和 lookup 找的是同一个绑定
+    fn forget_arity(&mut self, name: &str) {
+        let Some((innermost, outer)) = self.scopes.split_last_mut() else {
+            return;
+        };
+        if let Some(arity) = innermost.declared.get_mut(name) {
+            *arity = None;
+            return;
+        }
+        for scope in outer.iter_mut().rev() {
+            let hoisted = scope.hoisted.get_mut(name).map(|a| *a = None);
+            let declared = scope.declared.get_mut(name).map(|a| *a = None);
+            if hoisted.is_some() || declared.is_some() {
+                return;
+            }
+        }
+    }
+
+    fn is_bound(&self, name: &str) -> bool {
+        self.lookup(name).is_some()
+            || self.globals.contains(name)
+            || builtins::lookup(name).is_some()
+            || (self.prelude && stdlib::is_prelude(name))
+    }
+
+    // 没有被覆盖的内建函数也检查参数个数
+    fn arity_of(&self, name: &str) -> Option<usize> {
+        match self.lookup(name) {
+            Some(arity) => arity,
+            None if self.globals.contains(name) => None,
+            None => builtins::lookup(name).and_then(|b| b.arity()),
+        }
+    }
+
+    fn error(&mut self, message: String) {
+        self.diagnostics.push(Diagnostic {
+            pos: self.pos,
+            message,
+        });
+    }
+}
+
+/* ========== helpers ========== */
+
+fn fn_arity(e: &Expr) -> Option<usize> {
+    match e {
+        Expr::Fn(func) => Some(func.parameters.len()),
+        _ => None,
+    }
+}
+
+// 收集一个作用域里所有的 let (进入 if 分支 不进入内层函数和 match 分支)
+// 同名多次绑定且参数个数不一致时 就不再检查参数个数
+fn collect_lets(stmts: &[Statement], out: &mut HashMap<String, Option<usize>>) {
+    for stmt in stmts {
+        match stmt {
+            Statement::Let { pattern, value, .. } => {
+                let arity = pattern.as_ident().and_then(|_| fn_arity(value));
+                for name in pattern.idents() {
+                    out.entry(name.0.clone())
+                        .and_modify(|a| {
+                            if *a != arity {
+                                *a = None
+                            }
+                        })
+                        .or_insert(arity);
+                }
+                collect_expr_lets(value, out);
+            }
+            Statement::Import { name, .. } => {
+                out.insert(name.0.clone(), None);
+            }
+            Statement::Expression(e) => collect_expr_lets(&e.expression, out),
+            Statement::Return(r) => collect_expr_lets(&r.return_value, out),
+            Statement::Block(block) => collect_lets(&block.statements, out),
+            Statement::None => {}
+        }
+    }
+}
+
+fn collect_expr_lets(e: &Expr, out: &mut HashMap<String, Option<usize>>) {
+    match e {
+        Expr::IfExpression {
+            condition,
+            consequence,
+            alternative,
+        } => {
+            collect_expr_lets(condition, out);
+            collect_lets(std::slice::from_ref(&**consequence), out);
+            if let Some(alternative) = alternative {
+                collect_lets(std::slice::from_ref(&**alternative), out);
+            }
+        }
+        Expr::Assign { target, value, .. } => {
+            collect_expr_lets(target, out);
+            collect_expr_lets(value, out);
+        }
+        Expr::Prefix { right, .. } => collect_expr_lets(right, out),
+        Expr::Infix { left, right, .. } | Expr::Coalesce { left, right } => {
+            collect_expr_lets(left, out);
+            collect_expr_lets(right, out);
+        }
+        Expr::Conditional {
+            condition,
+            consequence,
+            alternative,
+        } => {
+            collect_expr_lets(condition, out);
+            collect_expr_lets(consequence, out);
+            collect_expr_lets(alternative, out);
+        }
+        Expr::Call {
+            function,
+            arguments,
+        } => {
+            collect_expr_lets(function, out);
+            for a in arguments {
+                collect_expr_lets(a, out);
+            }
+        }
+        Expr::Array(elements) => {
+            for e in elements {
+                collect_expr_lets(e, out);
+            }
+        }
+        Expr::Hash(pairs) => {
+            for (k, v) in pairs {
+                collect_expr_lets(k, out);
+                collect_expr_lets(v, out);
+            }
+        }
+        Expr::Index { left, index, .. } => {
+            collect_expr_lets(left, out);
+            collect_expr_lets(index, out);
+        }
+        Expr::Member { object, .. } => collect_expr_lets(object, out),
+        Expr::Match { subject, .. } => collect_expr_lets(subject, out),
+        Expr::Interpolated(parts) => {
+            for part in parts {
+                if let StringPart::Expr(e) = part {
+                    collect_expr_lets(e, out);
+                }
+            }
+        }
+        _ => {}
+    }
+}
@@ -0,0 +1,282 @@
+use std::rc::Rc;
+
+use ahash::AHashMap as HashMap;
+
+use crate::{
+    code::{Instructions, LineTable, Opcode, read_operands, validate},
+    compiler::Bytecode,
+    object::{CompiledFunction, Object},
+};
+
+// .mkc 预编译产物
+//
+//   magic     b"MKC\0"
+//   version   u16
+//   globals   u32 个数 + 每个名字 (u32 长度 + utf8)
+//   constants u32 个数 + 每个常量 (u8 tag + 内容)
+//             函数常量: 局部变量个数 参数个数 局部变量名 (u32 个数 + 名字) 指令和行号表
+//   main      指令 (u32 长度 + 字节) + 行号表 (u32 个数 + (u32 偏移, u32 行号))
+//
+// 所有整数都是大端序 和指令操作数保持一致
+pub const MAGIC: &[u8; 4] = b"MKC\0";
+pub const VERSION: u16 = 2;
+
+const TAG_INTEGER: u8 = 0;
+const TAG_FUNCTION: u8 = 1;
+
+pub fn is_artifact(bytes: &[u8]) -> bool {
+    bytes.starts_with(MAGIC)
+}
+
+/* ========== encode ========== */
+
+pub fn encode(bytecode: &Bytecode) -> Result<Vec<u8>, String> {
+    let mut out = Vec::new();
+    out.extend_from_slice(MAGIC);
+    out.extend_from_slice(&VERSION.to_be_bytes());
+
+    write_len(&mut out, bytecode.global_names.len());
+    for name in &bytecode.global_names {
+        write_bytes(&mut out, name.as_bytes());
+    }
+
+    write_len(&mut out, bytecode.constants.len());
+    for c in &bytecode.constants {
+        match c {
+            Object::Integer(i) => {
+                out.push(TAG_INTEGER);
+                out.extend_from_slice(&i.to_be_bytes());
+            }
+            Object::CompiledFunction(f) => {
+                out.push(TAG_FUNCTION);
+                write_len(&mut out, f.num_locals);
+                write_len(&mut out, f.num_parameters);
+                write_len(&mut out, f.local_names.len());
+                for name in &f.local_names {
+                    write_bytes(&mut out, name.as_bytes());
+                }
+                write_code(&mut out, &f.instructions, &f.lines);
+            }
+            other => {
+                return Err(format!(
+                    "cannot serialize constant of type {:?}",
+                    other.object_type()
+                ));
+            }
+        }
+    }
+
+    write_code(&mut out, &bytecode.instructions, &bytecode.lines);
+    Ok(out)
+}
+
+fn write_len(out: &mut Vec<u8>, n: usize) {
+    out.extend_from_slice(&(n as u32).to_be_bytes());
+}
+
+fn write_bytes(out: &mut Vec<u8>, bytes: &[u8]) {
+    write_len(out, bytes.len());
+    out.extend_from_slice(bytes);
+}
+
+fn write_code(out: &mut Vec<u8>, ins: &[u8], lines: &[(usize, usize)]) {
+    write_bytes(out, ins);
+    write_len(out, lines.len());
+    for &(offset, line) in lines {
+        write_len(out, offset);
+        write_len(out, line);
+    }
+}
+
+/* ========== decode ========== */
+
+pub fn decode(bytes: &[u8]) -> Result<Bytecode, String> {
+    let mut r = Reader { bytes, pos: 0 };
+
+    if r.take(MAGIC.len())? != MAGIC {
+        return Err("not a .mkc artifact (bad magic header)".to_string());
+    }
+    let version = u16::from_be_bytes(r.array()?);
+    if version != VERSION {
+        return Err(format!(
+            "unsupported .mkc version {} (expected {})",
+            version, VERSION
+        ));
+    }
+
+    let mut global_names = Vec::new();
+    for _ in 0..r.len()? {
+        global_names.push(r.name()?);
+    }
+
+    let mut constants = Vec::new();
+    for _ in 0..r.len()? {
+        let c = match r.take(1)?[0] {
+            TAG_INTEGER => Object::Integer(i64::from_be_bytes(r.array()?)),
+            TAG_FUNCTION => {
+                let num_locals = r.len()?;
+                let num_parameters = r.len()?;
+                let mut local_names = Vec::new();
+                for _ in 0..r.len()? {
+                    local_names.push(r.name()?);
+                }
+                let (instructions, lines) = r.code()?;
+                Object::CompiledFunction(Rc::new(CompiledFunction {
+                    instructions,
+                    num_locals,
+                    num_parameters,
+                    local_names,
+                    lines,
+                }))
+            }
+            tag => return Err(format!("unknown constant tag {}", tag)),
+        };
+        constants.push(c);
+    }
+
+    let (instructions, lines) = r.code()?;
+    if r.pos != bytes.len() {
+        return Err("trailing bytes after main instructions".to_string());
+    }
+
+    let bytecode = Bytecode {
+        instructions,
+        constants,
+        global_names,
+        lines,
+    };
+    check_references(&bytecode)?;
+    Ok(bytecode)
+}
+
+// 加载时检查常量 全局变量 局部变量和自由变量的下标 以及跳转目标
+// vm 执行时就不会越界 (弹空栈之类的运行时再报错)
+fn check_references(bytecode: &Bytecode) -> Result<(), String> {
+    // 每个函数常量被 OpClosure 创建时带的自由变量个数 取最小值
+    let mut free_counts: HashMap<usize, usize> = HashMap::new();
+    // (函数常量下标, 指令, 局部变量个数) 主程序没有局部变量
+    let mut bodies = vec![(None, &bytecode.instructions, 0)];
+    for (i, c) in bytecode.constants.iter().enumerate() {
+        if let Object::CompiledFunction(f) = c {
+            if f.num_parameters > f.num_locals {
+                return Err(format!("function {} has more parameters than locals", i));
+            }
+            bodies.push((Some(i), &f.instructions, f.num_locals));
+        }
+    }
+
+    for &(_, ins, num_locals) in &bodies {
+        let boundaries = instruction_offsets(ins);
+        for &offset in &boundaries {
+            let op = Opcode::from_byte(ins[offset]).expect("validated");
+            let (operands, _) = read_operands(op, &ins[offset + 1..]);
+            match op {
+                Opcode::Constant if operands[0] >= bytecode.constants.len() => {
+                    return Err(format!("constant {} out of range", operands[0]));
+                }
+                Opcode::Closure => {
+                    if !matches!(
+                        bytecode.constants.get(operands[0]),
+                        Some(Object::CompiledFunction(_))
+                    ) {
+                        return Err(format!("constant {} is not a function", operands[0]));
+                    }
+                    let count = free_counts.entry(operands[0]).or_insert(operands[1]);
+                    *count = (*count).min(operands[1]);
+                }
+                Opcode::GetGlobal | Opcode::SetGlobal
+                    if operands[0] >= bytecode.global_names.len() =>
+                {
+                    return Err(format!("global {} out of range", operands[0]));
+                }
+                Opcode::GetLocal | Opcode::SetLocal if operands[0] >= num_locals => {
+                    return Err(format!("local {} out of range", operands[0]));
+                }
+                // 跳到指令中间会把操作数当成操作码
+                Opcode::Jump | Opcode::JumpNotTruthy
+                    if operands[0] != ins.len()
+                        && boundaries.binary_search(&operands[0]).is_err() =>
+                {
+                    return Err(format!("jump target {} out of range", operands[0]));
+                }
+                _ => {}
+            }
+        }
+    }
+
+    // 自由变量下标要小于每个创建点捕获的个数 没被创建过的函数不会执行
+    for &(index, ins, _) in &bodies {
+        let free = index.and_then(|i| free_counts.get(&i).copied());
+        for offset in instruction_offsets(ins) {
+            let op = Opcode::from_byte(ins[offset]).expect("validated");
+            if op != Opcode::GetFree {
+                continue;
+            }
+            let (operands, _) = read_operands(op, &ins[offset + 1..]);
+            match (index, free) {
+                (Some(_), None) => {}
+                (_, Some(n)) if operands[0] < n => {}
+                _ => return Err(format!("free variable {} out of range", operands[0])),
+            }
+        }
+    }
+    Ok(())
+}
+
+// 每条指令的起始偏移 指令已经 validate 过
+fn instruction_offsets(ins: &[u8]) -> Vec<usize> {
+    let mut offsets = Vec::new();
+    let mut offset = 0;
+    while offset < ins.len() {
+        offsets.push(offset);
+        let op = Opcode::from_byte(ins[offset]).expect("validated");
+        offset += 1 + op.operand_widths().iter().sum::<usize>();
+    }
+    offsets
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], String> {
+        let end = self
+            .pos
+            .checked_add(n)
+            .filter(|&end| end <= self.bytes.len())
+            .ok_or_else(|| "unexpected end of .mkc artifact".to_string())?;
+        let slice = &self.bytes[self.pos..end];
+        self.pos = end;
+        Ok(slice)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], String> {
+        Ok(self.take(N)?.try_into().expect("length checked"))
+    }
+
+    fn len(&mut self) -> Result<usize, String> {
+        Ok(u32::from_be_bytes(self.array()?) as usize)
+    }
+
+    fn bytes(&mut self) -> Result<&'a [u8], String> {
+        let n = self.len()?;
+        self.take(n)
+    }
+
+    fn name(&mut self) -> Result<String, String> {
+        String::from_utf8(self.bytes()?.to_vec()).map_err(|_| "name is not valid utf-8".to_string())
+    }
+
+    fn code(&mut self) -> Result<(Instructions, LineTable), String> {
+        let instructions = self.bytes()?.to_vec();
+        validate(&instructions)?;
+
+        let mut lines = Vec::new();
+        for _ in 0..self.len()? {
+            lines.push((self.len()?, self.len()?));
+        }
+        Ok((instructions, lines))
+    }
+}
@@ -1,4 +1,9 @@
-use crate::token::TokenType;
+use std::rc::Rc;
+
+use crate::{
+    token::{Position, TokenType},
+    types::Type,
+};
 
 // Program struct
 #[derive(Debug, Clone)]
@@ -12,14 +17,28 @@ impl Program {
         for s in self.statements.iter() {
             out.push_str(&s.string());
         }
-        return out;
+        out
     }
 }
 
 #[derive(Debug, Clone)]
 pub enum Statement {
-    // Let Statement
-    Let { name: Ident, value: Expr },
+    // Let Statement  const x = 1; 也是它 constant 为 true 的绑定不能再赋值
+    // let [a, ...rest] = xs; 左边是模式 模式里的每个名字都是一个绑定
+    Let {
+        pattern: Pattern,
+        constant: bool,
+        // 可选的类型注解 let x: int = 5;
+        ty: Option<Type>,
+        value: Expr,
+        pos: Position,
+    },
+    // import "lib/math.monkey" as math; 路径相对于写这条语句的文件
+    Import {
+        path: String,
+        name: Ident,
+        pos: Position,
+    },
     // Return Statement
     Return(ReturnStatement),
     // Expression Statement
@@ -31,11 +50,40 @@ pub enum Statement {
 }
 
 impl Statement {
+    // 语句起始位置 Block/None 没有记录位置
+    pub fn pos(&self) -> Option<Position> {
+        match self {
+            Statement::Let { pos, .. } | Statement::Import { pos, .. } => Some(*pos),
+            Statement::Return(r) => Some(r.pos),
+            Statement::Expression(e) => Some(e.pos),
+            Statement::Block(_) | Statement::None => None,
+        }
+    }
+
     // 为statement enum 返回字符串类型
     pub fn string(&self) -> String {
         match self {
-            Statement::Let { name, value } => {
-                format!("let {} = {};", name.string(), value.string())
+            Statement::Let {
+                pattern,
+                constant,
+                ty,
+                value,
+                ..
+            } => {
+                let keyword = if *constant { "const" } else { "let" };
+                match ty {
+                    Some(ty) => format!(
+                        "{} {}: {} = {};",
+                        keyword,
+                        pattern.string(),
+                        ty,
+                        value.string()
+                    ),
+                    None => format!("{} {} = {};", keyword, pattern.string(), value.string()),
+                }
+            }
+            Statement::Import { path, name, .. } => {
+                format!("import {:?} as {};", path, name.string())
             }
             Statement::Expression(expression) => expression.string(),
             Statement::Return(returnstmt) => returnstmt.string(),
@@ -45,11 +93,29 @@ impl Statement {
     }
 }
 
-// Ident: string 变量
+// Ident: string 变量 + resolver 算出来的词法地址
 #[derive(Debug, Clone)]
-pub struct Ident(pub String);
+pub struct Ident(pub String, pub Address);
+
+// 变量的词法地址
+// Local: 往外数 depth 层函数环境 取第 slot 个槽位
+// Global: 顶层按名字查 Unresolved: 没经过 resolver 的节点 按名字沿环境链查
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Address {
+    #[default]
+    Unresolved,
+    Global,
+    Local {
+        depth: usize,
+        slot: usize,
+    },
+}
 
 impl Ident {
+    pub fn new(name: impl Into<String>) -> Self {
+        Ident(name.into(), Address::Unresolved)
+    }
+
     pub fn string(&self) -> String {
         self.0.clone()
     }
@@ -59,12 +125,13 @@ impl Ident {
 #[derive(Debug, Clone)]
 pub struct ReturnStatement {
     pub return_value: Expr,
+    pub pos: Position,
 }
 
 // 为这些类型授予String 方法
 impl ReturnStatement {
     pub fn string(&self) -> String {
-        return self.return_value.string();
+        self.return_value.string()
     }
 }
 
@@ -72,19 +139,24 @@ impl ReturnStatement {
 #[derive(Debug, Clone)]
 pub struct ExpressionStatement {
     pub expression: Expr,
+    pub pos: Position,
 }
 
 impl ExpressionStatement {
     pub fn string(&self) -> String {
-        return self.expression.string();
+        self.expression.string()
     }
 }
 
 // Function literal expression
 #[derive(Debug, Clone)]
 pub struct Function {
-    pub parameters: Vec<Ident>,
+    pub parameters: Vec<Param>,
+    // 可选的返回类型注解 fn(a: int) -> int { ... }
+    pub return_type: Option<Type>,
     pub body: Box<Statement>,
+    // 函数环境每个槽位对应的名字 参数在前 函数体里的 let 在后 由 resolver 填写
+    pub slots: Rc<[String]>,
 }
 
 impl Function {
@@ -94,7 +166,120 @@ impl Function {
             params.push(p.string());
         }
 
-        format!("fn({}) {}", params.join(", "), self.body.string())
+        match &self.return_type {
+            Some(ty) => format!("fn({}) -> {} {}", params.join(", "), ty, self.body.string()),
+            None => format!("fn({}) {}", params.join(", "), self.body.string()),
+        }
+    }
+}
+
+// 函数参数: 名字或者解构模式 + 可选的类型注解
+#[derive(Debug, Clone)]
+pub struct Param {
+    pub pattern: Pattern,
+    pub ty: Option<Type>,
+}
+
+impl Param {
+    pub fn string(&self) -> String {
+        match &self.ty {
+            Some(ty) => format!("{}: {}", self.pattern.string(), ty),
+            None => self.pattern.string(),
+        }
+    }
+}
+
+// 绑定的左边: let 和函数参数 还有 match 的分支
+#[derive(Debug, Clone)]
+pub enum Pattern {
+    // 普通的名字 x
+    Ident(Ident),
+    // 只在 match 里: _ 匹配任何值 不绑定
+    Wildcard,
+    // 只在 match 里: 整数 字符串 布尔字面量 值相等才匹配
+    Literal(Expr),
+    // [a, b, ...rest] 没有 rest 时长度必须正好一样
+    Array {
+        elements: Vec<Pattern>,
+        rest: Option<Ident>,
+    },
+    // {name, age: a, pos: [x, y]} 按字符串键取值 键必须存在
+    Hash(Vec<(Rc<str>, Pattern)>),
+}
+
+impl Pattern {
+    pub fn string(&self) -> String {
+        match self {
+            Pattern::Ident(ident) => ident.string(),
+            Pattern::Wildcard => "_".to_string(),
+            Pattern::Literal(lit) => lit.string(),
+            Pattern::Array { elements, rest } => {
+                let mut parts: Vec<String> = elements.iter().map(Pattern::string).collect();
+                if let Some(rest) = rest {
+                    parts.push(format!("...{}", rest.string()));
+                }
+                format!("[{}]", parts.join(", "))
+            }
+            Pattern::Hash(pairs) => {
+                let pairs: Vec<String> = pairs
+                    .iter()
+                    .map(|(key, pattern)| match pattern {
+                        Pattern::Ident(ident) if ident.0 == **key => ident.string(),
+                        pattern => format!("{}: {}", key, pattern.string()),
+                    })
+                    .collect();
+                format!("{{{}}}", pairs.join(", "))
+            }
+        }
+    }
+
+    // 只是一个名字时返回它
+    pub fn as_ident(&self) -> Option<&Ident> {
+        match self {
+            Pattern::Ident(ident) => Some(ident),
+            _ => None,
+        }
+    }
+
+    // 模式绑定的所有名字 按出现的顺序
+    pub fn idents(&self) -> Vec<&Ident> {
+        let mut out = Vec::new();
+        self.walk(&mut |ident| out.push(ident));
+        out
+    }
+
+    pub fn idents_mut(&mut self) -> Vec<&mut Ident> {
+        let mut out = Vec::new();
+        self.walk_mut(&mut |ident| out.push(ident));
+        out
+    }
+
+    fn walk<'a>(&'a self, f: &mut impl FnMut(&'a Ident)) {
+        match self {
+            Pattern::Ident(ident) => f(ident),
+            Pattern::Array { elements, rest } => {
+                elements.iter().for_each(|p| p.walk(f));
+                if let Some(rest) = rest {
+                    f(rest);
+                }
+            }
+            Pattern::Hash(pairs) => pairs.iter().for_each(|(_, p)| p.walk(f)),
+            Pattern::Wildcard | Pattern::Literal(_) => {}
+        }
+    }
+
+    fn walk_mut<'a>(&'a mut self, f: &mut impl FnMut(&'a mut Ident)) {
+        match self {
+            Pattern::Ident(ident) => f(ident),
+            Pattern::Array { elements, rest } => {
+                elements.iter_mut().for_each(|p| p.walk_mut(f));
+                if let Some(rest) = rest {
+                    f(rest);
+                }
+            }
+            Pattern::Hash(pairs) => pairs.iter_mut().for_each(|(_, p)| p.walk_mut(f)),
+            Pattern::Wildcard | Pattern::Literal(_) => {}
+        }
     }
 }
 
@@ -108,6 +293,33 @@ pub enum Expr {
     Integer(i64),
     // Float type: f64
     Float(f64),
+    // string literal 转义已经处理过 求值时直接共享这份 Rc<str>
+    String(Rc<str>),
+    // 带插值的字符串 "hello ${name}"
+    Interpolated(Vec<StringPart>),
+    // array literal [1, 2, 3]
+    Array(Vec<Expr>),
+    // hash literal {"a": 1, true: 2}
+    Hash(Vec<(Expr, Expr)>),
+    // index expression xs[0] / h["a"]  optional: xs?[0] 左边是 null 时得到 null
+    Index {
+        left: Box<Expr>,
+        index: Box<Expr>,
+        optional: bool,
+    },
+    // member access math.sqrt / person.name  optional: person?.name
+    Member {
+        object: Box<Expr>,
+        name: Rc<str>,
+        optional: bool,
+    },
+    // 赋值 x = 1 / xs[i] += 1 / h.k = v  op 是 = 或者 += -= *= /=
+    // target 由 parser 保证是标识符 或者以标识符开头的下标/成员链
+    Assign {
+        target: Box<Expr>,
+        op: TokenType,
+        value: Box<Expr>,
+    },
     // Prefix expression [ -1 ]
     Prefix {
         op: TokenType,
@@ -119,13 +331,30 @@ pub enum Expr {
         op: TokenType,
         right: Box<Expr>,
     },
+    // cond ? a : b 只求值选中的那一边
+    Conditional {
+        condition: Box<Expr>,
+        consequence: Box<Expr>,
+        alternative: Box<Expr>,
+    },
+    // a ?? b 左边是 null 时才求值右边
+    Coalesce {
+        left: Box<Expr>,
+        right: Box<Expr>,
+    },
     // boolean
     Boolean(bool),
     // if expression
     IfExpression {
         condition: Box<Expr>,
         consequence: Box<Statement>,
-        alternative: Box<Statement>, // 后续需要修改为Option<BlockStatement>
+        // else 的 block 或者 else if 的下一个 if (包在表达式语句里) 没有 else 时是 None
+        alternative: Option<Box<Statement>>,
+    },
+    // match (x) { 0 => "zero", [a, b] => a + b, n if n > 10 => n, _ => "other" }
+    Match {
+        subject: Box<Expr>,
+        arms: Vec<MatchArm>,
     },
     // fn expression
     Fn(Function),
@@ -144,6 +373,67 @@ impl Expr {
             Expr::Float(x) => x.to_string(),
             Expr::Ident(i) => i.0.clone(),
             Expr::Integer(it) => it.to_string(),
+            Expr::String(s) => format!("{:?}", s),
+            Expr::Interpolated(parts) => {
+                let mut out = String::from("\"");
+                for part in parts {
+                    match part {
+                        StringPart::Text(text) => {
+                            // 和 {:?} 一样转义 再把 ${ 转义掉 免得读回来变成插值
+                            let escaped = format!("{:?}", text);
+                            out.push_str(&escaped[1..escaped.len() - 1].replace("${", "\\${"));
+                        }
+                        StringPart::Expr(e) => {
+                            out.push_str("${");
+                            out.push_str(&e.string());
+                            out.push('}');
+                        }
+                    }
+                }
+                out.push('"');
+                out
+            }
+            Expr::Array(elements) => {
+                let elements: Vec<String> = elements.iter().map(Expr::string).collect();
+                format!("[{}]", elements.join(", "))
+            }
+            Expr::Hash(pairs) => {
+                let pairs: Vec<String> = pairs
+                    .iter()
+                    .map(|(k, v)| format!("{}: {}", k.string(), v.string()))
+                    .collect();
+                format!("{{{}}}", pairs.join(", "))
+            }
+            Expr::Index {
+                left,
+                index,
+                optional,
+            } => {
+                let open = if *optional { "?[" } else { "[" };
+                format!("({}{}{}])", left.string(), open, index.string())
+            }
+            Expr::Member {
+                object,
+                name,
+                optional,
+            } => {
+                let dot = if *optional { "?." } else { "." };
+                format!("{}{}{}", object.string(), dot, name)
+            }
+            Expr::Conditional {
+                condition,
+                consequence,
+                alternative,
+            } => format!(
+                "({} ? {} : {})",
+                condition.string(),
+                consequence.string(),
+                alternative.string()
+            ),
+            Expr::Coalesce { left, right } => format!("({} ?? {})", left.string(), right.string()),
+            Expr::Assign { target, op, value } => {
+                format!("({} {} {})", target.string(), op, value.string())
+            }
             Expr::Prefix { op, right } => format!("({}{})", op, right.string()),
             Expr::Infix { left, op, right } => {
                 format!("({} {} {})", left.string(), op, right.string())
@@ -155,15 +445,16 @@ impl Expr {
                 alternative,
             } => {
                 let mut out = format!("if{} {}", condition.string(), consequence.string());
-                // 骚操作
-                if let Statement::None = &**alternative {
-                    return out;
+                if let Some(alternative) = alternative {
+                    out.push_str(" else ");
+                    out.push_str(&alternative.string());
                 }
-
-                // 添加字符
-                out.push_str(&alternative.string());
                 out
             }
+            Expr::Match { subject, arms } => {
+                let arms: Vec<String> = arms.iter().map(MatchArm::string).collect();
+                format!("match ({}) {{ {} }}", subject.string(), arms.join(", "))
+            }
             Expr::Fn(func) => func.string(),
             Expr::Call {
                 function,
@@ -181,6 +472,38 @@ impl Expr {
     }
 }
 
+// match 的一个分支: 模式 [if 守卫] => 表达式
+// 模式里的名字绑定在分支自己的环境里 守卫和表达式都能看到
+#[derive(Debug, Clone)]
+pub struct MatchArm {
+    pub pattern: Pattern,
+    pub guard: Option<Expr>,
+    pub body: Expr,
+    // 分支环境每个槽位对应的名字 由 resolver 填写
+    pub slots: Rc<[String]>,
+}
+
+impl MatchArm {
+    pub fn string(&self) -> String {
+        match &self.guard {
+            Some(guard) => format!(
+                "{} if {} => {}",
+                self.pattern.string(),
+                guard.string(),
+                self.body.string()
+            ),
+            None => format!("{} => {}", self.pattern.string(), self.body.string()),
+        }
+    }
+}
+
+// 插值字符串的一段: 文字 (转义已经处理过) 或者 ${} 里的表达式
+#[derive(Debug, Clone)]
+pub enum StringPart {
+    Text(Rc<str>),
+    Expr(Expr),
+}
+
 // BlockStatement结构体
 #[derive(Debug, Clone)]
 pub struct BlockStatement {
@@ -194,6 +517,6 @@ impl BlockStatement {
         for stmt in &self.statements {
             out.push_str(&stmt.string());
         }
-        return out;
+        out
     }
 }
@@ -0,0 +1,445 @@
+use std::{borrow::Cow, rc::Rc};
+
+use crate::object::{Object, ObjectType};
+
+// 内建函数 调用前统一检查参数个数和类别 实现里可以直接假设参数是对的
+#[derive(Debug)]
+pub struct Builtin {
+    pub name: &'static str,
+    // 每个参数接受的类别 空的表示什么都行
+    pub params: &'static [&'static [ObjectType]],
+    // 最后一个参数可以重复任意次 (也可以一次都没有)
+    pub variadic: bool,
+    // 参数按值传进来 独占的数组可以原地修改
+    func: fn(Vec<Object>) -> Object,
+}
+
+const ANY: &[ObjectType] = &[];
+const ARRAY: &[ObjectType] = &[ObjectType::Array];
+const STRING: &[ObjectType] = &[ObjectType::String];
+const INTEGER: &[ObjectType] = &[ObjectType::Integer];
+
+/* ========== registry ========== */
+
+pub static BUILTINS: &[Builtin] = &[
+    Builtin {
+        name: "len",
+        params: &[&[ObjectType::String, ObjectType::Array, ObjectType::Hash]],
+        variadic: false,
+        func: len,
+    },
+    Builtin {
+        name: "first",
+        params: &[ARRAY],
+        variadic: false,
+        func: first,
+    },
+    Builtin {
+        name: "last",
+        params: &[ARRAY],
+        variadic: false,
+        func: last,
+    },
+    Builtin {
+        name: "rest",
+        params: &[ARRAY],
+        variadic: false,
+        func: rest,
+    },
+    Builtin {
+        name: "push",
+        params: &[ARRAY, ANY],
+        variadic: false,
+        func: push,
+    },
+    Builtin {
+        name: "array",
+        params: &[INTEGER, ANY],
+        variadic: false,
+        func: array,
+    },
+    Builtin {
+        name: "puts",
+        params: &[ANY],
+        variadic: true,
+        func: puts,
+    },
+    // 字符串 下标和长度都按字符算
+    Builtin {
+        name: "split",
+        params: &[STRING, STRING],
+        variadic: false,
+        func: split,
+    },
+    Builtin {
+        name: "join",
+        params: &[ARRAY, STRING],
+        variadic: false,
+        func: join,
+    },
+    Builtin {
+        name: "trim",
+        params: &[STRING],
+        variadic: false,
+        func: trim,
+    },
+    Builtin {
+        name: "upper",
+        params: &[STRING],
+        variadic: false,
+        func: upper,
+    },
+    Builtin {
+        name: "lower",
+        params: &[STRING],
+        variadic: false,
+        func: lower,
+    },
+    Builtin {
+        name: "contains",
+        params: &[STRING, STRING],
+        variadic: false,
+        func: contains,
+    },
+    Builtin {
+        name: "starts_with",
+        params: &[STRING, STRING],
+        variadic: false,
+        func: starts_with,
+    },
+    Builtin {
+        name: "replace",
+        params: &[STRING, STRING, STRING],
+        variadic: false,
+        func: replace,
+    },
+    Builtin {
+        name: "substr",
+        params: &[STRING, INTEGER, INTEGER],
+        variadic: false,
+        func: substr,
+    },
+    Builtin {
+        name: "slice",
+        params: &[&[ObjectType::String, ObjectType::Array], INTEGER, INTEGER],
+        variadic: false,
+        func: slice,
+    },
+    Builtin {
+        name: "chars",
+        params: &[STRING],
+        variadic: false,
+        func: chars,
+    },
+    Builtin {
+        name: "parse_int",
+        params: &[STRING],
+        variadic: false,
+        func: parse_int,
+    },
+    Builtin {
+        name: "to_string",
+        params: &[ANY],
+        variadic: false,
+        func: to_string,
+    },
+    Builtin {
+        name: "format",
+        params: &[STRING, ANY],
+        variadic: true,
+        func: format,
+    },
+];
+
+pub fn lookup(name: &str) -> Option<&'static Builtin> {
+    BUILTINS.iter().find(|b| b.name == name)
+}
+
+impl Builtin {
+    // 固定参数个数 可变参数的返回 None
+    pub fn arity(&self) -> Option<usize> {
+        (!self.variadic).then_some(self.params.len())
+    }
+
+    pub fn call(&self, args: Vec<Object>) -> Object {
+        let want = self.params.len();
+        if self.variadic && args.len() + 1 < want {
+            return Object::Error(format!(
+                "wrong number of arguments to {}: want at least {}, got={}",
+                self.name,
+                want - 1,
+                args.len()
+            ));
+        }
+        if !self.variadic && args.len() != want {
+            return Object::Error(format!(
+                "wrong number of arguments to {}: want={}, got={}",
+                self.name,
+                want,
+                args.len()
+            ));
+        }
+
+        for (i, arg) in args.iter().enumerate() {
+            let accepts = self.params[i.min(want - 1)];
+            if !accepts.is_empty() && !accepts.contains(&arg.object_type()) {
+                return Object::Error(format!(
+                    "argument {} to {} must be {}, got {:?}",
+                    i + 1,
+                    self.name,
+                    one_of(accepts),
+                    arg.object_type()
+                ));
+            }
+        }
+        (self.func)(args)
+    }
+}
+
+// [String, Array, Hash] -> "String, Array or Hash"
+fn one_of(types: &[ObjectType]) -> String {
+    let names: Vec<String> = types.iter().map(|t| format!("{:?}", t)).collect();
+    match names.split_last() {
+        Some((last, [])) => last.clone(),
+        Some((last, init)) => format!("{} or {}", init.join(", "), last),
+        None => String::new(),
+    }
+}
+
+/* ========== implementations ========== */
+
+// 字符串按字符数算 不是字节数
+fn len(args: Vec<Object>) -> Object {
+    let n = match &args[0] {
+        Object::String(s) => s.chars().count(),
+        Object::Array(elements) => elements.len(),
+        Object::Hash(map) => map.len(),
+        _ => unreachable!("checked by Builtin::call"),
+    };
+    Object::Integer(n as i64)
+}
+
+fn first(args: Vec<Object>) -> Object {
+    match &args[0] {
+        Object::Array(elements) => elements.first().cloned().unwrap_or(Object::Null),
+        _ => unreachable!("checked by Builtin::call"),
+    }
+}
+
+fn last(args: Vec<Object>) -> Object {
+    match &args[0] {
+        Object::Array(elements) => elements.last().cloned().unwrap_or(Object::Null),
+        _ => unreachable!("checked by Builtin::call"),
+    }
+}
+
+// 去掉第一个元素的新数组 空数组得到 null
+fn rest(args: Vec<Object>) -> Object {
+    match &args[0] {
+        Object::Array(elements) if elements.is_empty() => Object::Null,
+        Object::Array(elements) => Object::array(elements[1..].to_vec()),
+        _ => unreachable!("checked by Builtin::call"),
+    }
+}
+
+// 数组是共享的 push 返回追加后的新数组 原来的不变
+// 没有别处引用的数组 (比如 push(push(xs, 1), 2) 里面那个) 直接原地追加
+fn push(mut args: Vec<Object>) -> Object {
+    let value = args.pop().expect("checked by Builtin::call");
+    match args.pop() {
+        Some(Object::Array(mut elements)) => {
+            Rc::make_mut(&mut elements).push(value);
+            Object::Array(elements)
+        }
+        _ => unreachable!("checked by Builtin::call"),
+    }
+}
+
+// array(n, x): n 个 x 组成的新数组 先开好再用下标赋值填 不用一个个 push
+fn array(args: Vec<Object>) -> Object {
+    let n = integer(&args[0]);
+    let Ok(n) = usize::try_from(n) else {
+        return Object::Error(format!("array length must not be negative, got {}", n));
+    };
+    let mut elements = Vec::new();
+    if elements.try_reserve_exact(n).is_err() {
+        return Object::Error(format!("array length too large: {}", n));
+    }
+    elements.resize(n, args[1].clone());
+    Object::array(elements)
+}
+
+fn puts(args: Vec<Object>) -> Object {
+    for arg in args {
+        println!("{}", arg.inspect());
+    }
+    Object::Null
+}
+
+/* ========== strings ========== */
+
+fn string(o: &Object) -> &str {
+    match o {
+        Object::String(s) => s,
+        _ => unreachable!("checked by Builtin::call"),
+    }
+}
+
+fn integer(o: &Object) -> i64 {
+    match o {
+        Object::Integer(i) => *i,
+        _ => unreachable!("checked by Builtin::call"),
+    }
+}
+
+fn strings<'a>(parts: impl Iterator<Item = &'a str>) -> Object {
+    Object::array(parts.map(Object::string).collect())
+}
+
+// 字符串原样 其它值用 inspect 的样子
+fn stringify(o: &Object) -> Cow<'_, str> {
+    match o {
+        Object::String(s) => Cow::Borrowed(s),
+        other => Cow::Owned(other.inspect()),
+    }
+}
+
+// 第 start 到第 end 个字符 (不含 end) 超出范围的部分截掉
+fn char_range(s: &str, start: i64, end: i64) -> &str {
+    let byte = |i: i64| {
+        let i = usize::try_from(i).unwrap_or(0);
+        s.char_indices().nth(i).map_or(s.len(), |(b, _)| b)
+    };
+    let (start, end) = (byte(start), byte(end));
+    if start < end { &s[start..end] } else { "" }
+}
+
+// 分隔符是空串时按字符拆开
+fn split(args: Vec<Object>) -> Object {
+    let (s, sep) = (string(&args[0]), string(&args[1]));
+    if sep.is_empty() {
+        return chars(args);
+    }
+    strings(s.split(sep))
+}
+
+fn join(args: Vec<Object>) -> Object {
+    let Object::Array(elements) = &args[0] else {
+        unreachable!("checked by Builtin::call")
+    };
+    let parts: Vec<Cow<str>> = elements.iter().map(stringify).collect();
+    Object::string(parts.join(string(&args[1])))
+}
+
+fn trim(args: Vec<Object>) -> Object {
+    Object::string(string(&args[0]).trim())
+}
+
+fn upper(args: Vec<Object>) -> Object {
+    Object::string(string(&args[0]).to_uppercase())
+}
+
+fn lower(args: Vec<Object>) -> Object {
+    Object::string(string(&args[0]).to_lowercase())
+}
+
+fn contains(args: Vec<Object>) -> Object {
+    Object::Boolean(string(&args[0]).contains(string(&args[1])))
+}
+
+fn starts_with(args: Vec<Object>) -> Object {
+    Object::Boolean(string(&args[0]).starts_with(string(&args[1])))
+}
+
+fn replace(args: Vec<Object>) -> Object {
+    let (s, from, to) = (string(&args[0]), string(&args[1]), string(&args[2]));
+    if from.is_empty() {
+        return args[0].clone();
+    }
+    Object::string(s.replace(from, to))
+}
+
+// substr(s, start, count): 从第 start 个字符开始的 count 个字符
+fn substr(args: Vec<Object>) -> Object {
+    let (start, count) = (integer(&args[1]), integer(&args[2]));
+    let end = start.max(0).saturating_add(count);
+    Object::string(char_range(string(&args[0]), start, end))
+}
+
+// slice(x, start, end): 字符串按字符 数组按元素 越界的部分截掉
+fn slice(args: Vec<Object>) -> Object {
+    let (start, end) = (integer(&args[1]), integer(&args[2]));
+    match &args[0] {
+        Object::String(s) => Object::string(char_range(s, start, end)),
+        Object::Array(elements) => {
+            let clamp = |i: i64| usize::try_from(i).unwrap_or(0).min(elements.len());
+            let (start, end) = (clamp(start), clamp(end));
+            Object::array(elements[start..end.max(start)].to_vec())
+        }
+        _ => unreachable!("checked by Builtin::call"),
+    }
+}
+
+fn chars(args: Vec<Object>) -> Object {
+    let s = string(&args[0]);
+    Object::array(s.chars().map(Object::string).collect())
+}
+
+// 不是整数时得到 null
+fn parse_int(args: Vec<Object>) -> Object {
+    string(&args[0])
+        .trim()
+        .parse()
+        .map_or(Object::Null, Object::Integer)
+}
+
+fn to_string(args: Vec<Object>) -> Object {
+    match &args[0] {
+        Object::String(_) => args[0].clone(),
+        other => Object::string(other.inspect()),
+    }
+}
+
+// format("{} + {}", a, b) {{ 和 }} 是字面的花括号
+fn format(args: Vec<Object>) -> Object {
+    let template = string(&args[0]);
+    let values = &args[1..];
+    let mut out = String::with_capacity(template.len());
+    let mut used = 0;
+    let mut chars = template.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.peek() == Some(&'{') => {
+                chars.next();
+                out.push('{');
+            }
+            '}' if chars.peek() == Some(&'}') => {
+                chars.next();
+                out.push('}');
+            }
+            '{' if chars.peek() == Some(&'}') => {
+                chars.next();
+                match values.get(used) {
+                    Some(v) => out.push_str(&stringify(v)),
+                    None => {
+                        return Object::Error(format!(
+                            "format: not enough arguments for {:?}",
+                            template
+                        ));
+                    }
+                }
+                used += 1;
+            }
+            c => out.push(c),
+        }
+    }
+    if used < values.len() {
+        return Object::Error(format!(
+            "format: {} placeholder(s) in {:?} but {} argument(s)",
+            used,
+            template,
+            values.len()
+        ));
+    }
+    Object::string(out)
+}
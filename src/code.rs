@@ -0,0 +1,257 @@
+use std::fmt::Write;
+
+// 字节码定义: 指令就是一串字节 操作码后面跟着大端序的操作数
+pub type Instructions = Vec<u8>;
+
+// 调试行号表: (指令偏移, 源码行号) 按偏移递增 每条记录一直生效到下一条
+pub type LineTable = Vec<(usize, usize)>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Opcode {
+    // 常量池
+    Constant = 0, // [const index: u16]
+    Pop,
+    Null,
+    True,
+    False,
+
+    // 中缀运算
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Equal,
+    NotEqual,
+    LessThan,
+    GreaterThan,
+    LessEqual,
+    GreaterEqual,
+
+    // 前缀运算
+    Minus,
+    Plus,
+    Bang,
+
+    // 跳转
+    JumpNotTruthy, // [target: u16]
+    Jump,          // [target: u16]
+
+    // 变量
+    GetGlobal,      // [global index: u16]
+    SetGlobal,      // [global index: u16]
+    GetLocal,       // [local index: u8]
+    SetLocal,       // [local index: u8]
+    GetFree,        // [free index: u8]
+    CurrentClosure, // 递归引用自己
+
+    // 函数
+    Call,        // [argc: u8]
+    ReturnValue, // 返回栈顶
+    Closure,     // [const index: u16, free count: u8]
+}
+
+// 所有操作码 按编号排列 用于 u8 -> Opcode 的转换
+const OPCODES: [Opcode; 29] = [
+    Opcode::Constant,
+    Opcode::Pop,
+    Opcode::Null,
+    Opcode::True,
+    Opcode::False,
+    Opcode::Add,
+    Opcode::Sub,
+    Opcode::Mul,
+    Opcode::Div,
+    Opcode::Equal,
+    Opcode::NotEqual,
+    Opcode::LessThan,
+    Opcode::GreaterThan,
+    Opcode::LessEqual,
+    Opcode::GreaterEqual,
+    Opcode::Minus,
+    Opcode::Plus,
+    Opcode::Bang,
+    Opcode::JumpNotTruthy,
+    Opcode::Jump,
+    Opcode::GetGlobal,
+    Opcode::SetGlobal,
+    Opcode::GetLocal,
+    Opcode::SetLocal,
+    Opcode::GetFree,
+    Opcode::CurrentClosure,
+    Opcode::Call,
+    Opcode::ReturnValue,
+    Opcode::Closure,
+];
+
+impl Opcode {
+    pub fn from_byte(byte: u8) -> Option<Opcode> {
+        OPCODES.get(byte as usize).copied()
+    }
+
+    // 助记符 反汇编时使用
+    pub fn name(self) -> &'static str {
+        use Opcode::*;
+        match self {
+            Constant => "OpConstant",
+            Pop => "OpPop",
+            Null => "OpNull",
+            True => "OpTrue",
+            False => "OpFalse",
+            Add => "OpAdd",
+            Sub => "OpSub",
+            Mul => "OpMul",
+            Div => "OpDiv",
+            Equal => "OpEqual",
+            NotEqual => "OpNotEqual",
+            LessThan => "OpLessThan",
+            GreaterThan => "OpGreaterThan",
+            LessEqual => "OpLessEqual",
+            GreaterEqual => "OpGreaterEqual",
+            Minus => "OpMinus",
+            Plus => "OpPlus",
+            Bang => "OpBang",
+            JumpNotTruthy => "OpJumpNotTruthy",
+            Jump => "OpJump",
+            GetGlobal => "OpGetGlobal",
+            SetGlobal => "OpSetGlobal",
+            GetLocal => "OpGetLocal",
+            SetLocal => "OpSetLocal",
+            GetFree => "OpGetFree",
+            CurrentClosure => "OpCurrentClosure",
+            Call => "OpCall",
+            ReturnValue => "OpReturnValue",
+            Closure => "OpClosure",
+        }
+    }
+
+    // 每个操作数占用的字节数
+    pub fn operand_widths(self) -> &'static [usize] {
+        use Opcode::*;
+        match self {
+            Constant | JumpNotTruthy | Jump | GetGlobal | SetGlobal => &[2],
+            GetLocal | SetLocal | GetFree | Call => &[1],
+            Closure => &[2, 1],
+            _ => &[],
+        }
+    }
+}
+
+// 编码一条指令
+pub fn make(op: Opcode, operands: &[usize]) -> Instructions {
+    let widths = op.operand_widths();
+    let len = 1 + widths.iter().sum::<usize>();
+
+    let mut ins = Vec::with_capacity(len);
+    ins.push(op as u8);
+    for (operand, width) in operands.iter().zip(widths) {
+        match width {
+            2 => ins.extend_from_slice(&(*operand as u16).to_be_bytes()),
+            1 => ins.push(*operand as u8),
+            _ => unreachable!("unsupported operand width {}", width),
+        }
+    }
+    ins
+}
+
+// 检查操作数能否放进各自的宽度 make 本身会截断 编译器在编码前先调用这个
+pub fn check_operands(op: Opcode, operands: &[usize]) -> Result<(), String> {
+    for (operand, width) in operands.iter().zip(op.operand_widths()) {
+        let max = (1usize << (8 * width)) - 1;
+        if *operand > max {
+            let what = match (op, width) {
+                (Opcode::Closure, 1) => "free variable count",
+                (Opcode::Constant | Opcode::Closure, _) => "constant index",
+                (Opcode::JumpNotTruthy | Opcode::Jump, _) => "jump target",
+                (Opcode::GetGlobal | Opcode::SetGlobal, _) => "global index",
+                (Opcode::GetLocal | Opcode::SetLocal, _) => "local index",
+                (Opcode::GetFree, _) => "free variable index",
+                (Opcode::Call, _) => "argument count",
+                _ => "operand",
+            };
+            return Err(format!(
+                "{} {} does not fit in {} (max {})",
+                what,
+                operand,
+                op.name(),
+                max
+            ));
+        }
+    }
+    Ok(())
+}
+
+// 检查一段指令是否完整: 操作码合法 且操作数没有越界
+pub fn validate(ins: &[u8]) -> Result<(), String> {
+    let mut offset = 0;
+    while offset < ins.len() {
+        let op = Opcode::from_byte(ins[offset])
+            .ok_or_else(|| format!("unknown opcode {} at {:04}", ins[offset], offset))?;
+        offset += 1 + op.operand_widths().iter().sum::<usize>();
+        if offset > ins.len() {
+            return Err(format!("truncated {} at end of instructions", op.name()));
+        }
+    }
+    Ok(())
+}
+
+// 解码操作数 返回操作数和读取的字节数
+pub fn read_operands(op: Opcode, ins: &[u8]) -> (Vec<usize>, usize) {
+    let mut operands = Vec::with_capacity(op.operand_widths().len());
+    let mut offset = 0;
+    for width in op.operand_widths() {
+        match width {
+            2 => operands.push(read_u16(&ins[offset..]) as usize),
+            1 => operands.push(ins[offset] as usize),
+            _ => unreachable!("unsupported operand width {}", width),
+        }
+        offset += width;
+    }
+    (operands, offset)
+}
+
+#[inline(always)]
+pub fn read_u16(ins: &[u8]) -> u16 {
+    u16::from_be_bytes([ins[0], ins[1]])
+}
+
+// 查找某个指令偏移对应的源码行号
+pub fn line_for(lines: &[(usize, usize)], offset: usize) -> Option<usize> {
+    match lines.partition_point(|&(start, _)| start <= offset) {
+        0 => None,
+        i => Some(lines[i - 1].1),
+    }
+}
+
+// 反汇编: 每行一条指令 偏移 + 助记符 + 操作数 行号变化时标注源码行
+pub fn disassemble(ins: &[u8], lines: &[(usize, usize)]) -> String {
+    let mut out = String::new();
+    let mut offset = 0;
+    let mut last_line = None;
+
+    while offset < ins.len() {
+        let Some(op) = Opcode::from_byte(ins[offset]) else {
+            let _ = writeln!(out, "{:04} ERROR: unknown opcode {}", offset, ins[offset]);
+            offset += 1;
+            continue;
+        };
+        let (operands, read) = read_operands(op, &ins[offset + 1..]);
+
+        let mut line = format!("{:04} {}", offset, op.name());
+        for operand in &operands {
+            let _ = write!(line, " {}", operand);
+        }
+        let src_line = line_for(lines, offset);
+        if let Some(n) = src_line
+            && src_line != last_line
+        {
+            line = format!("{:<28}; line {}", line, n);
+            last_line = src_line;
+        }
+        out.push_str(&line);
+        out.push('\n');
+
+        offset += 1 + read;
+    }
+    out
+}
@@ -0,0 +1,572 @@
+use std::rc::Rc;
+
+use ahash::{AHashMap as HashMap, AHashSet as HashSet};
+
+use crate::{
+    ast::{Expr, Function, Program, Statement},
+    builtins,
+    code::{Instructions, LineTable, Opcode, check_operands, disassemble, make},
+    object::{CompiledFunction, Object},
+    token::TokenType,
+};
+
+/* ========== symbol table ========== */
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SymbolScope {
+    Global,
+    Local,
+    Free,
+    Function, // 函数自身的名字 用于递归
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Symbol {
+    pub name: String,
+    pub scope: SymbolScope,
+    pub index: usize,
+}
+
+#[derive(Debug, Default)]
+pub struct SymbolTable {
+    outer: Option<Box<SymbolTable>>,
+    store: HashMap<String, Symbol>,
+    num_definitions: usize,
+    // 按槽位排列的名字 同名参数各占一个
+    names: Vec<String>,
+    // 当前函数捕获的外层变量 按捕获顺序排列
+    pub free_symbols: Vec<Symbol>,
+    // 这一层被内层函数捕获过的名字 闭包捕获的是值 之后再 let 同名变量内层看不到
+    captured: HashSet<String>,
+}
+
+impl SymbolTable {
+    pub fn new() -> Self {
+        SymbolTable::default()
+    }
+
+    pub fn new_enclosed(outer: SymbolTable) -> Self {
+        SymbolTable {
+            outer: Some(Box::new(outer)),
+            ..SymbolTable::default()
+        }
+    }
+
+    // 同一作用域重复 let 复用原来的槽位 和树遍历的覆盖语义一致
+    pub fn define(&mut self, name: &str) -> Symbol {
+        if let Some(sym) = self.store.get(name)
+            && matches!(sym.scope, SymbolScope::Global | SymbolScope::Local)
+        {
+            return sym.clone();
+        }
+        let scope = if self.outer.is_none() {
+            SymbolScope::Global
+        } else {
+            SymbolScope::Local
+        };
+        let sym = Symbol {
+            name: name.to_string(),
+            scope,
+            index: self.num_definitions,
+        };
+        self.num_definitions += 1;
+        self.names.push(name.to_string());
+        self.store.insert(name.to_string(), sym.clone());
+        sym
+    }
+
+    // 参数按位置各占一个槽位 同名参数后面的遮住前面的 和树遍历依次绑定的结果一致
+    pub fn define_parameter(&mut self, name: &str) -> Symbol {
+        let sym = Symbol {
+            name: name.to_string(),
+            scope: SymbolScope::Local,
+            index: self.num_definitions,
+        };
+        self.num_definitions += 1;
+        self.names.push(name.to_string());
+        self.store.insert(name.to_string(), sym.clone());
+        sym
+    }
+
+    pub fn define_function_name(&mut self, name: &str) -> Symbol {
+        let sym = Symbol {
+            name: name.to_string(),
+            scope: SymbolScope::Function,
+            index: 0,
+        };
+        self.store.insert(name.to_string(), sym.clone());
+        sym
+    }
+
+    fn define_free(&mut self, original: Symbol) -> Symbol {
+        let sym = Symbol {
+            name: original.name.clone(),
+            scope: SymbolScope::Free,
+            index: self.free_symbols.len(),
+        };
+        self.free_symbols.push(original);
+        self.store.insert(sym.name.clone(), sym.clone());
+        sym
+    }
+
+    pub fn resolve(&mut self, name: &str) -> Option<Symbol> {
+        if let Some(sym) = self.store.get(name) {
+            return Some(sym.clone());
+        }
+        let outer = self.outer.as_mut()?;
+        let sym = outer.resolve(name)?;
+        match sym.scope {
+            SymbolScope::Global => Some(sym),
+            // 外层函数的局部变量 在这里变成自由变量
+            _ => {
+                outer.captured.insert(name.to_string());
+                Some(self.define_free(sym))
+            }
+        }
+    }
+
+    // 当前作用域里的这个名字是否已经被某个内层函数捕获
+    pub fn is_captured(&self, name: &str) -> bool {
+        self.captured.contains(name)
+    }
+
+    // 顶层全局表 找不到的名字挂在这里 运行时再报 identifier not found
+    pub fn define_global(&mut self, name: &str) -> Symbol {
+        match self.outer.as_mut() {
+            Some(outer) => outer.define_global(name),
+            None => self.define(name),
+        }
+    }
+
+    // 全局槽位对应的名字 vm 报错时使用
+    pub fn global_names(&self) -> Vec<String> {
+        match &self.outer {
+            Some(outer) => outer.global_names(),
+            None => {
+                let mut names = vec![String::new(); self.num_definitions];
+                for sym in self.store.values() {
+                    if sym.scope == SymbolScope::Global {
+                        names[sym.index] = sym.name.clone();
+                    }
+                }
+                names
+            }
+        }
+    }
+}
+
+/* ========== compiler ========== */
+
+// 编译产物 交给 vm 执行
+#[derive(Debug, Clone)]
+pub struct Bytecode {
+    pub instructions: Instructions,
+    pub constants: Vec<Object>,
+    pub global_names: Vec<String>,
+    pub lines: LineTable,
+}
+
+impl Bytecode {
+    // 反汇编主程序和常量池 函数常量会递归展开
+    pub fn disassemble(&self) -> String {
+        let mut out = String::from("== main ==\n");
+        out.push_str(&disassemble(&self.instructions, &self.lines));
+
+        out.push_str("\n== constants ==\n");
+        for (i, c) in self.constants.iter().enumerate() {
+            match c {
+                Object::CompiledFunction(f) => {
+                    out.push_str(&format!(
+                        "{:04} fn (params={}, locals={})\n",
+                        i, f.num_parameters, f.num_locals
+                    ));
+                    for line in disassemble(&f.instructions, &f.lines).lines() {
+                        out.push_str("     ");
+                        out.push_str(line);
+                        out.push('\n');
+                    }
+                }
+                other => out.push_str(&format!("{:04} {}\n", i, other.inspect())),
+            }
+        }
+
+        if !self.global_names.is_empty() {
+            out.push_str("\n== globals ==\n");
+            for (i, name) in self.global_names.iter().enumerate() {
+                out.push_str(&format!("{:04} {}\n", i, name));
+            }
+        }
+        out
+    }
+}
+
+#[derive(Debug, Default)]
+struct CompilationScope {
+    instructions: Instructions,
+    lines: LineTable,
+}
+
+#[derive(Debug)]
+pub struct Compiler {
+    constants: Vec<Object>,
+    symbol_table: SymbolTable,
+    scopes: Vec<CompilationScope>,
+}
+
+impl Default for Compiler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Compiler {
+    pub fn new() -> Self {
+        Compiler {
+            constants: Vec::new(),
+            symbol_table: SymbolTable::new(),
+            scopes: vec![CompilationScope::default()],
+        }
+    }
+
+    pub fn compile(&mut self, program: &Program) -> Result<(), String> {
+        for stmt in &program.statements {
+            self.compile_statement(stmt)?;
+        }
+        Ok(())
+    }
+
+    pub fn bytecode(&self) -> Bytecode {
+        Bytecode {
+            instructions: self.scopes[0].instructions.clone(),
+            constants: self.constants.clone(),
+            global_names: self.symbol_table.global_names(),
+            lines: self.scopes[0].lines.clone(),
+        }
+    }
+
+    /* ---------- statements ---------- */
+
+    fn compile_statement(&mut self, stmt: &Statement) -> Result<(), String> {
+        self.mark_line(stmt);
+        match stmt {
+            Statement::Expression(e) => {
+                self.compile_expr(&e.expression)?;
+                self.emit(Opcode::Pop, &[])?;
+            }
+            Statement::Block(block) => {
+                self.compile_block(&block.statements)?;
+                self.emit(Opcode::Pop, &[])?;
+            }
+            Statement::Let { pattern, value, .. } => {
+                // 解构还没有对应的指令
+                let Some(name) = pattern.as_ident() else {
+                    return Err(unsupported("destructuring", &stmt.string()));
+                };
+                // 树遍历里内层函数会看到重新绑定的值 vm 的闭包已经把旧值拷走了
+                if self.symbol_table.is_captured(&name.0) {
+                    return Err(unsupported("re-declaring a captured local", &stmt.string()));
+                }
+                let sym = self.symbol_table.define(&name.0);
+                match value {
+                    Expr::Fn(func) => self.compile_function(func, Some(&name.0))?,
+                    _ => self.compile_expr(value)?,
+                }
+                match sym.scope {
+                    SymbolScope::Global => self.emit(Opcode::SetGlobal, &[sym.index])?,
+                    _ => self.emit(Opcode::SetLocal, &[sym.index])?,
+                };
+            }
+            Statement::Return(r) => {
+                self.compile_expr(&r.return_value)?;
+                self.emit(Opcode::ReturnValue, &[])?;
+            }
+            Statement::Import { .. } => {
+                return Err(unsupported("import", &stmt.string()));
+            }
+            Statement::None => {}
+        }
+        Ok(())
+    }
+
+    // block 是表达式: 在栈上留下最后一个表达式语句的值 没有就留下 null
+    // let 会自己弹出它的值 所以夹在后面也不影响留下的结果
+    fn compile_block(&mut self, stmts: &[Statement]) -> Result<(), String> {
+        let last_value = stmts
+            .iter()
+            .rposition(|s| matches!(s, Statement::Expression(_) | Statement::Block(_)));
+        if last_value.is_none() {
+            self.emit(Opcode::Null, &[])?;
+        }
+
+        for (i, stmt) in stmts.iter().enumerate() {
+            match stmt {
+                Statement::Expression(e) => {
+                    self.mark_line(stmt);
+                    self.compile_expr(&e.expression)?;
+                    if Some(i) != last_value {
+                        self.emit(Opcode::Pop, &[])?;
+                    }
+                }
+                Statement::Block(block) => {
+                    self.compile_block(&block.statements)?;
+                    if Some(i) != last_value {
+                        self.emit(Opcode::Pop, &[])?;
+                    }
+                }
+                other => self.compile_statement(other)?,
+            }
+        }
+        Ok(())
+    }
+
+    // if 的分支 通常是 Block else if 是包着下一个 if 的表达式语句
+    fn compile_branch(&mut self, stmt: &Statement) -> Result<(), String> {
+        match stmt {
+            Statement::Block(block) => self.compile_block(&block.statements),
+            other => self.compile_block(std::slice::from_ref(other)),
+        }
+    }
+
+    /* ---------- expressions ---------- */
+
+    fn compile_expr(&mut self, e: &Expr) -> Result<(), String> {
+        match e {
+            Expr::Integer(i) => {
+                let idx = self.add_constant(Object::Integer(*i));
+                self.emit(Opcode::Constant, &[idx])?;
+            }
+            Expr::Boolean(true) => {
+                self.emit(Opcode::True, &[])?;
+            }
+            Expr::Boolean(false) => {
+                self.emit(Opcode::False, &[])?;
+            }
+            // 树遍历对它们求值为 null 这里保持一致
+            Expr::Float(_) | Expr::None => {
+                self.emit(Opcode::Null, &[])?;
+            }
+            Expr::Ident(ident) => {
+                let sym = match self.symbol_table.resolve(&ident.0) {
+                    Some(sym) => sym,
+                    // vm 里还没有内建函数 不能等到运行时报 identifier not found
+                    None if builtins::lookup(&ident.0).is_some() => {
+                        return Err(unsupported("builtin", &ident.0));
+                    }
+                    None => self.symbol_table.define_global(&ident.0),
+                };
+                self.load_symbol(&sym)?;
+            }
+            Expr::Prefix { op, right } => {
+                self.compile_expr(right)?;
+                let opcode = match op {
+                    TokenType::Minus => Opcode::Minus,
+                    TokenType::Plus => Opcode::Plus,
+                    TokenType::Bang => Opcode::Bang,
+                    _ => return Err(format!("unknown prefix operator: {}", op)),
+                };
+                self.emit(opcode, &[])?;
+            }
+            Expr::Infix { left, op, right } => {
+                self.compile_expr(left)?;
+                self.compile_expr(right)?;
+                let opcode = match op {
+                    TokenType::Plus => Opcode::Add,
+                    TokenType::Minus => Opcode::Sub,
+                    TokenType::Asterisk => Opcode::Mul,
+                    TokenType::Slash => Opcode::Div,
+                    TokenType::Eq => Opcode::Equal,
+                    TokenType::NotEq => Opcode::NotEqual,
+                    TokenType::Lt => Opcode::LessThan,
+                    TokenType::Gt => Opcode::GreaterThan,
+                    TokenType::Le => Opcode::LessEqual,
+                    TokenType::Ge => Opcode::GreaterEqual,
+                    _ => return Err(format!("unknown operator: {}", op)),
+                };
+                self.emit(opcode, &[])?;
+            }
+            Expr::IfExpression {
+                condition,
+                consequence,
+                alternative,
+            } => {
+                self.compile_expr(condition)?;
+                // 先放占位地址 之后回填
+                let jump_not_truthy = self.emit(Opcode::JumpNotTruthy, &[0xFFFF])?;
+                self.compile_branch(consequence)?;
+                let jump = self.emit(Opcode::Jump, &[0xFFFF])?;
+
+                let after_consequence = self.current_instructions().len();
+                self.change_operand(jump_not_truthy, after_consequence)?;
+
+                match alternative {
+                    Some(alternative) => self.compile_branch(alternative)?,
+                    None => {
+                        self.emit(Opcode::Null, &[])?;
+                    }
+                }
+                let after_alternative = self.current_instructions().len();
+                self.change_operand(jump, after_alternative)?;
+            }
+            // c ? a : b 和 if/else 一样的跳转 只是两边都是表达式
+            Expr::Conditional {
+                condition,
+                consequence,
+                alternative,
+            } => {
+                self.compile_expr(condition)?;
+                let jump_not_truthy = self.emit(Opcode::JumpNotTruthy, &[0xFFFF])?;
+                self.compile_expr(consequence)?;
+                let jump = self.emit(Opcode::Jump, &[0xFFFF])?;
+
+                let after_consequence = self.current_instructions().len();
+                self.change_operand(jump_not_truthy, after_consequence)?;
+
+                self.compile_expr(alternative)?;
+                let after_alternative = self.current_instructions().len();
+                self.change_operand(jump, after_alternative)?;
+            }
+            Expr::Fn(func) => self.compile_function(func, None)?,
+            Expr::Call {
+                function,
+                arguments,
+            } => {
+                self.compile_expr(function)?;
+                for a in arguments {
+                    self.compile_expr(a)?;
+                }
+                self.emit(Opcode::Call, &[arguments.len()])?;
+            }
+            // vm 还没有字符串/数组/hash 赋值 match 和 ?? 的指令 先只在树遍历解释器里支持
+            Expr::String(_) | Expr::Interpolated(_) => {
+                return Err(unsupported("string", &e.string()));
+            }
+            Expr::Array(_) => return Err(unsupported("array", &e.string())),
+            Expr::Hash(_) => return Err(unsupported("hash", &e.string())),
+            Expr::Index { .. } => return Err(unsupported("index", &e.string())),
+            Expr::Member { .. } => return Err(unsupported("member access", &e.string())),
+            Expr::Assign { .. } => return Err(unsupported("assignment", &e.string())),
+            Expr::Match { .. } => return Err(unsupported("match", &e.string())),
+            Expr::Coalesce { .. } => return Err(unsupported("??", &e.string())),
+        }
+        Ok(())
+    }
+
+    fn compile_function(&mut self, func: &Function, name: Option<&str>) -> Result<(), String> {
+        let params = func
+            .parameters
+            .iter()
+            .map(|p| p.pattern.as_ident())
+            .collect::<Option<Vec<_>>>()
+            .ok_or_else(|| unsupported("destructuring", &func.string()))?;
+
+        self.enter_scope();
+        if let Some(name) = name {
+            self.symbol_table.define_function_name(name);
+        }
+        for p in params {
+            self.symbol_table.define_parameter(&p.0);
+        }
+
+        self.compile_branch(&func.body)?;
+        self.emit(Opcode::ReturnValue, &[])?;
+
+        let num_locals = self.symbol_table.num_definitions;
+        let local_names = std::mem::take(&mut self.symbol_table.names);
+        let free_symbols = std::mem::take(&mut self.symbol_table.free_symbols);
+        let (instructions, lines) = self.leave_scope();
+
+        // 在外层作用域把要捕获的值依次压栈
+        for sym in &free_symbols {
+            self.load_symbol(sym)?;
+        }
+
+        let compiled = Object::CompiledFunction(Rc::new(CompiledFunction {
+            instructions,
+            num_locals,
+            num_parameters: func.parameters.len(),
+            local_names,
+            lines,
+        }));
+        let idx = self.add_constant(compiled);
+        self.emit(Opcode::Closure, &[idx, free_symbols.len()])?;
+        Ok(())
+    }
+
+    fn load_symbol(&mut self, sym: &Symbol) -> Result<(), String> {
+        match sym.scope {
+            SymbolScope::Global => self.emit(Opcode::GetGlobal, &[sym.index])?,
+            SymbolScope::Local => self.emit(Opcode::GetLocal, &[sym.index])?,
+            SymbolScope::Free => self.emit(Opcode::GetFree, &[sym.index])?,
+            SymbolScope::Function => self.emit(Opcode::CurrentClosure, &[])?,
+        };
+        Ok(())
+    }
+
+    /* ---------- helpers ---------- */
+
+    fn add_constant(&mut self, obj: Object) -> usize {
+        self.constants.push(obj);
+        self.constants.len() - 1
+    }
+
+    fn current_instructions(&mut self) -> &mut Instructions {
+        &mut self
+            .scopes
+            .last_mut()
+            .expect("no compilation scope")
+            .instructions
+    }
+
+    // 追加一条指令 返回它的起始位置
+    // 操作数放不进编码宽度 (常量池 局部变量 参数个数 跳转地址太大) 直接报编译错误 不能截断
+    fn emit(&mut self, op: Opcode, operands: &[usize]) -> Result<usize, String> {
+        check_operands(op, operands)?;
+        let ins = make(op, operands);
+        let current = self.current_instructions();
+        let pos = current.len();
+        current.extend_from_slice(&ins);
+        Ok(pos)
+    }
+
+    // 回填跳转地址
+    fn change_operand(&mut self, pos: usize, operand: usize) -> Result<(), String> {
+        let op = Opcode::from_byte(self.current_instructions()[pos]).expect("invalid opcode");
+        check_operands(op, &[operand])?;
+        let ins = make(op, &[operand]);
+        self.current_instructions()[pos..pos + ins.len()].copy_from_slice(&ins);
+        Ok(())
+    }
+
+    fn enter_scope(&mut self) {
+        self.scopes.push(CompilationScope::default());
+        let outer = std::mem::take(&mut self.symbol_table);
+        self.symbol_table = SymbolTable::new_enclosed(outer);
+    }
+
+    fn leave_scope(&mut self) -> (Instructions, LineTable) {
+        let scope = self.scopes.pop().expect("no compilation scope");
+        let inner = std::mem::take(&mut self.symbol_table);
+        self.symbol_table = *inner.outer.expect("leaving the global scope");
+        (scope.instructions, scope.lines)
+    }
+
+    // 语句开始处记录行号 同一行的连续语句只记一次
+    fn mark_line(&mut self, stmt: &Statement) {
+        let Some(pos) = stmt.pos() else {
+            return;
+        };
+        let scope = self.scopes.last_mut().expect("no compilation scope");
+        let offset = scope.instructions.len();
+        match scope.lines.last_mut() {
+            Some(last) if last.1 == pos.line => {}
+            // 同一偏移上后来的语句覆盖前面的 (比如空的 let 前缀)
+            Some(last) if last.0 == offset => last.1 = pos.line,
+            _ => scope.lines.push((offset, pos.line)),
+        }
+    }
+}
+
+// vm 还没实现的功能统一这样报: 功能名 + 出问题的代码
+fn unsupported(feature: &str, code: &str) -> String {
+    format!("not supported by the vm yet: {} `{}`", feature, code)
+}
@@ -1,13 +1,36 @@
-use crate::object::Object;
+use crate::{heap::Allocation, object::Object};
 use ahash::AHashMap as HashMap;
 use std::{cell::RefCell, rc::Rc};
 
+// 赋值失败的原因
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AssignError {
+    // 环境链上没有这个名字
+    Undeclared,
+    // 最近的绑定是 const
+    Constant,
+}
+
 #[derive(Debug, Clone)]
-struct Environment {
-    // 存储环境变量当前作用域
+pub struct Environment {
+    // 全局作用域 按名字存 (REPL 每次输入都可能加新名字)
     store: HashMap<String, Object>,
+    // 函数作用域 按 resolver 算好的槽位存 None 表示对应的 let 还没执行
+    slots: Vec<Option<Object>>,
+    // 每个槽位的名字 只在按名字查找时用
+    names: Rc<[String]>,
+    // 这个作用域里用 const 绑定的名字 (通常很少 没有时不分配)
+    constants: Vec<String>,
     // 外部环境变量
     outer: Option<Rc<RefCell<Environment>>>,
+    // 函数调用环境在解释器堆上记的账 环境释放时归还
+    charge: Option<Allocation>,
+}
+
+impl Default for Environment {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Environment {
@@ -15,19 +38,182 @@ impl Environment {
     pub fn new() -> Self {
         Environment {
             store: HashMap::new(),
+            slots: Vec::new(),
+            names: Rc::default(),
+            constants: Vec::new(),
             outer: None,
+            charge: None,
         }
     }
 
+    // 内层环境 函数调用时使用 names 是函数的槽位布局
+    pub fn new_enclosed(outer: Rc<RefCell<Environment>>, names: Rc<[String]>) -> Self {
+        Environment {
+            store: HashMap::new(),
+            slots: vec![None; names.len()],
+            names,
+            constants: Vec::new(),
+            outer: Some(outer),
+            charge: None,
+        }
+    }
+
+    // 槽位占的字节数 解释器按这个记账
+    pub fn size_of_enclosed(slots: usize) -> usize {
+        size_of::<RefCell<Environment>>() + slots * size_of::<Option<Object>>()
+    }
+
+    pub fn set_charge(&mut self, charge: Allocation) {
+        self.charge = Some(charge);
+    }
+
+    // 当前作用域找不到 沿着outer链向外查找
     pub fn get(&self, name: &str) -> Option<Object> {
-        if let Some(v) = self.store.get(name) {
-            Some(v.clone())
-        } else {
-            None
+        let local = match self.names.iter().position(|n| n == name) {
+            Some(slot) => self.slots[slot].clone(),
+            None => self.store.get(name).cloned(),
+        };
+        match local {
+            Some(v) => Some(v),
+            None => self.outer.as_ref().and_then(|o| o.borrow().get(name)),
+        }
+    }
+
+    // 按词法地址查找: 往外走 depth 层 取 slot
+    // 槽位还是空的 (let 还没执行到) 就和以前一样按名字继续往外找
+    pub fn get_at(&self, depth: usize, slot: usize, name: &str) -> Option<Object> {
+        if depth > 0 {
+            return self.outer.as_ref()?.borrow().get_at(depth - 1, slot, name);
+        }
+        match self.slots.get(slot) {
+            Some(Some(v)) => Some(v.clone()),
+            _ => self.outer.as_ref().and_then(|o| o.borrow().get(name)),
+        }
+    }
+
+    // 全局作用域 (环境链的最外层)
+    pub fn get_global(&self, name: &str) -> Option<Object> {
+        match &self.outer {
+            Some(o) => o.borrow().get_global(name),
+            None => self.store.get(name).cloned(),
         }
     }
 
+    // 当前作用域里绑定的名字 (不含outer)
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        let slots = self
+            .names
+            .iter()
+            .zip(&self.slots)
+            .filter(|(_, v)| v.is_some())
+            .map(|(n, _)| n.as_str());
+        self.store.keys().map(String::as_str).chain(slots)
+    }
+
     pub fn set(&mut self, name: &str, value: Object) {
-        self.store.insert(name.to_string(), value);
+        match self.names.iter().position(|n| n == name) {
+            Some(slot) => self.slots[slot] = Some(value),
+            None => {
+                self.store.insert(name.to_string(), value);
+            }
+        }
+    }
+
+    // 写当前函数环境的槽位 (let 总是绑定在当前作用域 depth 一定是 0)
+    pub fn set_slot(&mut self, slot: usize, value: Object) {
+        self.slots[slot] = Some(value);
+    }
+
+    // 把当前作用域里的 name 标记为常量 之后不能赋值 也不能在这个作用域里重新绑定
+    pub fn mark_constant(&mut self, name: &str) {
+        if !self.is_constant(name) {
+            self.constants.push(name.to_string());
+        }
+    }
+
+    // 当前作用域里的 name 是不是常量 (不看 outer 内层作用域可以遮住外面的常量)
+    pub fn is_constant(&self, name: &str) -> bool {
+        self.constants.iter().any(|c| c == name)
+    }
+
+    /* ========== assignment ========== */
+
+    // 赋值: 沿 outer 链找到最近的绑定 把 f 作用在它的值上
+    pub fn update<R>(
+        &mut self,
+        name: &str,
+        f: impl FnOnce(&mut Object) -> R,
+    ) -> Result<R, AssignError> {
+        let constant = self.is_constant(name);
+        let local = match self.names.iter().position(|n| n == name) {
+            Some(slot) => self.slots[slot].as_mut(),
+            None => self.store.get_mut(name),
+        };
+        match local {
+            Some(_) if constant => Err(AssignError::Constant),
+            Some(v) => Ok(f(v)),
+            None => match &self.outer {
+                Some(o) => o.borrow_mut().update(name, f),
+                None => Err(AssignError::Undeclared),
+            },
+        }
+    }
+
+    // 按词法地址赋值 槽位还是空的就和 get_at 一样按名字往外找
+    pub fn update_at<R>(
+        &mut self,
+        depth: usize,
+        slot: usize,
+        name: &str,
+        f: impl FnOnce(&mut Object) -> R,
+    ) -> Result<R, AssignError> {
+        if depth > 0 {
+            return match &self.outer {
+                Some(o) => o.borrow_mut().update_at(depth - 1, slot, name, f),
+                None => Err(AssignError::Undeclared),
+            };
+        }
+        let constant = self.is_constant(name);
+        match (self.slots.get_mut(slot), &self.outer) {
+            (Some(Some(_)), _) if constant => Err(AssignError::Constant),
+            (Some(Some(v)), _) => Ok(f(v)),
+            (_, Some(o)) => o.borrow_mut().update(name, f),
+            (_, None) => Err(AssignError::Undeclared),
+        }
+    }
+
+    pub fn update_global<R>(
+        &mut self,
+        name: &str,
+        f: impl FnOnce(&mut Object) -> R,
+    ) -> Result<R, AssignError> {
+        if let Some(o) = &self.outer {
+            return o.borrow_mut().update_global(name, f);
+        }
+        if self.is_constant(name) && self.store.contains_key(name) {
+            return Err(AssignError::Constant);
+        }
+        self.store
+            .get_mut(name)
+            .map(f)
+            .ok_or(AssignError::Undeclared)
+    }
+
+    /* ========== gc ========== */
+
+    // 这个环境直接引用的值和外层环境 回收器用它们找引用关系
+    pub(crate) fn values(&self) -> impl Iterator<Item = &Object> {
+        self.slots.iter().flatten().chain(self.store.values())
+    }
+
+    pub(crate) fn outer(&self) -> Option<&Rc<RefCell<Environment>>> {
+        self.outer.as_ref()
+    }
+
+    // 拆掉环境里的引用 打断循环 拿出来的东西由调用方在借用结束后 drop
+    pub(crate) fn take_references(&mut self) -> (Vec<Object>, Option<Rc<RefCell<Environment>>>) {
+        let mut values: Vec<Object> = self.slots.iter_mut().filter_map(Option::take).collect();
+        values.extend(self.store.drain().map(|(_, v)| v));
+        (values, self.outer.take())
     }
 }
@@ -1,7 +1,32 @@
+use std::{cell::RefCell, rc::Rc};
+
+use std::{
+    fmt, fs,
+    path::{Path, PathBuf},
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+use ahash::AHashMap as HashMap;
+
 use crate::{
-    ast::{Expr, Program, Statement},
-    object::Object,
+    analyzer,
+    ast::{
+        Address, Expr, Function, Ident, MatchArm, Param, Pattern, Program, Statement, StringPart,
+    },
+    builtins,
+    environment::{AssignError, Environment},
+    gc::Collector,
+    heap::{Allocation, Heap, MemoryUsage},
+    lexer::Lexer,
+    object::{FunctionObject, HashKey, Map, Module, Object, ObjectType},
+    parser::Parser,
+    stdlib,
     token::TokenType,
+    types::{self, Type},
 };
 
 /* ========== error helpers ========== */
@@ -21,206 +46,1270 @@ fn is_error(o: &Object) -> bool {
 #[derive(Debug, Clone)]
 enum EvalFlow {
     Value(Object),  // 正常值
-    Return(Object), // return 冒泡（以后在函数边界消化）
+    Return(Object), // return 冒泡（在函数边界消化）
+    // 尾调用: 冒泡到 apply_function 由它接着执行 不占 rust 栈
+    TailCall(Rc<FunctionObject>, Vec<Object>),
+}
+
+pub type Env = Rc<RefCell<Environment>>;
+
+// 默认的调用深度上限 (非尾调用)
+pub const DEFAULT_MAX_DEPTH: usize = 512;
+
+// 默认一次 run 最多用多少字节的 rust 栈
+// 调用层数管不住每层调用里嵌套的表达式 所以再直接量一下栈用了多少
+// 在 rust 默认 2MB 的线程上也留了 512KB 余量 宿主线程栈更大时用 max_stack 调高
+pub const DEFAULT_MAX_STACK: usize = 1536 * 1024;
+
+// 每隔多少步看一次时钟 Instant::now() 不便宜
+const CLOCK_INTERVAL: u64 = 256;
+
+/* ========== runtime errors ========== */
+
+// 运行时错误 Display 的内容就是 Object::Error 里的消息
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RuntimeError {
+    // 普通错误: 未定义的名字 类型不匹配 除零 ...
+    Error(String),
+    // --strict-types 下值不符合类型注解
+    TypeMismatch {
+        context: String,
+        expected: Type,
+        got: ObjectType,
+    },
+    // 非尾调用嵌套超过了 max_depth
+    StackOverflow {
+        depth: usize,
+    },
+    // 求值用掉的 rust 栈超过了 max_stack 再往下会把宿主线程的栈撑爆
+    NativeStackOverflow {
+        limit: usize,
+    },
+    // 执行步数用完了 (每次函数调用 包括尾调用 算一步)
+    OutOfFuel {
+        steps: u64,
+    },
+    // 超过了 timeout 给的时间
+    Timeout {
+        timeout: Duration,
+    },
+    // 宿主设置了 cancel flag
+    Cancelled,
+    // 活着的闭包和调用环境超过了 max_memory
+    OutOfMemory {
+        limit: usize,
+    },
+    // 模块直接或间接地 import 了自己 cycle 是 import 里写的路径 首尾相同
+    CircularImport {
+        cycle: Vec<String>,
+    },
+}
+
+impl fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RuntimeError::Error(msg) => write!(f, "{}", msg),
+            RuntimeError::TypeMismatch {
+                context,
+                expected,
+                got,
+            } => write!(
+                f,
+                "{}: expected {}, got {}",
+                context,
+                expected,
+                types::annotation_name(*got)
+            ),
+            RuntimeError::StackOverflow { depth } => {
+                write!(f, "stack overflow: call depth exceeded {}", depth)
+            }
+            RuntimeError::NativeStackOverflow { limit } => {
+                write!(
+                    f,
+                    "stack overflow: evaluation used more than {} bytes of stack",
+                    limit
+                )
+            }
+            RuntimeError::OutOfFuel { steps } => {
+                write!(f, "out of fuel: step limit of {} exceeded", steps)
+            }
+            RuntimeError::Timeout { timeout } => {
+                write!(f, "execution timed out after {:?}", timeout)
+            }
+            RuntimeError::Cancelled => write!(f, "execution cancelled"),
+            RuntimeError::OutOfMemory { limit } => {
+                write!(f, "out of memory: heap limit of {} bytes exceeded", limit)
+            }
+            RuntimeError::CircularImport { cycle } => {
+                write!(f, "circular import: {}", cycle.join(" -> "))
+            }
+        }
+    }
 }
 
 /* ========== public entry ========== */
 
 pub fn eval(program: &Program) -> Object {
-    match eval_statements(&program.statements) {
-        EvalFlow::Value(v) | EvalFlow::Return(v) => v, // 顶层：先都当值输出
+    Interpreter::new().eval(program)
+}
+
+// 在给定环境中求值 REPL 用它在多次输入之间保留绑定
+pub fn eval_program(program: &Program, env: &Env) -> Object {
+    Interpreter::with_env(env.clone()).eval(program)
+}
+
+// 树遍历解释器 持有全局环境和运行选项
+#[derive(Debug)]
+pub struct Interpreter {
+    env: Env,
+    // 运行时检查类型注解
+    strict_types: bool,
+    // 当前嵌套的函数调用层数 (尾调用不增加)
+    depth: usize,
+    // depth 的上限
+    max_depth: usize,
+    // 这次 run 开始时 rust 栈的位置 和从那里往下最多能用多少字节
+    stack_base: Option<usize>,
+    max_stack: usize,
+    // 执行预算: 每次 run 重新计算
+    max_steps: Option<u64>,
+    timeout: Option<Duration>,
+    cancel: Option<Arc<AtomicBool>>,
+    steps: u64,
+    deadline: Option<Instant>,
+    // 闭包和调用环境的内存记账 跨多次 run 保留
+    heap: Rc<Heap>,
+    // 回收闭包和调用环境之间的 Rc 循环
+    gc: Collector,
+    // 求值过程中错误以 Object::Error 往上传 结构化的错误先记在这里
+    fault: Option<RuntimeError>,
+    // 入口脚本 它 import 的路径从它所在的目录算起 没有时从当前目录算起
+    script: Option<PathBuf>,
+    // 加载过的模块 按规范化的路径缓存 跨多次 run 保留
+    modules: HashMap<PathBuf, Rc<Module>>,
+    // 正在执行的文件 (规范化的路径, import 里写的路径) 栈顶是当前文件
+    loading: Vec<(PathBuf, String)>,
+    // 第一次 run 之前把标准库的名字放进全局环境
+    prelude: bool,
+    prelude_loaded: bool,
+}
+
+impl Default for Interpreter {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
-/* ========== core ========== */
+impl Interpreter {
+    pub fn new() -> Self {
+        Self::with_env(Rc::new(RefCell::new(Environment::new())))
+    }
 
-fn eval_statements(stmts: &Vec<Statement>) -> EvalFlow {
-    let mut last = Object::Null;
+    pub fn with_env(env: Env) -> Self {
+        Interpreter {
+            env,
+            strict_types: false,
+            depth: 0,
+            max_depth: DEFAULT_MAX_DEPTH,
+            stack_base: None,
+            max_stack: DEFAULT_MAX_STACK,
+            max_steps: None,
+            timeout: None,
+            cancel: None,
+            steps: 0,
+            deadline: None,
+            heap: Heap::new(),
+            gc: Collector::new(),
+            fault: None,
+            script: None,
+            modules: HashMap::new(),
+            loading: Vec::new(),
+            prelude: true,
+            prelude_loaded: false,
+        }
+    }
 
-    for stmt in stmts {
-        match stmt {
-            Statement::Block(block) => {
-                match eval_statements(&block.statements) {
-                    r @ EvalFlow::Return(_) => return r, // 冒泡
-                    EvalFlow::Value(v) => {
-                        if is_error(&v) {
-                            return EvalFlow::Value(v);
-                        } // 错误粘性传播
-                        last = v;
+    pub fn strict_types(mut self, strict: bool) -> Self {
+        self.strict_types = strict;
+        self
+    }
+
+    // 非尾调用的最大嵌套层数 宿主线程栈更大时可以调高
+    pub fn max_depth(mut self, depth: usize) -> Self {
+        self.max_depth = depth;
+        self
+    }
+
+    // 一次 run 最多用多少字节的 rust 栈 要比运行它的线程的栈小 留出余量
+    pub fn max_stack(mut self, bytes: usize) -> Self {
+        self.max_stack = bytes;
+        self
+    }
+
+    // 每次 run 最多执行多少步 (函数调用和尾调用各算一步)
+    pub fn max_steps(mut self, steps: u64) -> Self {
+        self.max_steps = Some(steps);
+        self
+    }
+
+    // 每次 run 最多执行多长时间
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    // 其它线程把 flag 设为 true 就会让正在执行的 run 停下来
+    pub fn cancel_flag(mut self, flag: Arc<AtomicBool>) -> Self {
+        self.cancel = Some(flag);
+        self
+    }
+
+    // 正在运行的脚本文件 import 按它所在的目录解析相对路径
+    pub fn script_path(mut self, path: impl Into<PathBuf>) -> Self {
+        self.script = Some(path.into());
+        self
+    }
+
+    // 关掉之后全局环境里只有用户自己定义的名字和内建函数
+    pub fn prelude(mut self, prelude: bool) -> Self {
+        self.prelude = prelude;
+        self
+    }
+
+    // 回收只在循环里互相引用的调用环境 返回拆掉的个数
+    pub fn collect_garbage(&mut self) -> usize {
+        self.gc.collect()
+    }
+
+    // 活着的闭包和调用环境最多占多少字节
+    pub fn max_memory(self, bytes: usize) -> Self {
+        self.heap.set_limit(Some(bytes));
+        self
+    }
+
+    // 当前的内存用量 峰值和上限
+    pub fn memory(&self) -> MemoryUsage {
+        self.heap.usage()
+    }
+
+    pub fn env(&self) -> &Env {
+        &self.env
+    }
+
+    // 在解释器的全局环境里执行 多次调用之间保留绑定
+    pub fn run(&mut self, program: &Program) -> Result<Object, RuntimeError> {
+        // 嵌套的 run (宿主在回调里再调用) 从最外面那次开始量
+        let outermost = self.stack_base.is_none();
+        if outermost {
+            self.stack_base = Some(stack_position());
+        }
+        let result = self.run_program(program);
+        if outermost {
+            self.stack_base = None;
+        }
+        result
+    }
+
+    fn run_program(&mut self, program: &Program) -> Result<Object, RuntimeError> {
+        self.fault = None;
+        self.steps = 0;
+        self.deadline = self.timeout.map(|t| Instant::now() + t);
+        self.loading.clear();
+        if let Some(script) = &self.script {
+            let file = fs::canonicalize(script).unwrap_or_else(|_| script.clone());
+            self.loading.push((file, script.display().to_string()));
+        }
+        if self.prelude && !self.prelude_loaded {
+            self.prelude_loaded = true;
+            self.load_prelude()?;
+        }
+        let env = self.env.clone();
+        let value = match self.eval_statements(&program.statements, &env, false) {
+            EvalFlow::Value(v) | EvalFlow::Return(v) => v, // 顶层：先都当值输出
+            EvalFlow::TailCall(..) => unreachable!("tail call outside of a function"),
+        };
+        // 这次 run 留下的循环垃圾 在这里清掉
+        self.collect_garbage();
+        match value {
+            Object::Error(msg) => Err(self.fault.take().unwrap_or(RuntimeError::Error(msg))),
+            v => Ok(v),
+        }
+    }
+
+    // 和 run 一样 错误折叠成 Object::Error
+    pub fn eval(&mut self, program: &Program) -> Object {
+        self.run(program)
+            .unwrap_or_else(|e| Object::Error(e.to_string()))
+    }
+
+    /* ========== core ========== */
+
+    // tail: 这个语句列表的值就是当前函数的返回值 最后一条语句里的调用可以做尾调用
+    fn eval_statements(&mut self, stmts: &[Statement], env: &Env, tail: bool) -> EvalFlow {
+        if let Some(e) = self.check_stack() {
+            return EvalFlow::Value(e);
+        }
+        let mut last = Object::Null;
+
+        for (i, stmt) in stmts.iter().enumerate() {
+            let tail = tail && i + 1 == stmts.len();
+            match stmt {
+                Statement::Block(block) => {
+                    match self.eval_statements(&block.statements, env, tail) {
+                        EvalFlow::Value(v) => {
+                            if is_error(&v) {
+                                return EvalFlow::Value(v);
+                            } // 错误粘性传播
+                            last = v;
+                        }
+                        r => return r, // 冒泡
+                    }
+                }
+
+                Statement::Expression(expr_stmt) => {
+                    match self.eval_expr(&expr_stmt.expression, env, tail) {
+                        EvalFlow::Value(v) => {
+                            if is_error(&v) {
+                                return EvalFlow::Value(v);
+                            }
+                            last = v;
+                        }
+                        r => return r,
                     }
                 }
-            }
 
-            Statement::Expression(expr_stmt) => match eval_expr(&expr_stmt.expression) {
-                r @ EvalFlow::Return(_) => return r,
-                EvalFlow::Value(v) => {
+                Statement::Return(ret_stmt) => {
+                    return self.eval_return(&ret_stmt.return_value, env);
+                }
+
+                Statement::Let {
+                    pattern,
+                    constant,
+                    ty,
+                    value,
+                    ..
+                } => {
+                    let v = match self.eval_expr(value, env, false) {
+                        EvalFlow::Value(v) => v,
+                        r => return r,
+                    };
                     if is_error(&v) {
                         return EvalFlow::Value(v);
                     }
-                    last = v;
+                    if let Err(e) = self.bind(env, pattern, *constant, ty.as_ref(), v) {
+                        return EvalFlow::Value(e);
+                    }
+                }
+
+                Statement::Import { path, name, .. } => match self.import(path) {
+                    Ok(module) => bind_name(&mut env.borrow_mut(), name, Object::Module(module)),
+                    Err(e) => return EvalFlow::Value(e),
+                },
+
+                Statement::None => {}
+            }
+        }
+
+        EvalFlow::Value(last)
+    }
+
+    // 当 if 分支不是 Block（虽然很少见），用这个处理单个 Statement
+    fn eval_single_statement(&mut self, stmt: &Statement, env: &Env, tail: bool) -> EvalFlow {
+        match stmt {
+            Statement::Block(b) => self.eval_statements(&b.statements, env, tail),
+
+            Statement::Expression(e) => self.eval_expr(&e.expression, env, tail),
+
+            Statement::Return(r) => self.eval_return(&r.return_value, env),
+
+            Statement::Let { .. } | Statement::Import { .. } => {
+                self.eval_statements(std::slice::from_ref(stmt), env, false)
+            }
+
+            Statement::None => EvalFlow::Value(Object::Null),
+        }
+    }
+
+    // return 的值就是函数的返回值 在函数里总是尾调用位置
+    fn eval_return(&mut self, value: &Expr, env: &Env) -> EvalFlow {
+        match self.eval_expr(value, env, self.depth > 0) {
+            EvalFlow::Value(v) | EvalFlow::Return(v) => EvalFlow::Return(v), // 立刻冒泡
+            call @ EvalFlow::TailCall(..) => call,
+        }
+    }
+
+    // 表达式产生的新字符串 数组 hash 在这里统一记到堆上 超过上限就变成错误
+    fn eval_expr(&mut self, e: &Expr, env: &Env, tail: bool) -> EvalFlow {
+        if let Some(e) = self.check_stack() {
+            return EvalFlow::Value(e);
+        }
+        match self.eval_expr_uncharged(e, env, tail) {
+            EvalFlow::Value(mut v) => match self.charge(&mut v) {
+                Ok(()) => EvalFlow::Value(v),
+                Err(e) => EvalFlow::Value(e),
+            },
+            r => r,
+        }
+    }
+
+    fn eval_expr_uncharged(&mut self, e: &Expr, env: &Env, tail: bool) -> EvalFlow {
+        match e {
+            Expr::Integer(i) => EvalFlow::Value(Object::Integer(*i)),
+            Expr::Boolean(b) => EvalFlow::Value(Object::Boolean(*b)),
+            // 每次求值都产生一份新字符串 这样才能记到堆上
+            Expr::String(s) => EvalFlow::Value(Object::string(&**s)),
+
+            // 每一段用 inspect 转成字符串拼起来
+            Expr::Interpolated(parts) => {
+                let mut out = String::new();
+                for part in parts {
+                    match part {
+                        StringPart::Text(text) => out.push_str(text),
+                        StringPart::Expr(e) => match self.eval_expr(e, env, false) {
+                            EvalFlow::Value(v) if is_error(&v) => return EvalFlow::Value(v),
+                            EvalFlow::Value(v) => out.push_str(&v.inspect()),
+                            r => return r,
+                        },
+                    }
                 }
+                EvalFlow::Value(Object::string(out))
+            }
+
+            Expr::Array(elements) => match self.eval_exprs(elements, env) {
+                Ok(elements) => EvalFlow::Value(Object::array(elements)),
+                Err(flow) => flow,
             },
 
-            Statement::Return(ret_stmt) => {
-                let v = match eval_expr(&ret_stmt.return_value) {
-                    EvalFlow::Return(v) => v,
+            Expr::Hash(pairs) => {
+                let mut map = Map::new();
+                for (k, v) in pairs {
+                    let key = match self.eval_expr(k, env, false) {
+                        EvalFlow::Value(v) if is_error(&v) => return EvalFlow::Value(v),
+                        EvalFlow::Value(v) => v,
+                        r => return r,
+                    };
+                    let Some(key) = key.hash_key() else {
+                        return EvalFlow::Value(err(format!(
+                            "unusable as hash key: {:?}",
+                            key.object_type()
+                        )));
+                    };
+                    let value = match self.eval_expr(v, env, false) {
+                        EvalFlow::Value(v) if is_error(&v) => return EvalFlow::Value(v),
+                        EvalFlow::Value(v) => v,
+                        r => return r,
+                    };
+                    map.insert(key, value);
+                }
+                EvalFlow::Value(Object::hash(map))
+            }
+
+            // xs?[i]: xs 是 null 时直接得到 null 下标也不求值
+            Expr::Index {
+                left,
+                index,
+                optional,
+            } => {
+                let lv = match self.eval_expr(left, env, false) {
+                    EvalFlow::Value(v) if is_error(&v) => return EvalFlow::Value(v),
+                    EvalFlow::Value(Object::Null) if *optional => {
+                        return EvalFlow::Value(Object::Null);
+                    }
+                    EvalFlow::Value(v) => v,
+                    r => return r,
+                };
+                let iv = match self.eval_expr(index, env, false) {
+                    EvalFlow::Value(v) if is_error(&v) => return EvalFlow::Value(v),
                     EvalFlow::Value(v) => v,
+                    r => return r,
                 };
-                return EvalFlow::Return(v); // 立刻冒泡
+                EvalFlow::Value(eval_index_expression(&lv, &iv))
             }
 
-            Statement::Let { .. } => {
-                // 以后接环境 env.set(...)
+            Expr::Member {
+                object,
+                name,
+                optional,
+            } => match self.eval_expr(object, env, false) {
+                EvalFlow::Value(v) if is_error(&v) => EvalFlow::Value(v),
+                EvalFlow::Value(Object::Null) if *optional => EvalFlow::Value(Object::Null),
+                EvalFlow::Value(v) => EvalFlow::Value(eval_member_expression(&v, name)),
+                r => r,
+            },
+
+            // c ? a : b 和 if 一样按 is_truthy 选一边 选中的一边继承尾调用位置
+            Expr::Conditional {
+                condition,
+                consequence,
+                alternative,
+            } => {
+                let cond = match self.eval_expr(condition, env, false) {
+                    EvalFlow::Value(v) if is_error(&v) => return EvalFlow::Value(v),
+                    EvalFlow::Value(v) => v,
+                    r => return r,
+                };
+                let chosen = if is_truthy(&cond) {
+                    consequence
+                } else {
+                    alternative
+                };
+                self.eval_expr(chosen, env, tail)
             }
 
-            Statement::None => {}
-        }
-    }
+            // a ?? b 只有 a 是 null 时才求值 b (false 和 0 不算)
+            Expr::Coalesce { left, right } => match self.eval_expr(left, env, false) {
+                EvalFlow::Value(Object::Null) => self.eval_expr(right, env, tail),
+                r => r,
+            },
 
-    EvalFlow::Value(last)
-}
+            // 标识符：按 resolver 算好的地址查找
+            // 环境里没有的名字再看是不是内建函数 所以用户可以用 let 覆盖它们
+            Expr::Ident(ident) => match lookup(env, ident) {
+                Some(v) => EvalFlow::Value(v),
+                None => match builtins::lookup(&ident.0) {
+                    Some(builtin) => EvalFlow::Value(Object::Builtin(builtin)),
+                    None => EvalFlow::Value(err(format!("identifier not found: {}", ident.0))),
+                },
+            },
+
+            Expr::Assign { target, op, value } => self.eval_assign(target, *op, value, env),
+
+            // 前缀
+            Expr::Prefix { op, right } => {
+                let rv = match self.eval_expr(right, env, false) {
+                    EvalFlow::Value(v) => v,
+                    r => return r,
+                };
+                if is_error(&rv) {
+                    return EvalFlow::Value(rv);
+                } // 错误上抛
+
+                EvalFlow::Value(eval_prefix_expression(op, rv))
+            }
+
+            // 中缀
+            Expr::Infix { left, op, right } => {
+                let lv = match self.eval_expr(left, env, false) {
+                    EvalFlow::Value(v) => v,
+                    r => return r,
+                };
+                if is_error(&lv) {
+                    return EvalFlow::Value(lv);
+                }
+
+                let rv = match self.eval_expr(right, env, false) {
+                    EvalFlow::Value(v) => v,
+                    r => return r,
+                };
+                if is_error(&rv) {
+                    return EvalFlow::Value(rv);
+                }
+
+                EvalFlow::Value(eval_infix_expression(op, lv, rv))
+            }
 
-// 当 if 分支不是 Block（虽然很少见），用这个处理单个 Statement
-fn eval_single_statement(stmt: &Statement) -> EvalFlow {
-    match stmt {
-        Statement::Block(b) => eval_statements(&b.statements),
+            // if 表达式 选中的分支继承尾调用位置
+            Expr::IfExpression {
+                condition,
+                consequence,
+                alternative,
+            } => {
+                let cond = match self.eval_expr(condition, env, false) {
+                    EvalFlow::Value(v) => v,
+                    r => return r,
+                };
+                // 条件若出错，直接上抛；不要把错误当布尔用
+                if is_error(&cond) {
+                    return EvalFlow::Value(cond);
+                }
 
-        Statement::Expression(e) => match eval_expr(&e.expression) {
-            r @ EvalFlow::Return(_) => r,
-            EvalFlow::Value(v) => {
-                if is_error(&v) {
-                    EvalFlow::Value(v)
+                let chosen: &Statement = if is_truthy(&cond) {
+                    consequence
                 } else {
-                    EvalFlow::Value(v)
+                    match alternative {
+                        Some(alternative) => alternative,
+                        None => return EvalFlow::Value(Object::Null),
+                    }
+                };
+
+                // else if 的分支是一个表达式语句 也在尾调用位置
+                match chosen {
+                    Statement::Block(b) => self.eval_statements(&b.statements, env, tail),
+                    other => self.eval_single_statement(other, env, tail),
                 }
             }
-        },
 
-        Statement::Return(r) => {
-            let v = match eval_expr(&r.return_value) {
-                EvalFlow::Return(v) => v,
-                EvalFlow::Value(v) => v,
-            };
-            EvalFlow::Return(v)
-        }
+            Expr::Match { subject, arms } => self.eval_match(subject, arms, env, tail),
+
+            // 函数字面量：捕获当前环境形成闭包
+            Expr::Fn(func) => {
+                let charge = match self.alloc(size_of::<FunctionObject>()) {
+                    Ok(charge) => charge,
+                    Err(e) => return EvalFlow::Value(e),
+                };
+                EvalFlow::Value(Object::Function(Rc::new(FunctionObject {
+                    function: func.clone(),
+                    env: env.clone(),
+                    charge: Some(charge),
+                })))
+            }
 
-        Statement::Let { .. } | Statement::None => EvalFlow::Value(Object::Null),
+            // 函数调用
+            Expr::Call {
+                function,
+                arguments,
+            } => {
+                let callee = match self.eval_expr(function, env, false) {
+                    EvalFlow::Value(v) => v,
+                    r => return r,
+                };
+                if is_error(&callee) {
+                    return EvalFlow::Value(callee);
+                }
+
+                let args = match self.eval_exprs(arguments, env) {
+                    Ok(args) => args,
+                    Err(flow) => return flow,
+                };
+
+                // 尾调用: 不在这里递归 交给外层 apply_function 的循环
+                match callee {
+                    Object::Function(func) if tail => EvalFlow::TailCall(func, args),
+                    callee => EvalFlow::Value(self.apply_function(callee, args)),
+                }
+            }
+
+            // 其它暂不支持
+            Expr::Float(_) | Expr::None => EvalFlow::Value(Object::Null),
+        }
     }
-}
 
-fn eval_expr(e: &Expr) -> EvalFlow {
-    match e {
-        Expr::Integer(i) => EvalFlow::Value(Object::Integer(*i)),
-        Expr::Boolean(b) => EvalFlow::Value(Object::Boolean(*b)),
+    // 赋值: 从左往右求出下标 再求右边的值 最后找到变量的绑定 沿着下标原地修改
+    // 值是赋进去的新值 x += 1 得到加完之后的 x
+    fn eval_assign(&mut self, target: &Expr, op: TokenType, value: &Expr, env: &Env) -> EvalFlow {
+        // 从外往里拆开 xs[i].name 最里面是变量
+        let mut chain = Vec::new();
+        let mut base = target;
+        let name = loop {
+            match base {
+                Expr::Ident(name) => break name,
+                Expr::Index { left: inner, .. } | Expr::Member { object: inner, .. } => {
+                    chain.push(base);
+                    base = inner;
+                }
+                other => {
+                    return EvalFlow::Value(err(format!(
+                        "invalid assignment target: {}",
+                        other.string()
+                    )));
+                }
+            }
+        };
 
-        // 前缀
-        Expr::Prefix { op, right } => {
-            let rv = match eval_expr(right) {
-                r @ EvalFlow::Return(_) => return r,
-                EvalFlow::Value(v) => v,
-            };
-            if is_error(&rv) {
-                return EvalFlow::Value(rv);
-            } // 错误上抛
-
-            let out = match op {
-                TokenType::Bang => Object::Boolean(!is_truthy(&rv)),
-                TokenType::Minus => match rv {
-                    Object::Integer(i) => Object::Integer(-i),
-                    _ => err(format!("unknown operator: - {:?}", rv)),
-                },
-                TokenType::Plus => match rv {
-                    Object::Integer(i) => Object::Integer(i),
-                    _ => err(format!("unknown operator: + {:?}", rv)),
+        let mut path = Vec::with_capacity(chain.len());
+        for step in chain.into_iter().rev() {
+            path.push(match step {
+                Expr::Index { index, .. } => match self.eval_expr(index, env, false) {
+                    EvalFlow::Value(v) if is_error(&v) => return EvalFlow::Value(v),
+                    EvalFlow::Value(v) => PathKey::Index(v),
+                    r => return r,
                 },
-                _ => err(format!("unknown prefix operator: {}", op)),
-            };
-            EvalFlow::Value(out)
+                Expr::Member { name, .. } => PathKey::Member(name.clone()),
+                _ => unreachable!("only index and member expressions are collected"),
+            });
         }
 
-        // 中缀
-        Expr::Infix { left, op, right } => {
-            let lv = match eval_expr(left) {
-                r @ EvalFlow::Return(_) => return r,
-                EvalFlow::Value(v) => v,
-            };
-            if is_error(&lv) {
-                return EvalFlow::Value(lv);
+        let value = match self.eval_expr(value, env, false) {
+            EvalFlow::Value(v) if is_error(&v) => return EvalFlow::Value(v),
+            EvalFlow::Value(v) => v,
+            r => return r,
+        };
+
+        let update =
+            |slot: &mut Object| assign_path(slot, &path, op, value, &mut |bytes| self.alloc(bytes));
+        let mut env = env.borrow_mut();
+        let result = match name.1 {
+            Address::Local { depth, slot } => env.update_at(depth, slot, &name.0, update),
+            Address::Global => env.update_global(&name.0, update),
+            Address::Unresolved => env.update(&name.0, update),
+        };
+        EvalFlow::Value(match result {
+            Ok(Ok(v) | Err(v)) => v,
+            Err(AssignError::Undeclared) => {
+                err(format!("assignment to undeclared variable: {}", name.0))
             }
+            Err(AssignError::Constant) => err(format!("cannot assign to constant {}", name.0)),
+        })
+    }
+
+    // match: 按顺序找第一个模式匹配并且守卫为真的分支 分支表达式继承尾调用位置
+    // 模式绑定的名字放在分支自己的环境里 守卫不成立时这个环境直接丢掉
+    fn eval_match(&mut self, subject: &Expr, arms: &[MatchArm], env: &Env, tail: bool) -> EvalFlow {
+        let value = match self.eval_expr(subject, env, false) {
+            EvalFlow::Value(v) if is_error(&v) => return EvalFlow::Value(v),
+            EvalFlow::Value(v) => v,
+            r => return r,
+        };
 
-            let rv = match eval_expr(right) {
-                r @ EvalFlow::Return(_) => return r,
-                EvalFlow::Value(v) => v,
+        for arm in arms {
+            let mut bindings = Vec::new();
+            if !match_pattern(&arm.pattern, &value, &mut bindings) {
+                continue;
+            }
+            if let Err(e) = self.charge_bindings(&mut bindings) {
+                return EvalFlow::Value(e);
+            }
+            let charge = match self.alloc(Environment::size_of_enclosed(arm.slots.len())) {
+                Ok(charge) => charge,
+                Err(e) => return EvalFlow::Value(e),
             };
-            if is_error(&rv) {
-                return EvalFlow::Value(rv);
+            let mut inner = Environment::new_enclosed(env.clone(), arm.slots.clone());
+            inner.set_charge(charge);
+            for (name, value) in bindings {
+                bind_name(&mut inner, name, value);
+            }
+            let inner = Rc::new(RefCell::new(inner));
+            self.gc.track(&inner);
+
+            if let Some(guard) = &arm.guard {
+                match self.eval_expr(guard, &inner, false) {
+                    EvalFlow::Value(v) if is_error(&v) => return EvalFlow::Value(v),
+                    EvalFlow::Value(v) if !is_truthy(&v) => continue,
+                    EvalFlow::Value(_) => {}
+                    r => return r,
+                }
             }
+            return self.eval_expr(&arm.body, &inner, tail);
+        }
+        EvalFlow::Value(err(format!(
+            "non-exhaustive match: no arm matches {}",
+            value.inspect()
+        )))
+    }
 
-            EvalFlow::Value(eval_infix_expression(op, lv, rv))
+    // 依次求值 (实参 数组元素) 出错或者冒泡时把它交给调用方原样返回
+    fn eval_exprs(&mut self, exprs: &[Expr], env: &Env) -> Result<Vec<Object>, EvalFlow> {
+        let mut values = Vec::with_capacity(exprs.len());
+        for e in exprs {
+            match self.eval_expr(e, env, false) {
+                EvalFlow::Value(v) if is_error(&v) => return Err(EvalFlow::Value(v)),
+                EvalFlow::Value(v) => values.push(v),
+                r => return Err(r),
+            }
         }
+        Ok(values)
+    }
+
+    // 调用函数：新建内层环境绑定参数，执行函数体，在函数边界消化 return
+    // 函数体以尾调用结束时换成被调函数继续循环 (trampoline) rust 栈不会增长
+    fn apply_function(&mut self, callee: Object, args: Vec<Object>) -> Object {
+        let mut func = match callee {
+            Object::Function(func) => func,
+            Object::Builtin(builtin) => return builtin.call(args),
+            other => return err(format!("not a function: {:?}", other.object_type())),
+        };
+        let mut args = args;
+        // 尾调用链上每个函数的返回类型注解 最终的值要都符合
+        let mut return_types: Vec<Type> = Vec::new();
 
-        // if 表达式
-        Expr::IfExpression {
-            condition,
-            consequence,
-            alternative,
-        } => {
-            let cond = match eval_expr(condition) {
-                r @ EvalFlow::Return(_) => return r,
-                EvalFlow::Value(v) => v,
+        if self.depth >= self.max_depth {
+            return self.fail(RuntimeError::StackOverflow {
+                depth: self.max_depth,
+            });
+        }
+        self.depth += 1;
+        let result = loop {
+            // 每次调用 (包括尾调用循环的每一圈) 检查一次执行预算
+            if let Err(e) = self.tick() {
+                break self.fail(e);
+            }
+            let Function {
+                parameters,
+                return_type,
+                body,
+                slots,
+            } = &func.function;
+            if parameters.len() != args.len() {
+                break err(format!(
+                    "wrong number of arguments: want={}, got={}",
+                    parameters.len(),
+                    args.len()
+                ));
+            }
+            if self.strict_types
+                && let Some(ty) = return_type
+                && !return_types.contains(ty)
+            {
+                return_types.push(ty.clone());
+            }
+
+            let charge = match self.alloc(Environment::size_of_enclosed(slots.len())) {
+                Ok(charge) => charge,
+                Err(e) => break e,
             };
-            // 条件若出错，直接上抛；不要把错误当布尔用
-            if is_error(&cond) {
-                return EvalFlow::Value(cond);
+            let mut inner = Environment::new_enclosed(func.env.clone(), slots.clone());
+            inner.set_charge(charge);
+            let mut bad_arg = None;
+            for (Param { pattern, ty }, arg) in parameters.iter().zip(args) {
+                let mut bindings = Vec::new();
+                let checked = self
+                    .check_type(ty.as_ref(), &arg, || {
+                        format!("parameter {}", pattern.string())
+                    })
+                    .and_then(|_| destructure(pattern, arg, &mut bindings))
+                    .and_then(|_| self.charge_bindings(&mut bindings));
+                if let Err(e) = checked {
+                    bad_arg = Some(e);
+                    break;
+                }
+                for (name, value) in bindings {
+                    bind_name(&mut inner, name, value);
+                }
+            }
+            if let Some(e) = bad_arg {
+                break e;
+            }
+            let inner = Rc::new(RefCell::new(inner));
+            self.gc.track(&inner);
+            if self.gc.should_collect() {
+                self.gc.collect();
+            }
+
+            match self.eval_single_statement(body, &inner, true) {
+                EvalFlow::Value(v) | EvalFlow::Return(v) => break v,
+                EvalFlow::TailCall(next, next_args) => {
+                    func = next;
+                    args = next_args;
+                }
+            }
+        };
+        self.depth -= 1;
+
+        if !is_error(&result) {
+            for ty in &return_types {
+                if let Err(e) = self.check_type(Some(ty), &result, || "return value".to_string()) {
+                    return e;
+                }
             }
+        }
+        result
+    }
+
+    /* ========== modules ========== */
 
-            let chosen: &Statement = if is_truthy(&cond) {
-                consequence
-            } else {
-                alternative
+    // 标准库各模块在自己的环境里执行 再把顶层名字复制到全局环境
+    // 用户覆盖了 len 之类的名字也不影响标准库 prelude 的闭包不算在 max_memory 里
+    fn load_prelude(&mut self) -> Result<(), RuntimeError> {
+        let heap = std::mem::replace(&mut self.heap, Heap::new());
+        let mut result = Ok(());
+        for (path, _) in stdlib::MODULES {
+            match self.import(path) {
+                Ok(module) => {
+                    let module = module.env.borrow();
+                    let mut globals = self.env.borrow_mut();
+                    for name in module.names() {
+                        if let Some(value) = module.get(name) {
+                            globals.set(name, value);
+                        }
+                    }
+                }
+                Err(e) => {
+                    let msg = match e {
+                        Object::Error(msg) => msg,
+                        other => other.inspect(),
+                    };
+                    result = Err(self.fault.take().unwrap_or(RuntimeError::Error(msg)));
+                    break;
+                }
+            }
+        }
+        self.heap = heap;
+        result
+    }
+
+    // 加载模块: 相对路径从正在执行的文件所在的目录算起 std/ 开头的是内置的标准库
+    // 同一个文件只执行一次 模块在自己的全局环境里执行 执行中又被 import 到就是循环 import
+    fn import(&mut self, path: &str) -> Result<Rc<Module>, Object> {
+        let builtin = stdlib::program(path);
+        let base = match self.loading.last() {
+            Some((file, _)) => file.parent().map(Path::to_path_buf).unwrap_or_default(),
+            None => PathBuf::new(),
+        };
+        let file = match builtin {
+            Some(_) => PathBuf::from(path),
+            None => fs::canonicalize(base.join(path))
+                .map_err(|e| err(format!("cannot import {:?}: {}", path, e)))?,
+        };
+        if let Some(module) = self.modules.get(&file) {
+            return Ok(module.clone());
+        }
+        if let Some(start) = self.loading.iter().position(|(f, _)| *f == file) {
+            let mut cycle: Vec<String> = self.loading[start..]
+                .iter()
+                .map(|(_, p)| p.clone())
+                .collect();
+            cycle.push(path.to_string());
+            return Err(self.fail(RuntimeError::CircularImport { cycle }));
+        }
+
+        let program = match builtin {
+            Some(program) => program,
+            None => fs::read_to_string(&file)
+                .map_err(|e| e.to_string())
+                .and_then(|source| parse_module(&source))
+                .map(Rc::new)
+                .map_err(|msg| err(format!("cannot import {:?}: {}", path, msg)))?,
+        };
+
+        let env = Rc::new(RefCell::new(Environment::new()));
+        self.loading.push((file.clone(), path.to_string()));
+        let flow = self.eval_statements(&program.statements, &env, false);
+        self.loading.pop();
+        match flow {
+            EvalFlow::Value(v) | EvalFlow::Return(v) if is_error(&v) => return Err(v),
+            EvalFlow::TailCall(..) => unreachable!("tail call outside of a function"),
+            _ => {}
+        }
+
+        let module = Rc::new(Module {
+            path: path.to_string(),
+            env,
+        });
+        self.modules.insert(file, module.clone());
+        Ok(module)
+    }
+
+    /* ========== variables ========== */
+
+    // let / const 绑定到当前作用域 同一个作用域里的常量不能被重新绑定
+    // 解构时先把值全部拆开 形状不对就一个名字也不绑定
+    fn bind(
+        &mut self,
+        env: &Env,
+        pattern: &Pattern,
+        constant: bool,
+        ty: Option<&Type>,
+        value: Object,
+    ) -> Result<(), Object> {
+        self.check_type(ty, &value, || format!("let {}", pattern.string()))?;
+        let mut bindings = Vec::new();
+        destructure(pattern, value, &mut bindings)?;
+        self.charge_bindings(&mut bindings)?;
+
+        let mut env = env.borrow_mut();
+        if let Some((name, _)) = bindings.iter().find(|(name, _)| env.is_constant(&name.0)) {
+            return Err(err(format!("cannot redeclare constant {}", name.0)));
+        }
+        for (name, value) in bindings {
+            bind_name(&mut env, name, value);
+            if constant {
+                env.mark_constant(&name.0);
+            }
+        }
+        Ok(())
+    }
+
+    // --strict-types 下检查值是否符合注解 不符合时返回要往上传的错误
+    fn check_type(
+        &mut self,
+        ty: Option<&Type>,
+        value: &Object,
+        context: impl FnOnce() -> String,
+    ) -> Result<(), Object> {
+        if !self.strict_types {
+            return Ok(());
+        }
+        let Some((path, expected, got)) = ty.and_then(|ty| ty.mismatch(value)) else {
+            return Ok(());
+        };
+        // 数组元素不符合时指出是哪个元素
+        let context = path.iter().fold(context(), |context, i| {
+            format!("element {} of {}", i, context)
+        });
+        Err(self.fail(RuntimeError::TypeMismatch {
+            context,
+            expected: expected.clone(),
+            got,
+        }))
+    }
+
+    // 走一步 预算用完时返回对应的错误
+    fn tick(&mut self) -> Result<(), RuntimeError> {
+        self.steps += 1;
+        if let Some(max) = self.max_steps
+            && self.steps > max
+        {
+            return Err(RuntimeError::OutOfFuel { steps: max });
+        }
+        if let Some(flag) = &self.cancel
+            && flag.load(Ordering::Relaxed)
+        {
+            return Err(RuntimeError::Cancelled);
+        }
+        if let (Some(deadline), Some(timeout)) = (self.deadline, self.timeout)
+            && self.steps.is_multiple_of(CLOCK_INTERVAL)
+            && Instant::now() >= deadline
+        {
+            return Err(RuntimeError::Timeout { timeout });
+        }
+        Ok(())
+    }
+
+    // 在解释器堆上记一笔 超过上限时返回要往上传的错误
+    fn alloc(&mut self, bytes: usize) -> Result<Allocation, Object> {
+        // 超过上限之前先回收一次再试
+        let charge = self.heap.alloc(bytes).or_else(|| {
+            self.gc.collect();
+            self.heap.alloc(bytes)
+        });
+        match charge {
+            Some(charge) => Ok(charge),
+            None => {
+                let limit = self.heap.limit().unwrap_or(usize::MAX);
+                Err(self.fail(RuntimeError::OutOfMemory { limit }))
+            }
+        }
+    }
+
+    fn charge(&mut self, obj: &mut Object) -> Result<(), Object> {
+        charge(obj, &mut |bytes| self.alloc(bytes))
+    }
+
+    // 解构出来的 ..rest 是新数组
+    fn charge_bindings(&mut self, bindings: &mut [(&Ident, Object)]) -> Result<(), Object> {
+        bindings
+            .iter_mut()
+            .try_for_each(|(_, value)| self.charge(value))
+    }
+
+    // 表达式和语句每往下一层都看一次 栈用超了就报错 不等到进程崩掉
+    fn check_stack(&mut self) -> Option<Object> {
+        let base = self.stack_base?;
+        if base.abs_diff(stack_position()) <= self.max_stack {
+            return None;
+        }
+        let limit = self.max_stack;
+        Some(self.fail(RuntimeError::NativeStackOverflow { limit }))
+    }
+
+    fn fail(&mut self, e: RuntimeError) -> Object {
+        let obj = Object::Error(e.to_string());
+        self.fault = Some(e);
+        obj
+    }
+}
+
+/* ========== environment helpers ========== */
+
+fn lookup(env: &Env, ident: &Ident) -> Option<Object> {
+    let env = env.borrow();
+    match ident.1 {
+        Address::Local { depth, slot } => env.get_at(depth, slot, &ident.0),
+        Address::Global => env.get_global(&ident.0),
+        Address::Unresolved => env.get(&ident.0),
+    }
+}
+
+// 按模式拆开值 得到每个名字要绑定的值
+// 数组模式没有 ...rest 时长度必须一样 hash 模式的键必须存在 (模块也可以按名字拆)
+fn destructure<'p>(
+    pattern: &'p Pattern,
+    value: Object,
+    out: &mut Vec<(&'p Ident, Object)>,
+) -> Result<(), Object> {
+    let mismatch = |what: String| err(format!("cannot destructure {}: {}", pattern.string(), what));
+    match pattern {
+        Pattern::Ident(ident) => out.push((ident, value)),
+        Pattern::Wildcard => {}
+        Pattern::Literal(lit) => {
+            if !literal_matches(lit, &value) {
+                return Err(mismatch(format!(
+                    "expected {}, got {}",
+                    lit.string(),
+                    value.inspect()
+                )));
+            }
+        }
+        Pattern::Array { elements, rest } => {
+            let Object::Array(items) = &value else {
+                return Err(mismatch(format!(
+                    "expected Array, got {:?}",
+                    value.object_type()
+                )));
             };
+            let want = elements.len();
+            if rest.is_none() && items.len() != want {
+                return Err(mismatch(format!(
+                    "expected {} element(s), got {}",
+                    want,
+                    items.len()
+                )));
+            }
+            if items.len() < want {
+                return Err(mismatch(format!(
+                    "expected at least {} element(s), got {}",
+                    want,
+                    items.len()
+                )));
+            }
+            for (p, item) in elements.iter().zip(items.iter()) {
+                destructure(p, item.clone(), out)?;
+            }
+            if let Some(rest) = rest {
+                out.push((rest, Object::array(items[want..].to_vec())));
+            }
+        }
+        Pattern::Hash(pairs) => {
+            for (key, p) in pairs {
+                let item = match &value {
+                    Object::Hash(map) => map.get(&HashKey::string(key)).cloned(),
+                    Object::Module(module) => module.env.borrow().get(key),
+                    other => {
+                        return Err(mismatch(format!(
+                            "expected Hash, got {:?}",
+                            other.object_type()
+                        )));
+                    }
+                };
+                let Some(item) = item else {
+                    return Err(mismatch(format!("missing key {:?}", key)));
+                };
+                destructure(p, item, out)?;
+            }
+        }
+    }
+    Ok(())
+}
 
-            match chosen {
-                Statement::Block(b) => eval_statements(&b.statements),
-                other => eval_single_statement(other),
+// match 的模式: 和 destructure 一样拆值 但是不匹配时返回 false 而不是报错
+fn match_pattern<'p>(
+    pattern: &'p Pattern,
+    value: &Object,
+    out: &mut Vec<(&'p Ident, Object)>,
+) -> bool {
+    match pattern {
+        Pattern::Ident(ident) => {
+            out.push((ident, value.clone()));
+            true
+        }
+        Pattern::Wildcard => true,
+        Pattern::Literal(lit) => literal_matches(lit, value),
+        Pattern::Array { elements, rest } => {
+            let Object::Array(items) = value else {
+                return false;
+            };
+            let want = elements.len();
+            let len_ok = match rest {
+                Some(_) => items.len() >= want,
+                None => items.len() == want,
+            };
+            if !len_ok
+                || !elements
+                    .iter()
+                    .zip(items.iter())
+                    .all(|(p, item)| match_pattern(p, item, out))
+            {
+                return false;
             }
+            if let Some(rest) = rest {
+                out.push((rest, Object::array(items[want..].to_vec())));
+            }
+            true
         }
+        Pattern::Hash(pairs) => pairs.iter().all(|(key, p)| {
+            let item = match value {
+                Object::Hash(map) => map.get(&HashKey::string(key)).cloned(),
+                Object::Module(module) => module.env.borrow().get(key),
+                _ => None,
+            };
+            item.is_some_and(|item| match_pattern(p, &item, out))
+        }),
+    }
+}
+
+// 字面量模式只和同类型的相等值匹配 1 不匹配 true
+fn literal_matches(lit: &Expr, value: &Object) -> bool {
+    match (lit, value) {
+        (Expr::Integer(a), Object::Integer(b)) => a == b,
+        (Expr::Boolean(a), Object::Boolean(b)) => a == b,
+        (Expr::String(a), Object::String(b)) => **a == ***b,
+        _ => false,
+    }
+}
 
-        // 其它暂不支持
-        _ => EvalFlow::Value(Object::Null),
+fn bind_name(env: &mut Environment, name: &Ident, value: Object) {
+    match name.1 {
+        Address::Local { slot, .. } => env.set_slot(slot, value),
+        _ => env.set(&name.0, value),
     }
 }
 
 /* ========== helpers ========== */
 
-fn is_truthy(o: &Object) -> bool {
+// 模块源码和入口脚本一样 先过 parser 和 analyzer 有问题时报第一条
+fn parse_module(source: &str) -> Result<Program, String> {
+    let mut parser = Parser::new(Lexer::new(source));
+    let program = parser.parse_program();
+    if let Some(msg) = parser.errors().into_iter().next() {
+        return Err(msg);
+    }
+    let program = program.ok_or("failed to parse")?;
+    match analyzer::analyze(&program).into_iter().next() {
+        Some(d) => Err(d.to_string()),
+        None => Ok(program),
+    }
+}
+
+// 只有 false null 和 0 是假 其它值 (字符串 数组 hash 模块 各种函数) 都是真
+// 树遍历和vm共用 if ?: ! 和守卫都按这个判断
+pub(crate) fn is_truthy(o: &Object) -> bool {
     match o {
         Object::Boolean(b) => *b,
         Object::Integer(i) => *i != 0,
         Object::Null => false,
-        _ => {
-            // 理论上我们在进入 is_truthy 前就已拦截错误/其它类型
-            debug_assert!(
-                matches!(o, Object::Boolean(_) | Object::Integer(_) | Object::Null),
-                "is_truthy called with non-boolean/integer/null: {:?}",
-                o
-            );
-            false
-        }
+        Object::String(_)
+        | Object::Array(_)
+        | Object::Hash(_)
+        | Object::Module(_)
+        | Object::Function(_)
+        | Object::Builtin(_)
+        | Object::CompiledFunction(_)
+        | Object::Closure(_)
+        | Object::Error(_) => true,
+    }
+}
+
+// 前缀运算 树遍历和vm共用 保证两边报错一致
+pub(crate) fn eval_prefix_expression(op: &TokenType, right: Object) -> Object {
+    match op {
+        TokenType::Bang => Object::Boolean(!is_truthy(&right)),
+        TokenType::Minus => match right {
+            Object::Integer(i) => i.checked_neg().map_or_else(
+                || err(format!("integer overflow: -({})", i)),
+                Object::Integer,
+            ),
+            _ => err(format!("unknown operator: - {:?}", right)),
+        },
+        TokenType::Plus => match right {
+            Object::Integer(i) => Object::Integer(i),
+            _ => err(format!("unknown operator: + {:?}", right)),
+        },
+        _ => err(format!("unknown prefix operator: {}", op)),
     }
 }
 
-fn eval_infix_expression(op: &TokenType, left: Object, right: Object) -> Object {
+// 整数运算溢出报运行时错误 不回绕也不 panic (i64::MIN / -1 也算溢出)
+pub(crate) fn eval_infix_expression(op: &TokenType, left: Object, right: Object) -> Object {
     match (left, right) {
         (Object::Integer(l), Object::Integer(r)) => match *op {
-            TokenType::Plus => Object::Integer(l + r),
-            TokenType::Minus => Object::Integer(l - r),
-            TokenType::Asterisk => Object::Integer(l * r),
+            TokenType::Plus => checked(l.checked_add(r), l, op, r),
+            TokenType::Minus => checked(l.checked_sub(r), l, op, r),
+            TokenType::Asterisk => checked(l.checked_mul(r), l, op, r),
             TokenType::Slash => {
                 if r == 0 {
                     err("division by zero")
                 } else {
-                    Object::Integer(l / r)
+                    checked(l.checked_div(r), l, op, r)
                 }
             }
             TokenType::Eq => Object::Boolean(l == r),
@@ -238,7 +1327,196 @@ fn eval_infix_expression(op: &TokenType, left: Object, right: Object) -> Object
             _ => err(format!("unknown boolean operator: {}", op)),
         },
 
+        (Object::String(l), Object::String(r)) => match *op {
+            TokenType::Plus => Object::string(format!("{}{}", l.as_str(), r.as_str())),
+            TokenType::Eq => Object::Boolean(l == r),
+            TokenType::NotEq => Object::Boolean(l != r),
+            _ => err(format!("unknown string operator: {}", op)),
+        },
+
         // 注意这里绑定 (l, r) 才能在错误消息里使用
         (l, r) => err(format!("type mismatch: {:?} {} {:?}", l, op, r)),
     }
 }
+
+fn checked(result: Option<i64>, l: i64, op: &TokenType, r: i64) -> Object {
+    result.map_or_else(
+        || err(format!("integer overflow: {} {} {}", l, op, r)),
+        Object::Integer,
+    )
+}
+
+// 下标: 数组越界和 hash 里没有的键都得到 null 字符串按字符算
+fn eval_index_expression(left: &Object, index: &Object) -> Object {
+    match (left, index) {
+        (Object::String(s), Object::Integer(i)) => usize::try_from(*i)
+            .ok()
+            .and_then(|i| s.chars().nth(i))
+            .map_or(Object::Null, Object::string),
+        (Object::Array(elements), Object::Integer(i)) => usize::try_from(*i)
+            .ok()
+            .and_then(|i| elements.get(i))
+            .cloned()
+            .unwrap_or(Object::Null),
+        (Object::Hash(map), key) => match key.hash_key() {
+            Some(key) => map.get(&key).cloned().unwrap_or(Object::Null),
+            None => err(format!("unusable as hash key: {:?}", key.object_type())),
+        },
+        _ => err(format!(
+            "index operator not supported: {:?}[{:?}]",
+            left.object_type(),
+            index.object_type()
+        )),
+    }
+}
+
+// 赋值目标里的一层: xs[i] 的下标 或者 h.name 的名字
+enum PathKey {
+    Index(Object),
+    Member(Rc<str>),
+}
+
+// 当前 rust 栈的大概位置: 一个局部变量的地址
+#[inline(never)]
+fn stack_position() -> usize {
+    let marker = 0u8;
+    std::hint::black_box(&marker) as *const u8 as usize
+}
+
+// 负载大小变了 (或者还没记过) 就按现在的大小重新记
+// 刚建出来的容器里的元素可能也是新的 一起记上
+fn charge(
+    obj: &mut Object,
+    alloc: &mut dyn FnMut(usize) -> Result<Allocation, Object>,
+) -> Result<(), Object> {
+    let Some(bytes) = obj.payload_bytes() else {
+        return Ok(());
+    };
+    let Some(slot) = obj.charge_slot() else {
+        return Ok(());
+    };
+    if slot.as_ref().is_some_and(|c| c.bytes() == bytes) {
+        return Ok(());
+    }
+    let fresh = slot.take().is_none();
+    *slot = Some(alloc(bytes)?);
+    if fresh {
+        match obj {
+            Object::Array(elements) => {
+                for element in Rc::make_mut(elements).iter_mut() {
+                    charge(element, alloc)?;
+                }
+            }
+            Object::Hash(map) => {
+                for value in Rc::make_mut(map).values_mut() {
+                    charge(value, alloc)?;
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+// 沿着 path 一层层往里走 在最里面写入新值
+// 数组和 hash 用 Rc::make_mut: 没有被别处共享时原地修改 共享时先复制一份 (copy-on-write)
+// 所以 let b = a; b[0] = 1; 不会改到 a
+// 改完以后容器可能变大了 (hash 插了新键) 按新的大小重新记账
+fn assign_path(
+    target: &mut Object,
+    path: &[PathKey],
+    op: TokenType,
+    value: Object,
+    alloc: &mut dyn FnMut(usize) -> Result<Allocation, Object>,
+) -> Result<Object, Object> {
+    let value = assign_step(target, path, op, value, alloc)?;
+    charge(target, alloc)?;
+    Ok(value)
+}
+
+fn assign_step(
+    target: &mut Object,
+    path: &[PathKey],
+    op: TokenType,
+    value: Object,
+    alloc: &mut dyn FnMut(usize) -> Result<Allocation, Object>,
+) -> Result<Object, Object> {
+    let Some((key, rest)) = path.split_first() else {
+        let value = match op.compound_op() {
+            Some(op) => eval_infix_expression(&op, target.clone(), value),
+            None => value,
+        };
+        if is_error(&value) {
+            return Err(value);
+        }
+        *target = value.clone();
+        return Ok(value);
+    };
+
+    match (target, key) {
+        (Object::Array(elements), PathKey::Index(Object::Integer(i))) => {
+            match usize::try_from(*i).ok().filter(|&i| i < elements.len()) {
+                Some(i) => assign_path(&mut Rc::make_mut(elements)[i], rest, op, value, alloc),
+                None => Err(err(format!(
+                    "index out of range: {} (length {})",
+                    i,
+                    elements.len()
+                ))),
+            }
+        }
+        (Object::Hash(map), key) => {
+            let hash_key = match key {
+                PathKey::Index(index) => index.hash_key().ok_or_else(|| {
+                    err(format!("unusable as hash key: {:?}", index.object_type()))
+                })?,
+                PathKey::Member(name) => HashKey::string(name),
+            };
+            if rest.is_empty() && op == TokenType::Assign {
+                Rc::make_mut(map).insert(hash_key, value.clone());
+                return Ok(value);
+            }
+            // 没有的键读出来是 null 在 null 上做运算或者继续下标都会报错 不会插入新键
+            match map.contains_key(&hash_key) {
+                true => {
+                    let slot = Rc::make_mut(map).get_mut(&hash_key).expect("key exists");
+                    assign_path(slot, rest, op, value, alloc)
+                }
+                false => assign_path(&mut Object::Null, rest, op, value, alloc),
+            }
+        }
+        (Object::Module(module), PathKey::Member(name)) => Err(err(format!(
+            "cannot assign to module member {}.{}",
+            module.path, name
+        ))),
+        (target, PathKey::Index(index)) => Err(err(format!(
+            "index assignment not supported: {:?}[{:?}]",
+            target.object_type(),
+            index.object_type()
+        ))),
+        (target, PathKey::Member(name)) => Err(err(format!(
+            "member assignment not supported: {:?}.{}",
+            target.object_type(),
+            name
+        ))),
+    }
+}
+
+// 成员: 模块里没有的名字是错误 hash 里没有的键和下标一样得到 null
+fn eval_member_expression(object: &Object, name: &Rc<str>) -> Object {
+    match object {
+        Object::Module(module) => module
+            .env
+            .borrow()
+            .get(name)
+            .unwrap_or_else(|| err(format!("module {} has no member {}", module.path, name))),
+        Object::Hash(map) => map
+            .get(&HashKey::string(name))
+            .cloned()
+            .unwrap_or(Object::Null),
+        _ => err(format!(
+            "member access not supported: {:?}.{}",
+            object.object_type(),
+            name
+        )),
+    }
+}
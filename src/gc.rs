@@ -0,0 +1,199 @@
+use std::{
+    cell::RefCell,
+    rc::{Rc, Weak},
+};
+
+use ahash::AHashMap as HashMap;
+
+use crate::{environment::Environment, object::Object};
+
+// 登记的环境达到这么多个才考虑回收
+const MIN_THRESHOLD: usize = 1024;
+
+type Env = Rc<RefCell<Environment>>;
+
+// 函数调用环境的循环回收器 (trial deletion)
+// 闭包存在自己定义时的环境里 (直接放或者放在数组 hash 里)
+// 环境 -> 闭包 -> 环境 形成 Rc 循环 永远不会被释放
+// 回收时数一数每个环境/闭包被图里其它节点引用了几次 Rc 计数比这个多说明外面
+// (全局环境 rust 栈上正在用的值) 还拿着它 从这些根出发标记 标记不到的就是只在
+// 循环里互相引用的垃圾 清空它们的槽位把循环拆开 剩下的交给 Rc 释放
+#[derive(Debug)]
+pub struct Collector {
+    envs: Vec<Weak<RefCell<Environment>>>,
+    threshold: usize,
+}
+
+impl Default for Collector {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// 图里的一个值 (闭包 数组 hash): 被图里引用的次数 Rc 计数 闭包捕获的环境 容器里的值
+struct Node {
+    seen: usize,
+    strong: usize,
+    env: Option<usize>,
+    children: Vec<*const ()>,
+    marked: bool,
+}
+
+type Index = HashMap<*const RefCell<Environment>, usize>;
+
+// 把一个值记进图里 返回它的节点 第一次见到的数组和 hash 放进 todo 稍后展开
+fn visit<'a>(
+    value: &'a Object,
+    index: &Index,
+    values: &mut HashMap<*const (), Node>,
+    todo: &mut Vec<(*const (), &'a Object)>,
+) -> Option<*const ()> {
+    let (ptr, strong, env) = match value {
+        Object::Function(func) => (
+            Rc::as_ptr(func) as *const (),
+            Rc::strong_count(func),
+            index.get(&Rc::as_ptr(&func.env)).copied(),
+        ),
+        Object::Array(elements) => (
+            Rc::as_ptr(elements) as *const (),
+            Rc::strong_count(elements),
+            None,
+        ),
+        Object::Hash(map) => (Rc::as_ptr(map) as *const (), Rc::strong_count(map), None),
+        _ => return None,
+    };
+    let node = values.entry(ptr).or_insert_with(|| {
+        if !matches!(value, Object::Function(_)) {
+            todo.push((ptr, value));
+        }
+        Node {
+            seen: 0,
+            strong,
+            env,
+            children: Vec::new(),
+            marked: false,
+        }
+    });
+    node.seen += 1;
+    Some(ptr)
+}
+
+impl Collector {
+    pub fn new() -> Self {
+        Collector {
+            envs: Vec::new(),
+            threshold: MIN_THRESHOLD,
+        }
+    }
+
+    // 登记一个新建的调用环境
+    pub fn track(&mut self, env: &Env) {
+        self.envs.push(Rc::downgrade(env));
+    }
+
+    pub fn should_collect(&self) -> bool {
+        self.envs.len() >= self.threshold
+    }
+
+    // 还登记着的环境个数 (包括已经释放 还没清理的)
+    pub fn tracked(&self) -> usize {
+        self.envs.len()
+    }
+
+    // 回收一次 返回拆掉的环境个数
+    // 调用时不能有任何环境正被借用 否则这次什么都不做
+    pub fn collect(&mut self) -> usize {
+        let nodes: Vec<Env> = self.envs.iter().filter_map(Weak::upgrade).collect();
+        let index: Index = nodes
+            .iter()
+            .enumerate()
+            .map(|(i, env)| (Rc::as_ptr(env), i))
+            .collect();
+
+        // 每个环境被图里引用的次数 和它指向的节点
+        // 闭包可能直接放在环境里 也可能放在数组 hash 里 (let xs = [fn(x) { xs }])
+        let mut internal = vec![0usize; nodes.len()];
+        let mut outers: Vec<Option<usize>> = vec![None; nodes.len()];
+        let mut contents: Vec<Vec<*const ()>> = vec![Vec::new(); nodes.len()];
+        let mut values: HashMap<*const (), Node> = HashMap::new();
+
+        for (i, env) in nodes.iter().enumerate() {
+            let Ok(env) = env.try_borrow() else {
+                return 0;
+            };
+            if let Some(outer) = env.outer()
+                && let Some(&j) = index.get(&Rc::as_ptr(outer))
+            {
+                internal[j] += 1;
+                outers[i] = Some(j);
+            }
+            let mut todo = Vec::new();
+            for value in env.values() {
+                contents[i].extend(visit(value, &index, &mut values, &mut todo));
+            }
+            while let Some((ptr, container)) = todo.pop() {
+                let children: Vec<*const ()> = match container {
+                    Object::Array(elements) => elements
+                        .iter()
+                        .filter_map(|v| visit(v, &index, &mut values, &mut todo))
+                        .collect(),
+                    Object::Hash(map) => map
+                        .values()
+                        .filter_map(|v| visit(v, &index, &mut values, &mut todo))
+                        .collect(),
+                    _ => unreachable!("only arrays and hashes are expanded"),
+                };
+                values.get_mut(&ptr).expect("visited").children = children;
+            }
+        }
+        for node in values.values() {
+            if let Some(j) = node.env {
+                internal[j] += 1;
+            }
+        }
+
+        // 根: 图外面还有引用的环境和值 (nodes 自己拿着每个环境一次)
+        let mut marked = vec![false; nodes.len()];
+        let mut work: Vec<usize> = (0..nodes.len())
+            .filter(|&i| Rc::strong_count(&nodes[i]) - 1 > internal[i])
+            .collect();
+        let mut reach: Vec<*const ()> = values
+            .iter()
+            .filter(|(_, node)| node.strong > node.seen)
+            .map(|(&ptr, _)| ptr)
+            .collect();
+        loop {
+            while let Some(ptr) = reach.pop() {
+                let node = values.get_mut(&ptr).expect("visited");
+                if std::mem::replace(&mut node.marked, true) {
+                    continue;
+                }
+                work.extend(node.env);
+                reach.extend(node.children.iter().copied());
+            }
+            let Some(i) = work.pop() else {
+                break;
+            };
+            if std::mem::replace(&mut marked[i], true) {
+                continue;
+            }
+            work.extend(outers[i]);
+            reach.extend(contents[i].iter().copied());
+        }
+
+        // 没标记到的都是垃圾 先把引用拿出来 借用结束后再 drop
+        let mut garbage = Vec::new();
+        for (i, env) in nodes.iter().enumerate() {
+            if !marked[i] {
+                garbage.push(env.borrow_mut().take_references());
+            }
+        }
+        let freed = garbage.len();
+        drop(garbage);
+        drop(nodes);
+
+        self.envs.retain(|env| env.strong_count() > 0);
+        self.threshold = (self.envs.len() * 2).max(MIN_THRESHOLD);
+        freed
+    }
+}
@@ -0,0 +1,90 @@
+use std::{cell::Cell, rc::Rc};
+
+// 运行时堆的记账: 统计还活着的 Object 负载 (闭包 调用环境) 占了多少字节
+// 每次分配拿到一个 Allocation 它被 drop 的时候把字节还回来
+#[derive(Debug, Default)]
+pub struct Heap {
+    used: Cell<usize>,
+    peak: Cell<usize>,
+    limit: Cell<Option<usize>>,
+}
+
+// 给宿主看的用量快照
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MemoryUsage {
+    pub used: usize,
+    pub peak: usize,
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug)]
+pub struct Allocation {
+    heap: Rc<Heap>,
+    bytes: usize,
+}
+
+impl Heap {
+    pub fn new() -> Rc<Heap> {
+        Rc::new(Heap::default())
+    }
+
+    pub fn set_limit(&self, limit: Option<usize>) {
+        self.limit.set(limit);
+    }
+
+    pub fn limit(&self) -> Option<usize> {
+        self.limit.get()
+    }
+
+    // 超过上限时返回 None 什么都不记
+    pub fn alloc(self: &Rc<Self>, bytes: usize) -> Option<Allocation> {
+        let used = self.used.get().checked_add(bytes)?;
+        if let Some(limit) = self.limit.get()
+            && used > limit
+        {
+            return None;
+        }
+        self.charge(bytes);
+        Some(Allocation {
+            heap: self.clone(),
+            bytes,
+        })
+    }
+
+    pub fn usage(&self) -> MemoryUsage {
+        MemoryUsage {
+            used: self.used.get(),
+            peak: self.peak.get(),
+            limit: self.limit.get(),
+        }
+    }
+
+    fn charge(&self, bytes: usize) {
+        let used = self.used.get() + bytes;
+        self.used.set(used);
+        self.peak.set(self.peak.get().max(used));
+    }
+}
+
+impl Allocation {
+    pub fn bytes(&self) -> usize {
+        self.bytes
+    }
+}
+
+// 复制一份负载就再记一次 (不检查上限 已经分配出去的东西不能失败)
+impl Clone for Allocation {
+    fn clone(&self) -> Self {
+        self.heap.charge(self.bytes);
+        Allocation {
+            heap: self.heap.clone(),
+            bytes: self.bytes,
+        }
+    }
+}
+
+impl Drop for Allocation {
+    fn drop(&mut self) {
+        self.heap.used.set(self.heap.used.get() - self.bytes);
+    }
+}
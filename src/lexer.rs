@@ -1,185 +1,259 @@
-use std::char;
-
-use crate::token::{Token, TokenType, lookup_ident};
+use crate::token::{Position, Token, TokenType, lookup_ident};
 
+// Lexer 直接借用源码 所有token的literal都是input上的切片
 #[derive(Debug, Clone)]
-pub struct Lexer {
-    pub input: String,
+pub struct Lexer<'src> {
+    pub input: &'src str,
     pub position: usize,
     pub read_position: usize,
     pub ch: u8,
+    // 当前行号 和当前行第一个字节的位置 用来算列号
+    line: usize,
+    line_start: usize,
+    // 最近一次 next_token 返回的token的起始位置
+    token_position: Position,
 }
 
-impl Lexer {
+impl<'src> Lexer<'src> {
     // new lexer
-    pub fn new(input: &str) -> Lexer {
+    pub fn new(input: &'src str) -> Lexer<'src> {
         let mut l = Lexer {
-            input: input.to_string(),
+            input,
             position: 0,
             read_position: 0,
             ch: 0,
+            line: 1,
+            line_start: 0,
+            token_position: Position::default(),
         };
         l.read_char();
-        return l;
+        l
     }
 
     // 逐渐读取
     pub fn read_char(&mut self) {
-        if self.read_position >= self.input.len() {
-            self.ch = 0;
-        } else {
-            if let Some(x) = self.input.as_bytes().get(self.read_position) {
-                self.ch = *x;
-            }
+        // 跨过换行 行号加一
+        if self.ch == b'\n' {
+            self.line += 1;
+            self.line_start = self.read_position;
         }
+        self.ch = self
+            .input
+            .as_bytes()
+            .get(self.read_position)
+            .copied()
+            .unwrap_or(0);
         self.position = self.read_position;
         self.read_position += 1;
     }
 
     // 提前读取
-    pub fn peek_char(&mut self) -> u8 {
-        if self.read_position >= self.input.len() {
-            return 0;
+    pub fn peek_char(&self) -> u8 {
+        self.input
+            .as_bytes()
+            .get(self.read_position)
+            .copied()
+            .unwrap_or(0)
+    }
+
+    // 截取 input[start..end] 作为token的literal
+    #[inline(always)]
+    fn slice(&self, start: usize, end: usize) -> &'src str {
+        &self.input[start..end]
+    }
+
+    // 单字符token: 直接切片 不分配
+    #[inline(always)]
+    fn single(&self, token_type: TokenType) -> Token<'src> {
+        Token::new(token_type, self.slice(self.position, self.read_position))
+    }
+
+    // 双字符token (== != <= >= += ...): 如果下一个字符是expect 就吞掉它
+    fn either(&mut self, expect: u8, double: TokenType, single: TokenType) -> Token<'src> {
+        if self.peek_char() == expect {
+            let start = self.position;
+            self.read_char();
+            Token::new(double, self.slice(start, self.read_position))
         } else {
-            let mut ch: u8 = 0;
-            if let Some(x) = self.input.as_bytes().get(self.read_position) {
-                ch = *x
-            }
-            return ch;
+            self.single(single)
         }
     }
 
-    pub fn next_token(&mut self) -> Token {
-        // 默认初始化 EOF
-        let mut token = Token::new_with_char(TokenType::Eof, '\0');
+    // 最近一个token在源码中的位置
+    pub fn token_position(&self) -> Position {
+        self.token_position
+    }
 
+    pub fn next_token(&mut self) -> Token<'src> {
         self.skip_whitespace();
+        self.token_position = Position {
+            line: self.line,
+            column: self.position - self.line_start + 1,
+        };
 
-        match self.ch as char {
-            '=' => {
-                if self.peek_char() == '=' as u8 {
-                    let ch = self.ch;
-                    self.read_char();
-                    token = Token::new_with_string(
-                        TokenType::Eq,
-                        format!("{}{}", ch as char, self.ch as char),
-                    );
-                } else {
-                    token = Token::new_with_char(TokenType::Assign, self.ch as char);
-                }
+        let token = match self.ch {
+            b'=' if self.peek_char() == b'>' => {
+                self.either(b'>', TokenType::FatArrow, TokenType::Assign)
             }
-            ';' => token = Token::new_with_char(TokenType::Semicolon, self.ch as char),
-            '(' => token = Token::new_with_char(TokenType::Lparen, self.ch as char),
-            ')' => token = Token::new_with_char(TokenType::Rparen, self.ch as char),
-            '{' => token = Token::new_with_char(TokenType::Lbrace, self.ch as char),
-            '}' => token = Token::new_with_char(TokenType::Rbrace, self.ch as char),
-            ',' => token = Token::new_with_char(TokenType::Comma, self.ch as char),
-            '+' => token = Token::new_with_char(TokenType::Plus, self.ch as char),
-            '-' => token = Token::new_with_char(TokenType::Minus, self.ch as char),
-            '/' => token = Token::new_with_char(TokenType::Slash, self.ch as char),
-            '*' => token = Token::new_with_char(TokenType::Asterisk, self.ch as char),
-            '<' => {
-                if self.peek_char() == '=' as u8 {
-                    let ch = self.ch;
-                    self.read_char();
-                    token = Token::new_with_string(
-                        TokenType::Le,
-                        format!("{}{}", ch as char, self.ch as char),
-                    )
-                } else {
-                    token = Token::new_with_char(TokenType::Lt, self.ch as char)
-                }
+            b'=' => self.either(b'=', TokenType::Eq, TokenType::Assign),
+            b';' => self.single(TokenType::Semicolon),
+            b'(' => self.single(TokenType::Lparen),
+            b')' => self.single(TokenType::Rparen),
+            b'{' => self.single(TokenType::Lbrace),
+            b'}' => self.single(TokenType::Rbrace),
+            b'[' => self.single(TokenType::Lbracket),
+            b']' => self.single(TokenType::Rbracket),
+            b'"' => Token::new(TokenType::String, self.read_string()),
+            b',' => self.single(TokenType::Comma),
+            b':' => self.single(TokenType::Colon),
+            b'.' if self.input[self.position..].starts_with("...") => {
+                let start = self.position;
+                self.read_char();
+                self.read_char();
+                Token::new(TokenType::Ellipsis, self.slice(start, self.read_position))
             }
-            '>' => {
-                if self.peek_char() == '=' as u8 {
-                    let ch = self.ch;
-                    self.read_char();
-                    token = Token::new_with_string(
-                        TokenType::Ge,
-                        format!("{}{}", ch as char, self.ch as char),
-                    )
-                } else {
-                    token = Token::new_with_char(TokenType::Gt, self.ch as char)
-                }
-            }
-            '!' => {
-                if self.peek_char() == '=' as u8 {
-                    let ch = self.ch;
-                    self.read_char();
-                    token = Token::new_with_string(
-                        TokenType::NotEq,
-                        format!("{}{}", ch as char, self.ch as char),
-                    )
-                } else {
-                    token = Token::new_with_char(TokenType::Bang, self.ch as char)
-                }
-            }
-            '\0' => {
-                token.literal = "".to_string();
-                token.token_type = TokenType::Eof;
+            b'.' => self.single(TokenType::Dot),
+            b'+' => self.either(b'=', TokenType::PlusAssign, TokenType::Plus),
+            b'-' if self.peek_char() == b'=' => {
+                self.either(b'=', TokenType::MinusAssign, TokenType::Minus)
             }
+            b'-' => self.either(b'>', TokenType::Arrow, TokenType::Minus),
+            b'/' => self.either(b'=', TokenType::SlashAssign, TokenType::Slash),
+            b'*' => self.either(b'=', TokenType::AsteriskAssign, TokenType::Asterisk),
+            b'<' => self.either(b'=', TokenType::Le, TokenType::Lt),
+            b'>' => self.either(b'=', TokenType::Ge, TokenType::Gt),
+            b'!' => self.either(b'=', TokenType::NotEq, TokenType::Bang),
+            // ?[ 要紧挨着写 c ? [1] : [2] 里的 ? 和 [ 之间要有空格
+            b'?' => match self.peek_char() {
+                b'?' => self.either(b'?', TokenType::NullCoalesce, TokenType::Question),
+                b'.' => self.either(b'.', TokenType::OptionalDot, TokenType::Question),
+                b'[' => self.either(b'[', TokenType::OptionalBracket, TokenType::Question),
+                _ => self.single(TokenType::Question),
+            },
+            0 => return Token::new(TokenType::Eof, ""),
             _ => {
                 if is_letter(self.ch) {
-                    token.literal = self.read_identifier();
-                    token.token_type = lookup_ident(token.literal.clone());
-                    return token;
+                    let literal = self.read_identifier();
+                    return Token::new(lookup_ident(literal), literal);
                 } else if is_digital(self.ch) {
-                    token.token_type = TokenType::Int;
-                    token.literal = self.read_number();
-                    return token;
+                    return Token::new(TokenType::Int, self.read_number());
                 } else {
-                    token = Token::new_with_char(TokenType::Illegal, self.ch as char)
+                    self.illegal()
                 }
             }
-        }
+        };
         self.read_char();
-        return token;
+        token
+    }
+
+    // 非法字符: 按完整的UTF-8字符切片 防止切在多字节字符中间
+    fn illegal(&mut self) -> Token<'src> {
+        let start = self.position;
+        let width = self.input[start..]
+            .chars()
+            .next()
+            .map_or(1, |c| c.len_utf8());
+        // 跳过续字节 最后一个字节交给next_token里的read_char
+        for _ in 1..width {
+            self.read_char();
+        }
+        Token::new(TokenType::Illegal, self.slice(start, start + width))
     }
 
-    pub fn read_identifier(&mut self) -> String {
+    pub fn read_identifier(&mut self) -> &'src str {
         let position = self.position;
         while is_letter(self.ch) {
             self.read_char();
         }
-        return self.input.get(position..self.position).unwrap().to_string();
+        self.slice(position, self.position)
     }
 
-    pub fn read_number(&mut self) -> String {
+    pub fn read_number(&mut self) -> &'src str {
         let position = self.position;
         while is_digital(self.ch) {
             self.read_char();
         }
-        return self.input.get(position..self.position).unwrap().to_string();
+        self.slice(position, self.position)
+    }
+
+    // 字符串: literal 是两个引号之间的原始内容 转义和 ${...} 插值由 parser 处理
+    // 停在右引号上 (没有右引号就停在结尾) 交给 next_token 里的 read_char 跳过
+    pub fn read_string(&mut self) -> &'src str {
+        let position = self.position + 1;
+        loop {
+            self.read_char();
+            match self.ch {
+                b'"' | 0 => break,
+                // 转义的引号不结束字符串
+                b'\\' if self.peek_char() != 0 => self.read_char(),
+                // ${...} 里面是代码 里面的引号不结束外面的字符串
+                b'$' if self.peek_char() == b'{' => {
+                    self.read_char();
+                    if !self.skip_interpolation() {
+                        break;
+                    }
+                }
+                _ => {}
+            }
+        }
+        self.slice(position, self.position)
+    }
+
+    // 当前在 ${ 的 { 上 跳到配对的 } 上 里面可以有花括号和字符串
+    // 没有配对的 } 时停在结尾 返回 false
+    fn skip_interpolation(&mut self) -> bool {
+        let mut depth = 1;
+        loop {
+            self.read_char();
+            match self.ch {
+                0 => return false,
+                b'{' => depth += 1,
+                b'}' => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return true;
+                    }
+                }
+                b'"' => {
+                    self.read_string();
+                    if self.ch == 0 {
+                        return false;
+                    }
+                }
+                _ => {}
+            }
+        }
     }
 
     pub fn skip_whitespace(&mut self) {
-        while self.ch as char == ' '
-            || self.ch as char == '\t'
-            || self.ch as char == '\n'
-            || self.ch as char == '\r'
-        {
+        while matches!(self.ch, b' ' | b'\t' | b'\n' | b'\r') {
             self.read_char();
         }
     }
 }
 
+// src 以 ${ 开头 返回到配对的 } 为止 (包括它) 的字节数 没有配对的 } 返回 None
+pub fn interpolation_len(src: &str) -> Option<usize> {
+    let mut l = Lexer::new(src);
+    l.read_char();
+    l.skip_interpolation().then_some(l.position + 1)
+}
+
 // 判断是不是字母
 #[inline(always)]
 pub fn is_letter(ch: u8) -> bool {
-    return 'a' <= ch as char && ch as char <= 'z'
-        || 'A' <= ch as char && ch as char <= 'Z'
-        || ch as char == '_';
+    ch.is_ascii_alphabetic() || ch == b'_'
 }
 
 // 判断是不是数字
 #[inline(always)]
 pub fn is_digital(ch: u8) -> bool {
-    return '0' as u8 <= ch && ch <= '9' as u8;
+    ch.is_ascii_digit()
 }
 
 // 为lexer 实现迭代器
-impl Iterator for Lexer {
-    type Item = Token;
+impl<'src> Iterator for Lexer<'src> {
+    type Item = Token<'src>;
 
     fn next(&mut self) -> Option<Self::Item> {
         let tok = self.next_token();
@@ -1,8 +1,21 @@
+pub mod analyzer;
+pub mod artifact;
 pub mod ast;
+pub mod builtins;
+pub mod code;
+pub mod compiler;
 pub mod environment;
 pub mod evaluator;
+pub mod gc;
+pub mod heap;
 pub mod lexer;
 pub mod object;
+pub mod optimizer;
 pub mod parser;
 pub mod repl;
+pub mod resolver;
+pub mod stdlib;
 pub mod token;
+pub mod typechecker;
+pub mod types;
+pub mod vm;
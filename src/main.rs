@@ -1,7 +1,271 @@
-use monkeycc::repl;
+use std::{fs, process};
+
+use monkeycc::{
+    analyzer::{self, Analyzer},
+    artifact,
+    ast::Program,
+    compiler::{Bytecode, Compiler},
+    evaluator::Interpreter,
+    lexer::Lexer,
+    object::Object,
+    optimizer,
+    parser::Parser,
+    repl, typechecker,
+    vm::{self, Vm},
+};
+
+const USAGE: &str = "usage:
+    monkeycc                                     start the REPL
+    monkeycc run [-O] [--engine=tree|vm] [--strict-types] [--no-prelude] <file>
+                                                 run a script or a .mkc artifact
+    monkeycc build [-O] <file> [-o <out.mkc>]    precompile a script to bytecode
+    monkeycc disasm [-O] <file>                  print the bytecode of a script or artifact
+    monkeycc check <file>                        infer types and report mismatches without running
+
+options:
+    -O                fold constant expressions and prune constant if branches
+    --strict-types    check type annotations at runtime (tree engine only)
+    --no-prelude      do not load map, filter, sort, ... from the standard library";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Engine {
+    Tree,
+    Vm,
+}
+
+// 解析和求值都是递归的 放在一个栈大小确定的线程上跑 求值能用的栈按它来定
+// debug 构建下每层调用大约要 20KB 48MB 够 DEFAULT_MAX_DEPTH 层还有富余
+const STACK_SIZE: usize = 64 << 20;
+const EVAL_STACK: usize = 48 << 20;
 
 fn main() {
-    println!("Hello! This is the Monkey programming language!");
-    println!("Feel free to type");
-    repl::start();
+    let worker = std::thread::Builder::new()
+        .stack_size(STACK_SIZE)
+        .spawn(cli)
+        .expect("failed to spawn the interpreter thread");
+    if worker.join().is_err() {
+        process::exit(101);
+    }
+}
+
+fn cli() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+
+    match args.first().map(String::as_str) {
+        None => {
+            println!("Hello! This is the Monkey programming language!");
+            println!("Feel free to type");
+            repl::start(Interpreter::new().max_stack(EVAL_STACK));
+        }
+        Some("run") => run(&args[1..]),
+        Some("build") => build(&args[1..]),
+        Some("disasm") => disasm(&args[1..]),
+        Some("check") => check(&args[1..]),
+        Some("-h" | "--help" | "help") => println!("{}", USAGE),
+        Some(other) => fail(&format!("unknown command: {}\n{}", other, USAGE)),
+    }
+}
+
+// monkeycc run [--engine=tree|vm] [--strict-types] [--no-prelude] <file>
+fn run(args: &[String]) {
+    let mut engine = None;
+    let mut optimize = false;
+    let mut strict_types = false;
+    let mut prelude = true;
+    let mut path = None;
+
+    for arg in args {
+        match arg.as_str() {
+            "-O" => optimize = true,
+            "--strict-types" => strict_types = true,
+            "--no-prelude" => prelude = false,
+            "--engine=tree" => engine = Some(Engine::Tree),
+            "--engine=vm" => engine = Some(Engine::Vm),
+            flag if flag.starts_with('-') => fail(&format!("unknown option: {}\n{}", flag, USAGE)),
+            file => path = Some(file),
+        }
+    }
+    let Some(path) = path else {
+        fail(USAGE);
+    };
+    // 类型注解只有树遍历解释器会检查
+    if strict_types && engine == Some(Engine::Vm) {
+        fail("--strict-types needs --engine=tree");
+    }
+
+    // vm 没有 prelude
+    let prelude = prelude && engine != Some(Engine::Vm);
+    let result = match load(path, optimize, prelude) {
+        // 预编译产物只能交给 vm
+        Input::Artifact(_) if engine == Some(Engine::Tree) => fail(&format!(
+            "{} is a .mkc artifact and needs --engine=vm",
+            path
+        )),
+        Input::Artifact(_) if strict_types => fail(&format!(
+            "{} is a .mkc artifact and cannot run with --strict-types",
+            path
+        )),
+        Input::Artifact(bytecode) => Vm::new(bytecode).run(),
+        Input::Source(program) => match engine.unwrap_or(Engine::Tree) {
+            Engine::Tree => Interpreter::new()
+                .max_stack(EVAL_STACK)
+                .strict_types(strict_types)
+                .prelude(prelude)
+                .script_path(path)
+                .eval(&program),
+            Engine::Vm => vm::run(&program),
+        },
+    };
+    match result {
+        Object::Error(msg) => fail(&format!("runtime error: {}", msg)),
+        Object::Null => {}
+        value => println!("{}", value.inspect()),
+    }
+}
+
+// monkeycc build <file> [-o <out.mkc>]
+fn build(args: &[String]) {
+    let mut path = None;
+    let mut out = None;
+    let mut optimize = false;
+
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "-O" => optimize = true,
+            "-o" => match iter.next() {
+                Some(o) => out = Some(o.clone()),
+                None => fail("-o needs an output path"),
+            },
+            flag if flag.starts_with('-') => fail(&format!("unknown option: {}\n{}", flag, USAGE)),
+            file => path = Some(file),
+        }
+    }
+    let Some(path) = path else {
+        fail(USAGE);
+    };
+    // 默认输出: 同名 .mkc
+    let out = out.unwrap_or_else(|| {
+        let stem = path.strip_suffix(".monkey").unwrap_or(path);
+        format!("{}.mkc", stem)
+    });
+
+    let bytes = artifact::encode(&compile(&parse_file(path, optimize)))
+        .unwrap_or_else(|e| fail(&format!("cannot build {}: {}", path, e)));
+    fs::write(&out, bytes).unwrap_or_else(|e| fail(&format!("cannot write {}: {}", out, e)));
+}
+
+// monkeycc disasm <file>
+fn disasm(args: &[String]) {
+    let (optimize, path) = match args {
+        [path] => (false, path),
+        [flag, path] if flag == "-O" => (true, path),
+        _ => fail(USAGE),
+    };
+    let bytecode = match load(path, optimize, false) {
+        Input::Artifact(bytecode) => bytecode,
+        Input::Source(program) => compile(&program),
+    };
+    print!("{}", bytecode.disassemble());
+}
+
+// monkeycc check <file>
+fn check(args: &[String]) {
+    let [path] = args else {
+        fail(USAGE);
+    };
+    let source =
+        fs::read_to_string(path).unwrap_or_else(|e| fail(&format!("cannot read {}: {}", path, e)));
+    let (program, resolve_errors) = parse_program(path, &source);
+
+    let mut diagnostics = analyzer::analyze(&program);
+    diagnostics.extend(typechecker::check(&program));
+    diagnostics.sort_by_key(|d| d.pos);
+    let count = resolve_errors.len() + diagnostics.len();
+    if count > 0 {
+        // resolver 的错误没有位置 放在前面
+        for msg in &resolve_errors {
+            eprintln!("{}: {}", path, msg);
+        }
+        for d in &diagnostics {
+            eprintln!("{}:{}", path, d);
+        }
+        fail(&format!("{} has {} error(s)", path, count));
+    }
+}
+
+/* ========== helpers ========== */
+
+enum Input {
+    Source(Program),
+    Artifact(Bytecode),
+}
+
+// 根据文件头判断是源码还是 .mkc
+fn load(path: &str, optimize: bool, prelude: bool) -> Input {
+    let bytes = fs::read(path).unwrap_or_else(|e| fail(&format!("cannot read {}: {}", path, e)));
+    if artifact::is_artifact(&bytes) {
+        let bytecode = artifact::decode(&bytes)
+            .unwrap_or_else(|e| fail(&format!("cannot load {}: {}", path, e)));
+        return Input::Artifact(bytecode);
+    }
+    let source =
+        String::from_utf8(bytes).unwrap_or_else(|_| fail(&format!("{} is not valid utf-8", path)));
+    Input::Source(parse_source(path, &source, optimize, prelude))
+}
+
+fn parse_file(path: &str, optimize: bool) -> Program {
+    let source =
+        fs::read_to_string(path).unwrap_or_else(|e| fail(&format!("cannot read {}: {}", path, e)));
+    parse_source(path, &source, optimize, false)
+}
+
+// prelude: 运行时会不会加载标准库 决定 map filter 这些名字算不算已定义
+fn parse_source(path: &str, source: &str, optimize: bool, prelude: bool) -> Program {
+    let (program, resolve_errors) = parse_program(path, source);
+    // 静态检查 运行前就报出未定义的名字 参数个数不对之类的错误
+    let diagnostics = Analyzer::new().prelude(prelude).analyze(&program);
+    let count = resolve_errors.len() + diagnostics.len();
+    if count > 0 {
+        for msg in &resolve_errors {
+            eprintln!("\t{}", msg);
+        }
+        for d in &diagnostics {
+            eprintln!("\t{}", d);
+        }
+        fail(&format!("{} has {} error(s)", path, count));
+    }
+    if optimize {
+        optimizer::optimize(program)
+    } else {
+        program
+    }
+}
+
+// 只有语法错误算解析失败 resolver 的错误 (给常量赋值) 交给调用方和静态检查一起报
+fn parse_program(path: &str, source: &str) -> (Program, Vec<String>) {
+    let mut parser = Parser::new(Lexer::new(source));
+    let program = parser.parse_program();
+    let errors = parser.syntax_errors();
+    if !errors.is_empty() {
+        for msg in errors {
+            eprintln!("\t{}", msg);
+        }
+        fail(&format!("failed to parse {}", path));
+    }
+    let program = program.unwrap_or_else(|| fail(&format!("failed to parse {}", path)));
+    (program, parser.resolve_errors())
+}
+
+fn compile(program: &Program) -> Bytecode {
+    let mut compiler = Compiler::new();
+    compiler
+        .compile(program)
+        .unwrap_or_else(|e| fail(&format!("compile error: {}", e)));
+    compiler.bytecode()
+}
+
+fn fail(msg: &str) -> ! {
+    eprintln!("{}", msg);
+    process::exit(1);
 }
@@ -1,23 +1,92 @@
+use std::{
+    cell::RefCell,
+    cmp::Ordering,
+    collections::BTreeMap,
+    fmt,
+    hash::{Hash, Hasher},
+    ops::{Deref, DerefMut},
+    rc::Rc,
+};
+
+use crate::{
+    ast::Function,
+    builtins::Builtin,
+    code::{Instructions, LineTable},
+    environment::Environment,
+    heap::Allocation,
+};
+
 // object type for different object
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ObjectType {
     Integer,
     Boolean,
     Null,
     Error,
+    Function,
+    String,
+    Array,
+    Hash,
+    Module,
 }
 
 // different object for evaluation
 #[derive(Debug, Clone)]
 pub enum Object {
-    Null,          // Null
-    Integer(i64),  // Int
-    Boolean(bool), // Boolean
-    Error(String), // Error message
+    Null,                                   // Null
+    Integer(i64),                           // Int
+    Boolean(bool),                          // Boolean
+    Error(String),                          // Error message
+    String(Rc<Payload<String>>),            // 不可变 读变量只复制指针
+    Array(Rc<Payload<Vec<Object>>>),        // 修改时 copy-on-write
+    Hash(Rc<Payload<Map>>),                 // 修改时 copy-on-write
+    Function(Rc<FunctionObject>),           // tree-walker closure
+    Builtin(&'static Builtin),              // 内建函数 len push ...
+    Module(Rc<Module>),                     // import 得到的模块
+    CompiledFunction(Rc<CompiledFunction>), // vm: function body bytecode
+    Closure(Rc<Closure>),                   // vm: compiled function + captured values
 }
 
 // the method for Object
 impl Object {
+    // 新建的字符串 数组 hash 还没有记账 由求值器按大小补上
+    pub fn string(s: impl Into<String>) -> Object {
+        Object::String(Rc::new(Payload::new(s.into())))
+    }
+
+    pub fn array(elements: Vec<Object>) -> Object {
+        Object::Array(Rc::new(Payload::new(elements)))
+    }
+
+    pub fn hash(map: Map) -> Object {
+        Object::Hash(Rc::new(Payload::new(map)))
+    }
+
+    // 字符串 数组 hash 的负载现在占多少字节 元素自己的负载另算 其它值返回 None
+    pub fn payload_bytes(&self) -> Option<usize> {
+        match self {
+            Object::String(s) => Some(size_of::<Payload<String>>() + s.len()),
+            Object::Array(elements) => {
+                Some(size_of::<Payload<Vec<Object>>>() + elements.len() * size_of::<Object>())
+            }
+            Object::Hash(map) => Some(
+                size_of::<Payload<Map>>()
+                    + map.len() * (size_of::<HashKey>() + size_of::<Object>()),
+            ),
+            _ => None,
+        }
+    }
+
+    // 只有独占的负载才能改记账 共享的负载已经由持有它的那一份记过了
+    pub fn charge_slot(&mut self) -> Option<&mut Option<Allocation>> {
+        match self {
+            Object::String(s) => Rc::get_mut(s).map(|s| &mut s.charge),
+            Object::Array(elements) => Rc::get_mut(elements).map(|e| &mut e.charge),
+            Object::Hash(map) => Rc::get_mut(map).map(|m| &mut m.charge),
+            _ => None,
+        }
+    }
+
     // return a ObjectType
     pub fn object_type(&self) -> ObjectType {
         match self {
@@ -25,6 +94,14 @@ impl Object {
             Object::Boolean(..) => ObjectType::Boolean,
             Object::Null => ObjectType::Null,
             Object::Error(..) => ObjectType::Error,
+            Object::String(..) => ObjectType::String,
+            Object::Array(..) => ObjectType::Array,
+            Object::Hash(..) => ObjectType::Hash,
+            Object::Module(..) => ObjectType::Module,
+            Object::Function(..)
+            | Object::Builtin(..)
+            | Object::CompiledFunction(..)
+            | Object::Closure(..) => ObjectType::Function,
         }
     }
 
@@ -35,6 +112,169 @@ impl Object {
             Object::Boolean(boolean) => format!("{}", boolean),
             Object::Null => "null".to_string(),
             Object::Error(err) => format!("Error: {}", err),
+            Object::String(s) => s.to_string(),
+            Object::Array(elements) => {
+                let elements: Vec<String> = elements.iter().map(Object::inspect).collect();
+                format!("[{}]", elements.join(", "))
+            }
+            Object::Hash(map) => {
+                let pairs: Vec<String> = map
+                    .iter()
+                    .map(|(k, v)| format!("{}: {}", k.object().inspect(), v.inspect()))
+                    .collect();
+                format!("{{{}}}", pairs.join(", "))
+            }
+            Object::Function(func) => func.function.string(),
+            Object::Builtin(builtin) => format!("<builtin {}>", builtin.name),
+            Object::Module(module) => format!("<module {}>", module.path),
+            Object::CompiledFunction(func) => format!("CompiledFunction[{:p}]", Rc::as_ptr(func)),
+            Object::Closure(closure) => format!("Closure[{:p}]", Rc::as_ptr(closure)),
         }
     }
+
+    // 能做 hash 的键的值 其它类型返回 None
+    pub fn hash_key(&self) -> Option<HashKey> {
+        match self {
+            Object::Integer(i) => Some(HashKey::Integer(*i)),
+            Object::Boolean(b) => Some(HashKey::Boolean(*b)),
+            Object::String(s) => Some(HashKey::String(s.clone())),
+            _ => None,
+        }
+    }
+}
+
+// hash 的键 BTreeMap 保证打印顺序稳定
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum HashKey {
+    Integer(i64),
+    Boolean(bool),
+    String(Rc<Payload<String>>),
+}
+
+impl HashKey {
+    // 成员访问 h.name 用的键
+    pub fn string(s: &str) -> HashKey {
+        HashKey::String(Rc::new(Payload::new(s.to_string())))
+    }
+
+    pub fn object(&self) -> Object {
+        match self {
+            HashKey::Integer(i) => Object::Integer(*i),
+            HashKey::Boolean(b) => Object::Boolean(*b),
+            HashKey::String(s) => Object::String(s.clone()),
+        }
+    }
+}
+
+pub type Map = BTreeMap<HashKey, Object>;
+
+// 字符串 数组 hash 的负载: 值 + 它在解释器堆上记的账 (和 FunctionObject 一样)
+// 最后一个 Rc 释放时归还 copy-on-write 复制出来的那份会再记一次
+// 比较 排序 hash 和调试输出只看值
+#[derive(Clone, Default)]
+pub struct Payload<T> {
+    pub value: T,
+    pub charge: Option<Allocation>,
+}
+
+impl<T> Payload<T> {
+    pub fn new(value: T) -> Self {
+        Payload {
+            value,
+            charge: None,
+        }
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for Payload<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.value.fmt(f)
+    }
+}
+
+impl<T> Deref for Payload<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.value
+    }
+}
+
+impl<T> DerefMut for Payload<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.value
+    }
+}
+
+impl<T: PartialEq> PartialEq for Payload<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.value == other.value
+    }
+}
+
+impl<T: Eq> Eq for Payload<T> {}
+
+impl<T: PartialOrd> PartialOrd for Payload<T> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        self.value.partial_cmp(&other.value)
+    }
+}
+
+impl<T: Ord> Ord for Payload<T> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.value.cmp(&other.value)
+    }
+}
+
+impl<T: Hash> Hash for Payload<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.value.hash(state);
+    }
+}
+
+// 树遍历解释器的函数对象: 函数字面量 + 定义时的环境
+pub struct FunctionObject {
+    pub function: Function,
+    pub env: Rc<RefCell<Environment>>,
+    // 这个闭包在解释器堆上记的账 没有开内存上限时也会记
+    pub charge: Option<Allocation>,
+}
+
+// 环境里可能引用自己(递归函数) 不打印env 防止无限递归
+impl fmt::Debug for FunctionObject {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Function({})", self.function.string())
+    }
+}
+
+// 模块: 自己的顶层环境 顶层 let 绑定的名字就是它导出的成员
+// 同一个文件只加载一次 所有 import 它的地方共享这一个
+pub struct Module {
+    // import 语句里写的路径 只用来打印
+    pub path: String,
+    pub env: Rc<RefCell<Environment>>,
+}
+
+impl fmt::Debug for Module {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Module({})", self.path)
+    }
+}
+
+// 编译后的函数: 指令 + 局部变量个数 + 参数个数 + 调试行号表
+// local_names 按槽位记录局部变量名 读到还没赋值的槽位时报错用
+#[derive(Debug, Clone, PartialEq)]
+pub struct CompiledFunction {
+    pub instructions: Instructions,
+    pub num_locals: usize,
+    pub num_parameters: usize,
+    pub local_names: Vec<String>,
+    pub lines: LineTable,
+}
+
+// vm 中的闭包: 编译函数 + 创建时捕获的自由变量
+#[derive(Debug, Clone)]
+pub struct Closure {
+    pub func: Rc<CompiledFunction>,
+    pub free: Vec<Object>,
 }
@@ -0,0 +1,261 @@
+use crate::{
+    ast::{
+        BlockStatement, Expr, ExpressionStatement, Function, MatchArm, Program, ReturnStatement,
+        Statement, StringPart,
+    },
+    token::TokenType,
+};
+
+/* ========== public entry ========== */
+
+// 常量折叠 + 剪掉条件为常量的 if
+// 只折叠一定不会出错的运算: 除零 溢出 类型不匹配都原样保留 留到运行时报错
+pub fn optimize(program: Program) -> Program {
+    Program {
+        statements: program.statements.into_iter().map(fold_statement).collect(),
+    }
+}
+
+/* ========== statements ========== */
+
+fn fold_statement(stmt: Statement) -> Statement {
+    match stmt {
+        Statement::Let {
+            pattern,
+            constant,
+            ty,
+            value,
+            pos,
+        } => Statement::Let {
+            pattern,
+            constant,
+            ty,
+            value: fold_expr(value),
+            pos,
+        },
+        Statement::Return(r) => Statement::Return(ReturnStatement {
+            return_value: fold_expr(r.return_value),
+            pos: r.pos,
+        }),
+        Statement::Expression(e) => Statement::Expression(ExpressionStatement {
+            expression: fold_expr(e.expression),
+            pos: e.pos,
+        }),
+        Statement::Block(b) => Statement::Block(fold_block(b)),
+        stmt @ (Statement::Import { .. } | Statement::None) => stmt,
+    }
+}
+
+fn fold_block(block: BlockStatement) -> BlockStatement {
+    BlockStatement {
+        statements: block.statements.into_iter().map(fold_statement).collect(),
+    }
+}
+
+/* ========== expressions ========== */
+
+fn fold_expr(e: Expr) -> Expr {
+    match e {
+        Expr::Prefix { op, right } => {
+            let right = fold_expr(*right);
+            fold_prefix(op, &right).unwrap_or(Expr::Prefix {
+                op,
+                right: Box::new(right),
+            })
+        }
+        Expr::Infix { left, op, right } => {
+            let left = fold_expr(*left);
+            let right = fold_expr(*right);
+            fold_infix(op, &left, &right).unwrap_or(Expr::Infix {
+                left: Box::new(left),
+                op,
+                right: Box::new(right),
+            })
+        }
+        Expr::IfExpression {
+            condition,
+            consequence,
+            alternative,
+        } => fold_if(fold_expr(*condition), *consequence, alternative.map(|a| *a)),
+        Expr::Conditional {
+            condition,
+            consequence,
+            alternative,
+        } => {
+            let condition = fold_expr(*condition);
+            let consequence = fold_expr(*consequence);
+            let alternative = fold_expr(*alternative);
+            match const_truthy(&condition) {
+                Some(true) => consequence,
+                Some(false) => alternative,
+                None => Expr::Conditional {
+                    condition: Box::new(condition),
+                    consequence: Box::new(consequence),
+                    alternative: Box::new(alternative),
+                },
+            }
+        }
+        // 左边是字面量时一定不是 null 右边不会被求值
+        Expr::Coalesce { left, right } => match fold_expr(*left) {
+            left @ (Expr::Integer(_) | Expr::Boolean(_) | Expr::String(_)) => left,
+            left => Expr::Coalesce {
+                left: Box::new(left),
+                right: Box::new(fold_expr(*right)),
+            },
+        },
+        Expr::Fn(func) => Expr::Fn(Function {
+            parameters: func.parameters,
+            return_type: func.return_type,
+            body: Box::new(fold_statement(*func.body)),
+            slots: func.slots,
+        }),
+        Expr::Call {
+            function,
+            arguments,
+        } => Expr::Call {
+            function: Box::new(fold_expr(*function)),
+            arguments: arguments.into_iter().map(fold_expr).collect(),
+        },
+        Expr::Array(elements) => Expr::Array(elements.into_iter().map(fold_expr).collect()),
+        Expr::Hash(pairs) => Expr::Hash(
+            pairs
+                .into_iter()
+                .map(|(k, v)| (fold_expr(k), fold_expr(v)))
+                .collect(),
+        ),
+        Expr::Index {
+            left,
+            index,
+            optional,
+        } => Expr::Index {
+            left: Box::new(fold_expr(*left)),
+            index: Box::new(fold_expr(*index)),
+            optional,
+        },
+        Expr::Interpolated(parts) => Expr::Interpolated(
+            parts
+                .into_iter()
+                .map(|part| match part {
+                    StringPart::Expr(e) => StringPart::Expr(fold_expr(e)),
+                    text => text,
+                })
+                .collect(),
+        ),
+        // 被赋值的变量不能折叠 只折叠下标和右边的值
+        Expr::Assign { target, op, value } => Expr::Assign {
+            target: Box::new(fold_expr(*target)),
+            op,
+            value: Box::new(fold_expr(*value)),
+        },
+        Expr::Member {
+            object,
+            name,
+            optional,
+        } => Expr::Member {
+            object: Box::new(fold_expr(*object)),
+            name,
+            optional,
+        },
+        // 分支的槽位已经由 resolver 算好 只折叠里面的表达式
+        Expr::Match { subject, arms } => Expr::Match {
+            subject: Box::new(fold_expr(*subject)),
+            arms: arms
+                .into_iter()
+                .map(|arm| MatchArm {
+                    guard: arm.guard.map(fold_expr),
+                    body: fold_expr(arm.body),
+                    ..arm
+                })
+                .collect(),
+        },
+        other => other,
+    }
+}
+
+fn fold_prefix(op: TokenType, right: &Expr) -> Option<Expr> {
+    match (op, right) {
+        (TokenType::Minus, Expr::Integer(i)) => i.checked_neg().map(Expr::Integer),
+        (TokenType::Plus, Expr::Integer(i)) => Some(Expr::Integer(*i)),
+        (TokenType::Bang, Expr::Integer(i)) => Some(Expr::Boolean(*i == 0)),
+        (TokenType::Bang, Expr::Boolean(b)) => Some(Expr::Boolean(!b)),
+        _ => None,
+    }
+}
+
+fn fold_infix(op: TokenType, left: &Expr, right: &Expr) -> Option<Expr> {
+    match (left, right) {
+        (Expr::Integer(l), Expr::Integer(r)) => {
+            let (l, r) = (*l, *r);
+            match op {
+                TokenType::Plus => l.checked_add(r).map(Expr::Integer),
+                TokenType::Minus => l.checked_sub(r).map(Expr::Integer),
+                TokenType::Asterisk => l.checked_mul(r).map(Expr::Integer),
+                // 除零返回 None: 保留原表达式 运行时照样报 division by zero
+                TokenType::Slash => l.checked_div(r).map(Expr::Integer),
+                TokenType::Eq => Some(Expr::Boolean(l == r)),
+                TokenType::NotEq => Some(Expr::Boolean(l != r)),
+                TokenType::Lt => Some(Expr::Boolean(l < r)),
+                TokenType::Gt => Some(Expr::Boolean(l > r)),
+                TokenType::Le => Some(Expr::Boolean(l <= r)),
+                TokenType::Ge => Some(Expr::Boolean(l >= r)),
+                _ => None,
+            }
+        }
+        (Expr::Boolean(l), Expr::Boolean(r)) => match op {
+            TokenType::Eq => Some(Expr::Boolean(l == r)),
+            TokenType::NotEq => Some(Expr::Boolean(l != r)),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+// 常量条件的真假 不是常量时返回 None
+fn const_truthy(condition: &Expr) -> Option<bool> {
+    match condition {
+        Expr::Boolean(b) => Some(*b),
+        Expr::Integer(i) => Some(*i != 0),
+        _ => None,
+    }
+}
+
+// 条件是常量时只保留会执行的分支
+fn fold_if(condition: Expr, consequence: Statement, alternative: Option<Statement>) -> Expr {
+    let consequence = fold_statement(consequence);
+    let alternative = alternative.map(fold_statement);
+
+    let Some(truthy) = const_truthy(&condition) else {
+        return Expr::IfExpression {
+            condition: Box::new(condition),
+            consequence: Box::new(consequence),
+            alternative: alternative.map(Box::new),
+        };
+    };
+    let chosen = if truthy {
+        consequence
+    } else {
+        alternative.unwrap_or(Statement::Block(BlockStatement { statements: vec![] }))
+    };
+
+    let chosen = match chosen {
+        // 分支只有一个表达式: 直接用它替换整个 if
+        Statement::Block(mut block)
+            if matches!(block.statements.as_slice(), [Statement::Expression(_)]) =>
+        {
+            match block.statements.pop() {
+                Some(Statement::Expression(e)) => return e.expression,
+                _ => unreachable!(),
+            }
+        }
+        // else if 选中的分支: 已经折叠过的下一个 if
+        Statement::Expression(e) => return e.expression,
+        other => other,
+    };
+
+    // 否则保留分支里的语句 (let/return 需要原样执行) 条件换成 true
+    Expr::IfExpression {
+        condition: Box::new(Expr::Boolean(true)),
+        consequence: Box::new(chosen),
+        alternative: None,
+    }
+}
@@ -1,22 +1,34 @@
+use std::rc::Rc;
+
 use crate::{
     ast::{
-        BlockStatement, Expr, ExpressionStatement, Function, Ident, Program, ReturnStatement,
-        Statement,
+        BlockStatement, Expr, ExpressionStatement, Function, Ident, MatchArm, Param, Pattern,
+        Program, ReturnStatement, Statement, StringPart,
     },
-    lexer::Lexer,
-    token::{Token, TokenType},
+    lexer::{self, Lexer},
+    resolver,
+    token::{Position, Token, TokenType},
+    types::Type,
 };
 
+// parse_expression 最多嵌套的层数 后面的 resolver / evaluator 也是递归的
+// 这个深度在 debug 构建 2MB 的线程栈上跑完整个流程也是安全的
+pub const MAX_NESTING_DEPTH: usize = 128;
+
 #[derive(Default, Debug, PartialEq, Eq, PartialOrd, Ord, Clone)]
 pub enum Precedence {
     #[default]
     Lowest = 0,
+    Assign,      // = += -= *= /= (右结合)
+    Conditional, // c ? a : b (右结合)
+    Coalesce,    // a ?? b
     Equals,      // == !=
     LessGreater, // >= or > or < or <=
     Sum,         // a + b or a - b
     Product,     // a * b or a / b
     Prefix,      // !a -a +a
     Call,        // call(x)
+    Index,       // xs[0] m.f xs?[0] m?.f
     Highest,
 }
 
@@ -26,36 +38,57 @@ impl Precedence {
     pub fn of(token_type: TokenType) -> Precedence {
         use TokenType::*;
         match token_type {
+            Assign | PlusAssign | MinusAssign | AsteriskAssign | SlashAssign => Precedence::Assign,
+            Question => Precedence::Conditional,
+            NullCoalesce => Precedence::Coalesce,
             Eq | NotEq => Precedence::Equals,
             Lt | Gt | Le | Ge => Precedence::LessGreater,
             Plus | Minus => Precedence::Sum,
             Slash | Asterisk => Precedence::Product,
             Lparen => Precedence::Call,
+            Lbracket | Dot | OptionalBracket | OptionalDot => Precedence::Index,
             _ => Precedence::Lowest,
         }
     }
 }
 
 #[derive(Debug)]
-pub struct Parser {
+pub struct Parser<'src> {
     // lexer
-    l: Lexer,
+    l: Lexer<'src>,
     // error massage collect
     errors: Vec<String>,
+    // resolver 报的错误 (给常量赋值) 语法本身没有问题
+    resolve_errors: Vec<String>,
     // 当前的token
-    cur_token: Token,
+    cur_token: Token<'src>,
     // 下一个预测的token
-    peek_token: Token,
+    peek_token: Token<'src>,
+    // cur/peek token 在源码中的位置
+    cur_position: Position,
+    peek_position: Position,
+    // 当前 parse_expression 的嵌套层数
+    depth: usize,
+    // 嵌套超过上限之后不再解析 也不再报后续的错误
+    too_deep: bool,
+    // 当前在几层函数体里 import / export 只能写在顶层
+    fn_depth: usize,
 }
 
-impl Parser {
-    pub fn new(lexer: Lexer) -> Parser {
+impl<'src> Parser<'src> {
+    pub fn new(lexer: Lexer<'src>) -> Parser<'src> {
         // 初始化
         let mut p: Parser = Parser {
             l: lexer,
             errors: Vec::new(),
+            resolve_errors: Vec::new(),
             cur_token: Token::default(),
             peek_token: Token::default(),
+            cur_position: Position::default(),
+            peek_position: Position::default(),
+            depth: 0,
+            too_deep: false,
+            fn_depth: 0,
         };
 
         // 跳跃两次 让token建立正确顺序
@@ -68,9 +101,11 @@ impl Parser {
 
     // cur -> peek, peek -> next
     pub fn next_token(&mut self) {
-        // std::mem::take 减少clone开销
-        self.cur_token = std::mem::take(&mut self.peek_token);
+        // Token 是 Copy 的切片 直接移动即可
+        self.cur_token = self.peek_token;
+        self.cur_position = self.peek_position;
         self.peek_token = self.l.next_token();
+        self.peek_position = self.l.token_position();
     }
 
     // 解析程序
@@ -94,32 +129,43 @@ impl Parser {
             // 下一个token开始循环
             self.next_token();
         }
-        return Some(program);
+        // 给每个变量算好词法地址 求值时不用再按名字查 顺便检查给常量赋值
+        self.resolve_errors = resolver::resolve(&mut program);
+        Some(program)
     }
 
     // 解析statement
     pub fn parse_statement(&mut self) -> Statement {
         match self.cur_token.token_type {
-            // skip error handle later for more
-            // TODO: make unwrap() dispear
-            TokenType::Let => self.parse_let_statement().unwrap(),
-            TokenType::Return => self.parse_return_statement().unwrap(),
+            TokenType::Let | TokenType::Const => self.parse_let_statement(),
+            TokenType::Return => self.parse_return_statement(),
+            TokenType::Import => self.parse_import_statement(),
+            TokenType::Export => self.parse_export_statement(),
             // 默认处理表达式
-            _ => self.parse_expression_statement().unwrap(),
+            _ => self.parse_expression_statement(),
         }
+        .unwrap_or(Statement::None)
     }
 
-    // 解析let statement 一个Option<Statement> => Statement::Let{name: Ident, value: Expr}
+    // 解析let statement 一个Option<Statement> => Statement::Let{pattern: Pattern, value: Expr}
+    // const x = 1; 走同一条路 只是绑定不能再赋值
     pub fn parse_let_statement(&mut self) -> Option<Statement> {
-        // 因为我提前预判到cur_token 是TokenType::Let
-        // 直接就可以peek 是不是ident
-        if !self.expect_peek(TokenType::Ident) {
+        let pos = self.cur_position;
+        let constant = self.cur_token_is(TokenType::Const);
+        // 后面是名字或者 [ { 开头的解构模式 都不是时和以前一样报缺少名字
+        if !matches!(
+            self.peek_token.token_type,
+            TokenType::Lbracket | TokenType::Lbrace
+        ) && !self.expect_peek(TokenType::Ident)
+        {
             return None;
         }
-
-        // 开始创建Let Statement
-        let name = Ident(self.cur_token.literal.clone());
-        // skip value expression
+        if !self.cur_token_is(TokenType::Ident) {
+            self.next_token();
+        }
+        let pattern = self.parse_pattern()?;
+        // let x: int = 5; 可选的类型注解
+        let ty = self.parse_type_annotation();
 
         // let x = y;
         // 确保ident 下一个是assign标志
@@ -136,15 +182,55 @@ impl Parser {
             self.next_token();
         }
 
-        return Some(Statement::Let {
-            name: name,
-            value: value,
-        });
+        Some(Statement::Let {
+            pattern,
+            constant,
+            ty,
+            value,
+            pos,
+        })
+    }
+
+    // import "path" as name;
+    pub fn parse_import_statement(&mut self) -> Option<Statement> {
+        let pos = self.cur_position;
+        if self.fn_depth > 0 {
+            self.errors
+                .push("import is only allowed at the top level".into());
+        }
+        if !self.expect_peek(TokenType::String) {
+            return None;
+        }
+        let path = unescape(self.cur_token.literal);
+        if !self.expect_peek(TokenType::As) || !self.expect_peek(TokenType::Ident) {
+            return None;
+        }
+        let name = Ident::new(self.cur_token.literal);
+
+        if self.peek_token_is(TokenType::Semicolon) {
+            self.next_token();
+        }
+        Some(Statement::Import { path, name, pos })
+    }
+
+    // export let/const x = ...; 顶层的 let 本来就会导出 export 只是写给读代码的人看
+    pub fn parse_export_statement(&mut self) -> Option<Statement> {
+        if self.fn_depth > 0 {
+            self.errors
+                .push("export is only allowed at the top level".into());
+        }
+        if self.peek_token_is(TokenType::Const) {
+            self.next_token();
+        } else if !self.expect_peek(TokenType::Let) {
+            return None;
+        }
+        self.parse_let_statement()
     }
 
     // 解析return statement => Statement::Returnt{ReturnStatement}
     pub fn parse_return_statement(&mut self) -> Option<Statement> {
         // 因为我已经知道tokenType == TokenType::Return 所以没必要获取literal
+        let pos = self.cur_position;
 
         // 跳到下一个token  (处理value)
         self.next_token();
@@ -154,13 +240,15 @@ impl Parser {
             self.next_token();
         }
 
-        return Some(Statement::Return(ReturnStatement {
+        Some(Statement::Return(ReturnStatement {
             return_value: value,
-        }));
+            pos,
+        }))
     }
 
     // 解析expresion statement => Statement::Expression(ExpressionStatement)
     pub fn parse_expression_statement(&mut self) -> Option<Statement> {
+        let pos = self.cur_position;
         // 预先是Lowest优先级
         let expression = self.parse_expression(Precedence::Lowest);
 
@@ -170,16 +258,47 @@ impl Parser {
         }
 
         // return a Statement
-        return Some(Statement::Expression(ExpressionStatement {
-            expression: expression,
-        }));
+        Some(Statement::Expression(ExpressionStatement {
+            expression,
+            pos,
+        }))
+    }
+
+    // 嵌套太深 (比如 10 万个 `(` 或 `[`) 时报一个错误 跳过剩下的输入 不让递归把栈撑爆
+    // 表达式 类型和模式共用同一个深度计数
+    fn nested_too_deep(&mut self, what: &str) -> bool {
+        if self.depth < MAX_NESTING_DEPTH {
+            return false;
+        }
+        if !self.too_deep {
+            self.too_deep = true;
+            self.errors.push(format!(
+                "{} nested too deeply (limit {})",
+                what, MAX_NESTING_DEPTH
+            ));
+        }
+        while !self.peek_token_is(TokenType::Eof) {
+            self.next_token();
+        }
+        true
     }
 
     // 解析 Expression 的案例 但是目前错误处理是 Expr::Default 做占位
     pub fn parse_expression(&mut self, prec: Precedence) -> Expr {
+        if self.nested_too_deep("expression") {
+            return Expr::None;
+        }
+
+        self.depth += 1;
+        let expr = self.parse_expression_inner(prec);
+        self.depth -= 1;
+        expr
+    }
+
+    fn parse_expression_inner(&mut self, prec: Precedence) -> Expr {
         let mut left = match self.cur_token.token_type {
             // 处理Expression 中的 Ident
-            TokenType::Ident => Expr::Ident(Ident(self.cur_token.literal.clone())),
+            TokenType::Ident => Expr::Ident(Ident::new(self.cur_token.literal)),
             // 处理 Expression 中的 Integer
             // 直接逻辑就是 和monkey go不太一样的事情是 我直接parser为Integer
             TokenType::Int => match self.cur_token.literal.parse::<i64>() {
@@ -191,11 +310,11 @@ impl Parser {
             },
             // 解析Prefix式子用的 ! 和 - 和 +
             TokenType::Bang | TokenType::Minus | TokenType::Plus => {
-                let op = self.cur_token.token_type.clone();
+                let op = self.cur_token.token_type;
                 self.next_token();
                 let right = self.parse_expression(Precedence::Prefix);
                 Expr::Prefix {
-                    op: op,
+                    op,
                     right: Box::new(right),
                 }
             }
@@ -208,16 +327,29 @@ impl Parser {
                 }
                 expr
             }
+            TokenType::String => self.parse_string_literal(),
+            // [1, 2, 3]
+            TokenType::Lbracket => match self.parse_expression_list(TokenType::Rbracket) {
+                Some(elements) => Expr::Array(elements),
+                None => return Expr::None,
+            },
+            // {"a": 1}
+            TokenType::Lbrace => self.parse_hash_literal(),
             // 处理boolean #[bug] 不要返回 之前return 了合成直接退出了pratt
             TokenType::True | TokenType::False => self.parse_boolean(),
             // 处理if表达式
             TokenType::If => self.parse_if_expression(),
+            // match 表达式
+            TokenType::Match => self.parse_match_expression(),
             // 处理Function 函数
             TokenType::Function => self.parse_function(),
             _ => Expr::None,
         };
 
         // 基于优先级的infix折叠循环
+        // 左结合的链 (1 + 1 + ... / f()()...) 在这个循环里一层层往上包 不经过递归
+        // 每包一层也算一层嵌套 否则后面遍历 AST 的递归会把栈撑爆
+        let mut wrapped = 0;
         while !self.peek_token_is(TokenType::Semicolon)
             && self.peek_token.token_type != TokenType::Eof
             && prec < self.peek_precedence()
@@ -235,11 +367,28 @@ impl Parser {
                     | TokenType::Eq
                     | TokenType::NotEq
                     | TokenType::Lparen
+                    | TokenType::Lbracket
+                    | TokenType::Dot
+                    | TokenType::Assign
+                    | TokenType::PlusAssign
+                    | TokenType::MinusAssign
+                    | TokenType::AsteriskAssign
+                    | TokenType::SlashAssign
+                    | TokenType::Question
+                    | TokenType::NullCoalesce
+                    | TokenType::OptionalDot
+                    | TokenType::OptionalBracket
             );
             // 如果下一个tokentype 不是运算符 operator 那就直接break循环
             if !is_infix_or_call {
                 break;
             }
+            if self.nested_too_deep("expression") {
+                left = Expr::None;
+                break;
+            }
+            self.depth += 1;
+            wrapped += 1;
             // 跳转到运算符号
             self.next_token();
             // 然后找到 parser_infix_expression
@@ -250,13 +399,27 @@ impl Parser {
             //  解析运算符号
             if self.cur_token_is(TokenType::Lparen) {
                 left = self.parse_call_expression(left);
+            } else if self.cur_token_is(TokenType::Lbracket)
+                || self.cur_token_is(TokenType::OptionalBracket)
+            {
+                left = self.parse_index_expression(left);
+            } else if self.cur_token_is(TokenType::Dot) || self.cur_token_is(TokenType::OptionalDot)
+            {
+                left = self.parse_member_expression(left);
+            } else if self.cur_token_is(TokenType::Question) {
+                left = self.parse_conditional_expression(left);
+            } else if self.cur_token_is(TokenType::NullCoalesce) {
+                left = self.parse_coalesce_expression(left);
+            } else if self.cur_precedence() == Precedence::Assign {
+                left = self.parse_assign_expression(left);
             } else {
                 left = self.parse_infix_expression(left);
             }
         }
+        self.depth -= wrapped;
 
         // 返回解析好的infix expression
-        return left;
+        left
     }
 
     // parse call expression
@@ -264,7 +427,7 @@ impl Parser {
         // 解析arguements
         let arguements = match self.parse_call_arguments() {
             Some(args) => args,
-            None => panic!("error parsing arguements()"),
+            None => return Expr::None,
         };
 
         // return Expr::Call
@@ -276,11 +439,16 @@ impl Parser {
 
     // helper function: parser call expression for arguements
     pub fn parse_call_arguments(&mut self) -> Option<Vec<Expr>> {
+        self.parse_expression_list(TokenType::Rparen)
+    }
+
+    // 逗号分隔的表达式 直到 end: 调用的实参 数组字面量
+    pub fn parse_expression_list(&mut self, end: TokenType) -> Option<Vec<Expr>> {
         // 初始化args队列
         let mut args = Vec::new();
 
-        // 如果下一个是Rparen 直接退出
-        if self.peek_token_is(TokenType::Rparen) {
+        // 如果下一个是end 直接退出
+        if self.peek_token_is(end) {
             self.next_token();
             return Some(args);
         }
@@ -300,7 +468,7 @@ impl Parser {
         }
 
         // 解析完 发现没有右括号
-        if !self.expect_peek(TokenType::Rparen) {
+        if !self.expect_peek(end) {
             return None;
         }
 
@@ -308,6 +476,135 @@ impl Parser {
         Some(args)
     }
 
+    // xs[i] 当前在 [ 上
+    pub fn parse_index_expression(&mut self, left: Expr) -> Expr {
+        let optional = self.cur_token_is(TokenType::OptionalBracket);
+        self.next_token();
+        let index = self.parse_expression(Precedence::Lowest);
+        if !self.expect_peek(TokenType::Rbracket) {
+            return Expr::None;
+        }
+        Expr::Index {
+            left: Box::new(left),
+            index: Box::new(index),
+            optional,
+        }
+    }
+
+    // c ? a : b 当前在 ? 上 中间可以是任何表达式
+    // 冒号后面按比 ?: 低一级解析 所以 a ? b : c ? d : e 是右结合的
+    pub fn parse_conditional_expression(&mut self, condition: Expr) -> Expr {
+        self.next_token();
+        let consequence = self.parse_expression(Precedence::Lowest);
+        if !self.expect_peek(TokenType::Colon) {
+            return Expr::None;
+        }
+        self.next_token();
+        let alternative = self.parse_expression(Precedence::Assign);
+        Expr::Conditional {
+            condition: Box::new(condition),
+            consequence: Box::new(consequence),
+            alternative: Box::new(alternative),
+        }
+    }
+
+    // a ?? b 左结合 当前在 ?? 上
+    pub fn parse_coalesce_expression(&mut self, left: Expr) -> Expr {
+        self.next_token();
+        let right = self.parse_expression(Precedence::Coalesce);
+        Expr::Coalesce {
+            left: Box::new(left),
+            right: Box::new(right),
+        }
+    }
+
+    // 没有 ${ 的就是普通字符串 有的话拆成文字和表达式交替的几段
+    pub fn parse_string_literal(&mut self) -> Expr {
+        let mut rest = self.cur_token.literal;
+        let Some(mut start) = find_interpolation(rest) else {
+            return Expr::String(unescape(rest).into());
+        };
+
+        let mut parts = Vec::new();
+        loop {
+            if start > 0 {
+                parts.push(StringPart::Text(unescape(&rest[..start]).into()));
+            }
+            let Some(len) = lexer::interpolation_len(&rest[start..]) else {
+                self.errors.push("unterminated ${ in string literal".into());
+                return Expr::None;
+            };
+            let code = &rest[start + 2..start + len - 1];
+            parts.push(StringPart::Expr(self.parse_interpolation(code)));
+
+            rest = &rest[start + len..];
+            match find_interpolation(rest) {
+                Some(next) => start = next,
+                None => break,
+            }
+        }
+        if !rest.is_empty() {
+            parts.push(StringPart::Text(unescape(rest).into()));
+        }
+        Expr::Interpolated(parts)
+    }
+
+    // ${} 里的代码用一个新的 parser 解析 嵌套层数接着外面的算
+    fn parse_interpolation(&mut self, code: &'src str) -> Expr {
+        if code.trim().is_empty() {
+            self.errors.push("empty ${} in string literal".into());
+            return Expr::None;
+        }
+        let mut p = Parser::new(Lexer::new(code));
+        p.depth = self.depth;
+        let expr = p.parse_expression(Precedence::Lowest);
+        if !p.too_deep && !p.peek_token_is(TokenType::Eof) {
+            p.errors.push(format!(
+                "unexpected {} in ${{{}}}",
+                p.peek_token.literal, code
+            ));
+        }
+        self.too_deep |= p.too_deep;
+        self.errors.append(&mut p.errors);
+        expr
+    }
+
+    // m.name 当前在 . 上
+    pub fn parse_member_expression(&mut self, object: Expr) -> Expr {
+        let optional = self.cur_token_is(TokenType::OptionalDot);
+        if !self.expect_peek(TokenType::Ident) {
+            return Expr::None;
+        }
+        Expr::Member {
+            object: Box::new(object),
+            name: self.cur_token.literal.into(),
+            optional,
+        }
+    }
+
+    // {k: v, ...} 当前在 { 上
+    pub fn parse_hash_literal(&mut self) -> Expr {
+        let mut pairs = Vec::new();
+        while !self.peek_token_is(TokenType::Rbrace) {
+            self.next_token();
+            let key = self.parse_expression(Precedence::Lowest);
+            if !self.expect_peek(TokenType::Colon) {
+                return Expr::None;
+            }
+            self.next_token();
+            let value = self.parse_expression(Precedence::Lowest);
+            pairs.push((key, value));
+
+            if !self.peek_token_is(TokenType::Rbrace) && !self.expect_peek(TokenType::Comma) {
+                return Expr::None;
+            }
+        }
+        if !self.expect_peek(TokenType::Rbrace) {
+            return Expr::None;
+        }
+        Expr::Hash(pairs)
+    }
+
     // parse infix
     pub fn parse_infix_expression(&mut self, left: Expr) -> Expr {
         // 提取优先级
@@ -326,6 +623,23 @@ impl Parser {
         }
     }
 
+    // target = value 当前在 = 上
+    // 右边用最低优先级解析 所以 a = b = 1 是 a = (b = 1)
+    pub fn parse_assign_expression(&mut self, target: Expr) -> Expr {
+        let op = self.cur_token.token_type;
+        if !is_assignable(&target) {
+            self.errors
+                .push(format!("invalid assignment target: {}", target.string()));
+        }
+        self.next_token();
+        let value = self.parse_expression(Precedence::Lowest);
+        Expr::Assign {
+            target: Box::new(target),
+            op,
+            value: Box::new(value),
+        }
+    }
+
     // parse boolean
     pub fn parse_boolean(&mut self) -> Expr {
         // 返回boolean expression
@@ -336,7 +650,7 @@ impl Parser {
     pub fn parse_if_expression(&mut self) -> Expr {
         // cur_token.TokenType == If
         if !self.expect_peek(TokenType::Lparen) {
-            panic!("need (");
+            return Expr::None;
         }
 
         self.next_token();
@@ -344,91 +658,405 @@ impl Parser {
 
         // 别忘了 expect_peek() 会自己滚动一个token
         if !self.expect_peek(TokenType::Rparen) {
-            panic!("need )")
+            return Expr::None;
         }
 
         // 跳转到了{ lbrace
         if !self.expect_peek(TokenType::Lbrace) {
-            panic!("need {{");
+            return Expr::None;
         }
 
         // 解析block
         let consequence = self.parse_block_statement();
+        // else { ... } 或者 else if 接着下一个 if 表达式
         let alternative = if self.peek_token_is(TokenType::Else) {
             self.next_token();
-            if !self.expect_peek(TokenType::Lbrace) {
-                panic!("need {{ after else");
+            if self.peek_token_is(TokenType::If) {
+                // 每个 else if 在 AST 里都多套一层 和表达式一样算嵌套
+                if self.nested_too_deep("expression") {
+                    return Expr::None;
+                }
+                self.next_token();
+                let pos = self.cur_position;
+                self.depth += 1;
+                let expression = self.parse_if_expression();
+                self.depth -= 1;
+                if matches!(expression, Expr::None) {
+                    return Expr::None;
+                }
+                Some(Statement::Expression(ExpressionStatement {
+                    expression,
+                    pos,
+                }))
+            } else {
+                if !self.expect_peek(TokenType::Lbrace) {
+                    return Expr::None;
+                }
+                Some(self.parse_block_statement())
             }
-            self.parse_block_statement()
         } else {
-            Statement::None
+            None
         };
 
-        let expression = Expr::IfExpression {
+        Expr::IfExpression {
             condition: Box::new(condition),
             consequence: Box::new(consequence), // statement::Block(BlockStatements)
-            alternative: Box::new(alternative),
-        };
-        return expression;
+            alternative: alternative.map(Box::new),
+        }
+    }
+
+    // match (x) { pattern [if guard] => expr, ... } 最后一个分支后面的逗号可选
+    pub fn parse_match_expression(&mut self) -> Expr {
+        if !self.expect_peek(TokenType::Lparen) {
+            return Expr::None;
+        }
+        self.next_token();
+        let subject = self.parse_expression(Precedence::Lowest);
+        if !self.expect_peek(TokenType::Rparen) || !self.expect_peek(TokenType::Lbrace) {
+            return Expr::None;
+        }
+
+        let mut arms = Vec::new();
+        while !self.peek_token_is(TokenType::Rbrace) {
+            self.next_token();
+            let Some(pattern) = self.parse_pattern_with(true) else {
+                return Expr::None;
+            };
+            let guard = if self.peek_token_is(TokenType::If) {
+                self.next_token();
+                self.next_token();
+                Some(self.parse_expression(Precedence::Lowest))
+            } else {
+                None
+            };
+            if !self.expect_peek(TokenType::FatArrow) {
+                return Expr::None;
+            }
+            self.next_token();
+            let body = self.parse_expression(Precedence::Lowest);
+            arms.push(MatchArm {
+                pattern,
+                guard,
+                body,
+                slots: Rc::default(),
+            });
+            if !self.peek_token_is(TokenType::Rbrace) && !self.expect_peek(TokenType::Comma) {
+                return Expr::None;
+            }
+        }
+        if !self.expect_peek(TokenType::Rbrace) {
+            return Expr::None;
+        }
+
+        Expr::Match {
+            subject: Box::new(subject),
+            arms,
+        }
     }
 
     // parse fn expression
     pub fn parse_function(&mut self) -> Expr {
         // 先跳转到左括号
         if !self.expect_peek(TokenType::Lparen) {
-            panic!("expected (");
+            return Expr::None;
         }
 
         // 然后开始解析 函数参数
         let parameters = self.parse_function_parameters();
+        // 可选的返回类型 -> int
+        let return_type = if self.peek_token_is(TokenType::Arrow) {
+            self.next_token();
+            self.next_token();
+            Some(self.parse_type())
+        } else {
+            None
+        };
         // 跳转到{
         if !self.expect_peek(TokenType::Lbrace) {
-            panic!("expected {{ after function parameters");
+            return Expr::None;
         }
         // 解析函数block
+        self.fn_depth += 1;
         let body = self.parse_block_statement();
+        self.fn_depth -= 1;
 
         // 返回解析好的Expr::Fn(func)
-        let func = Expr::Fn(Function {
-            parameters: parameters,
+
+        Expr::Fn(Function {
+            parameters,
+            return_type,
             body: Box::new(body),
-        });
-        return func;
+            slots: Rc::default(),
+        })
     }
 
     // parse fn parameters (helper function)
-    pub fn parse_function_parameters(&mut self) -> Vec<Ident> {
-        // 初始化变量Idents 变量保存
-        let mut idents: Vec<Ident> = Vec::new();
+    pub fn parse_function_parameters(&mut self) -> Vec<Param> {
+        // 初始化参数列表
+        let mut params: Vec<Param> = Vec::new();
 
         // () 参数为0的情况
         if self.peek_token_is(TokenType::Rparen) {
             // 直接跳转 )
             self.next_token();
             // 返回空参数
-            return idents;
+            return params;
         }
 
         // 如果 不是参数为0 跳转到第一个参数 差不多x, y 的x位置
         self.next_token();
-        // 计入x 变量
-        idents.push(Ident(self.cur_token.literal.clone()));
+        // 计入x 变量 (可能带 : int)
+        params.push(self.parse_param());
 
         // 如果下一个是, 那么跳转
         while self.peek_token_is(TokenType::Comma) {
-            // x_(当前在x), y, 距离下一个变量总是相差2个身位
+            // x_(当前在x), y 跳过逗号
             self.next_token();
             self.next_token();
             // 跳转到了 y
-            idents.push(Ident(self.cur_token.literal.clone()));
+            params.push(self.parse_param());
         }
 
-        // 如果下一个不是) 直接panic 如果是 跳转到了 )
-        if !self.expect_peek(TokenType::Rparen) {
-            panic!("expected )")
+        // 如果下一个不是) 记一个错误 如果是 跳转到了 )
+        self.expect_peek(TokenType::Rparen);
+
+        params
+    }
+
+    fn parse_param(&mut self) -> Param {
+        let pattern = self
+            .parse_pattern()
+            .unwrap_or_else(|| Pattern::Ident(Ident::new(self.cur_token.literal)));
+        let ty = self.parse_type_annotation();
+        Param { pattern, ty }
+    }
+
+    // 绑定的左边: x / [a, b, ...rest] / {name, age: a} 可以嵌套
+    // 当前在模式的第一个 token 上 停在最后一个 token 上
+    pub fn parse_pattern(&mut self) -> Option<Pattern> {
+        self.parse_pattern_with(false)
+    }
+
+    // refutable: match 的分支 还可以写 _ 和字面量
+    fn parse_pattern_with(&mut self, refutable: bool) -> Option<Pattern> {
+        let pattern = self.parse_pattern_inner(refutable)?;
+        let idents = pattern.idents();
+        for (i, ident) in idents.iter().enumerate() {
+            if idents[..i].iter().any(|prev| prev.0 == ident.0) {
+                self.errors
+                    .push(format!("duplicate name in pattern: {}", ident.0));
+            }
+        }
+        Some(pattern)
+    }
+
+    fn parse_pattern_inner(&mut self, refutable: bool) -> Option<Pattern> {
+        if self.nested_too_deep("pattern") {
+            return None;
+        }
+
+        self.depth += 1;
+        let pattern = self.parse_pattern_node(refutable);
+        self.depth -= 1;
+        pattern
+    }
+
+    fn parse_pattern_node(&mut self, refutable: bool) -> Option<Pattern> {
+        match self.cur_token.token_type {
+            TokenType::Ident if refutable && self.cur_token.literal == "_" => {
+                Some(Pattern::Wildcard)
+            }
+            TokenType::Ident => Some(Pattern::Ident(Ident::new(self.cur_token.literal))),
+            TokenType::Int
+            | TokenType::Minus
+            | TokenType::String
+            | TokenType::True
+            | TokenType::False
+                if refutable =>
+            {
+                self.parse_literal_pattern()
+            }
+            TokenType::Lbracket => {
+                let mut elements = Vec::new();
+                let mut rest = None;
+                while !self.peek_token_is(TokenType::Rbracket) {
+                    self.next_token();
+                    // ...rest 只能放在最后
+                    if self.cur_token_is(TokenType::Ellipsis) {
+                        if !self.expect_peek(TokenType::Ident) {
+                            return None;
+                        }
+                        rest = Some(Ident::new(self.cur_token.literal));
+                        break;
+                    }
+                    elements.push(self.parse_pattern_inner(refutable)?);
+                    if !self.peek_token_is(TokenType::Rbracket)
+                        && !self.expect_peek(TokenType::Comma)
+                    {
+                        return None;
+                    }
+                }
+                if !self.expect_peek(TokenType::Rbracket) {
+                    return None;
+                }
+                Some(Pattern::Array { elements, rest })
+            }
+            TokenType::Lbrace => {
+                let mut pairs = Vec::new();
+                while !self.peek_token_is(TokenType::Rbrace) {
+                    self.next_token();
+                    let key: Rc<str> = match self.cur_token.token_type {
+                        TokenType::Ident => self.cur_token.literal.into(),
+                        TokenType::String => unescape(self.cur_token.literal).into(),
+                        other => {
+                            self.errors
+                                .push(format!("expected a hash key in pattern, got {:?}", other));
+                            return None;
+                        }
+                    };
+                    // {name} 是 {name: name} 的简写 字符串键必须写出模式
+                    let pattern = if self.peek_token_is(TokenType::Colon)
+                        || self.cur_token_is(TokenType::String)
+                    {
+                        if !self.expect_peek(TokenType::Colon) {
+                            return None;
+                        }
+                        self.next_token();
+                        self.parse_pattern_inner(refutable)?
+                    } else {
+                        Pattern::Ident(Ident::new(self.cur_token.literal))
+                    };
+                    pairs.push((key, pattern));
+                    if !self.peek_token_is(TokenType::Rbrace) && !self.expect_peek(TokenType::Comma)
+                    {
+                        return None;
+                    }
+                }
+                if !self.expect_peek(TokenType::Rbrace) {
+                    return None;
+                }
+                Some(Pattern::Hash(pairs))
+            }
+            other => {
+                self.errors
+                    .push(format!("expected a name or a pattern, got {:?}", other));
+                None
+            }
+        }
+    }
+
+    // 字面量模式: 整数 (可以带负号) 字符串 (不能插值) true false
+    fn parse_literal_pattern(&mut self) -> Option<Pattern> {
+        let negative = self.cur_token_is(TokenType::Minus);
+        if negative && !self.expect_peek(TokenType::Int) {
+            return None;
         }
+        let literal = match self.cur_token.token_type {
+            TokenType::Int => {
+                let digits = self.cur_token.literal;
+                let parsed = if negative {
+                    format!("-{}", digits).parse::<i64>()
+                } else {
+                    digits.parse::<i64>()
+                };
+                match parsed {
+                    Ok(i) => Expr::Integer(i),
+                    Err(_) => {
+                        self.errors.push(format!(
+                            "integer literal out of range in pattern: {}",
+                            digits
+                        ));
+                        return None;
+                    }
+                }
+            }
+            TokenType::String => match self.parse_string_literal() {
+                lit @ Expr::String(_) => lit,
+                _ => {
+                    self.errors
+                        .push("string interpolation is not allowed in patterns".to_string());
+                    return None;
+                }
+            },
+            _ => self.parse_boolean(),
+        };
+        Some(Pattern::Literal(literal))
+    }
 
-        return idents;
+    // 当前在名字上 后面跟着 : 就解析类型 停在类型的最后一个token上
+    fn parse_type_annotation(&mut self) -> Option<Type> {
+        if !self.peek_token_is(TokenType::Colon) {
+            return None;
+        }
+        self.next_token();
+        self.next_token();
+        Some(self.parse_type())
+    }
+
+    // int | bool | string | null | any | [T] | fn(T, ...) -> T
+    pub fn parse_type(&mut self) -> Type {
+        if self.nested_too_deep("type") {
+            return Type::Any;
+        }
+
+        self.depth += 1;
+        let ty = self.parse_type_inner();
+        self.depth -= 1;
+        ty
+    }
+
+    fn parse_type_inner(&mut self) -> Type {
+        match self.cur_token.token_type {
+            TokenType::Ident => match Type::from_name(self.cur_token.literal) {
+                Some(ty) => ty,
+                None => {
+                    self.errors
+                        .push(format!("unknown type: {}", self.cur_token.literal));
+                    Type::Any
+                }
+            },
+            // [T]
+            TokenType::Lbracket => {
+                self.next_token();
+                let elem = self.parse_type();
+                if !self.expect_peek(TokenType::Rbracket) {
+                    return Type::Any;
+                }
+                Type::Array(Box::new(elem))
+            }
+            TokenType::Function => {
+                if !self.expect_peek(TokenType::Lparen) {
+                    return Type::Any;
+                }
+                let mut params = Vec::new();
+                if self.peek_token_is(TokenType::Rparen) {
+                    self.next_token();
+                } else {
+                    loop {
+                        self.next_token();
+                        params.push(self.parse_type());
+                        if !self.peek_token_is(TokenType::Comma) {
+                            break;
+                        }
+                        self.next_token();
+                    }
+                    if !self.expect_peek(TokenType::Rparen) {
+                        return Type::Any;
+                    }
+                }
+                if !self.expect_peek(TokenType::Arrow) {
+                    return Type::Any;
+                }
+                self.next_token();
+                let ret = self.parse_type();
+                Type::Function(params, Box::new(ret))
+            }
+            other => {
+                self.errors
+                    .push(format!("expected a type, got {:?}", other));
+                Type::Any
+            }
+        }
     }
 
     // parse block statement
@@ -444,8 +1072,10 @@ impl Parser {
             let stmt = self.parse_statement();
             match stmt {
                 Statement::None => {
-                    self.errors
-                        .push("failed to parse statement inside block".into());
+                    if !self.too_deep {
+                        self.errors
+                            .push("failed to parse statement inside block".into());
+                    }
                 }
                 _ => statements.push(stmt),
             }
@@ -454,29 +1084,27 @@ impl Parser {
         }
 
         // 返回statements block
-        return Statement::Block(BlockStatement {
-            statements: statements,
-        });
+        Statement::Block(BlockStatement { statements })
     }
 
     // 辅助函数 查看当前tokentype 是否匹配
     pub fn cur_token_is(&self, token_type: TokenType) -> bool {
-        return self.cur_token.token_type == token_type;
+        self.cur_token.token_type == token_type
     }
 
     // 辅助函数 查看下一个tokentype 是否匹配
     pub fn peek_token_is(&self, token_type: TokenType) -> bool {
-        return self.peek_token.token_type == token_type;
+        self.peek_token.token_type == token_type
     }
 
     // 如果接下来的类型是和参数token_type 匹配 滚动下一个next token 然后返回true
     pub fn expect_peek(&mut self, token_type: TokenType) -> bool {
         if self.peek_token_is(token_type) {
             self.next_token();
-            return true;
+            true
         } else {
             self.peek_errors(token_type);
-            return false;
+            false
         }
     }
 
@@ -490,13 +1118,29 @@ impl Parser {
         Precedence::of(self.peek_token.token_type)
     }
 
-    // errors 辅助函数
+    // errors 辅助函数 语法错误在前 resolver 的错误在后
     pub fn errors(&self) -> Vec<String> {
-        return self.errors.clone();
+        let mut errors = self.errors.clone();
+        errors.extend(self.resolve_errors.iter().cloned());
+        errors
+    }
+
+    // 只要语法错误
+    pub fn syntax_errors(&self) -> Vec<String> {
+        self.errors.clone()
+    }
+
+    // 只要 resolver 的错误
+    pub fn resolve_errors(&self) -> Vec<String> {
+        self.resolve_errors.clone()
     }
 
     // peek error 函数 怕出现peek error 然后添加信息到errors
     pub fn peek_errors(&mut self, token_type: TokenType) {
+        // 嵌套太深时后面的 token 都被跳过了 这些错误没有意义
+        if self.too_deep {
+            return;
+        }
         // 先使用debug
         let msg = format!(
             "Expected next token to be {:?}, got {:?} instead",
@@ -505,3 +1149,61 @@ impl Parser {
         self.errors.push(msg);
     }
 }
+
+// 能赋值的: 变量 以及以变量开头的下标和成员链 xs[0].name (不能是 ?. ?[])
+fn is_assignable(e: &Expr) -> bool {
+    match e {
+        Expr::Ident(_) => true,
+        Expr::Index {
+            left,
+            optional: false,
+            ..
+        } => is_assignable(left),
+        Expr::Member {
+            object,
+            optional: false,
+            ..
+        } => is_assignable(object),
+        _ => false,
+    }
+}
+
+// 处理字符串里的转义 \n \t \" \\ \$ 其它的原样保留
+fn unescape(raw: &str) -> String {
+    let mut out = String::with_capacity(raw.len());
+    let mut chars = raw.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => out.push('\n'),
+            Some('t') => out.push('\t'),
+            Some('"') => out.push('"'),
+            Some('\\') => out.push('\\'),
+            Some('$') => out.push('$'),
+            Some(other) => {
+                out.push('\\');
+                out.push(other);
+            }
+            None => out.push('\\'),
+        }
+    }
+    out
+}
+
+// 第一个没有被转义的 ${ 的位置
+fn find_interpolation(raw: &str) -> Option<usize> {
+    let bytes = raw.as_bytes();
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\\' => i += 1,
+            b'$' if bytes.get(i + 1) == Some(&b'{') => return Some(i),
+            _ => {}
+        }
+        i += 1;
+    }
+    None
+}
@@ -1,4 +1,9 @@
-use crate::{evaluator::eval, lexer::Lexer, parser::Parser};
+use crate::{
+    analyzer::Analyzer,
+    evaluator::Interpreter,
+    lexer::{self, Lexer},
+    parser::Parser,
+};
 use nu_ansi_term::{Color, Style};
 use reedline::{
     DefaultHinter, DefaultPrompt, DefaultPromptSegment, Emacs, FileBackedHistory, Highlighter,
@@ -116,6 +121,7 @@ fn is_sym(c: char) -> bool {
             | ','
             | ':'
             | ';'
+            | '.'
             | '='
             | '+'
             | '-'
@@ -137,7 +143,12 @@ fn flush_word_token(tok: &str, out: &mut StyledText, kw: &Style, num: &Style, no
     }
     if tok.chars().all(|c| c.is_ascii_digit()) {
         out.push((*num, tok.to_string()));
-    } else if ["let", "fn", "if", "else", "return", "true", "false"].contains(&tok) {
+    } else if [
+        "let", "const", "fn", "if", "else", "match", "return", "true", "false", "import", "export",
+        "as",
+    ]
+    .contains(&tok)
+    {
         out.push((*kw, tok.to_string()));
     } else {
         out.push((*norm, tok.to_string()));
@@ -200,6 +211,7 @@ fn find_unmatched_brackets(s: &str) -> HashSet<usize> {
     unmatched
 }
 
+#[allow(clippy::too_many_arguments)]
 fn flush_code_segment(
     s: &str,
     out: &mut StyledText,
@@ -221,6 +233,40 @@ fn flush_code_segment(
         let ch = chars[idx];
 
         if *in_string {
+            // 转义字符原样带过 \" 和 \${ 不结束字符串 也不开始插值
+            if ch == '\\' && idx + 1 < chars.len() {
+                buf.push(ch);
+                buf.push(chars[idx + 1]);
+                idx += 2;
+                continue;
+            }
+            // "...${code}..." 里的代码按代码高亮
+            if ch == '$'
+                && *string_delim == Some('"')
+                && let Some(end) = interpolation_end(&chars, idx)
+            {
+                out.push((*stry, buf.clone()));
+                buf.clear();
+                out.push((*sym, "${".to_string()));
+                let code: String = chars[idx + 2..end].iter().collect();
+                let (mut inner_str, mut inner_delim) = (false, None);
+                flush_code_segment(
+                    &code,
+                    out,
+                    buf,
+                    &mut inner_str,
+                    &mut inner_delim,
+                    kw,
+                    num,
+                    sym,
+                    stry,
+                    norm,
+                    &HashSet::new(),
+                );
+                out.push((*sym, "}".to_string()));
+                idx = end + 1;
+                continue;
+            }
             buf.push(ch);
             if Some(ch) == *string_delim {
                 out.push((*stry, buf.clone()));
@@ -277,6 +323,16 @@ fn flush_code_segment(
     }
 }
 
+// chars[open] 是 ${ 的 $ 返回配对的 } 的字符索引
+fn interpolation_end(chars: &[char], open: usize) -> Option<usize> {
+    if chars.get(open + 1) != Some(&'{') {
+        return None;
+    }
+    let rest: String = chars[open..].iter().collect();
+    let len = lexer::interpolation_len(&rest)?;
+    Some(open + rest[..len].chars().count() - 1)
+}
+
 // ---- 多行配平（整段） ----
 fn is_balanced(s: &str) -> bool {
     let (mut r, mut c, mut sq) = (0i32, 0i32, 0i32);
@@ -315,7 +371,8 @@ fn print_parser_errors(errors: &Vec<String>) {
 }
 
 // ---- 入口 ----
-pub fn start() {
+// interp 由调用方配置好 (比如按运行它的线程的栈大小设 max_stack)
+pub fn start(interp: Interpreter) {
     let mut rl = Reedline::create()
         .with_highlighter(Box::new(DarculaHighlighter))
         .with_hinter(Box::new(
@@ -334,6 +391,8 @@ pub fn start() {
 
     println!("🦀 Monkey REPL  (:q 退出)");
     let mut buffer = String::new();
+    // 整个会话共享一个解释器 之前 let 的变量后面还能用 循环垃圾也由它回收
+    let mut interp = interp;
 
     loop {
         // 根据是否在续行，切换提示符
@@ -365,10 +424,17 @@ pub fn start() {
                 let mut parser = Parser::new(lexer);
                 match parser.parse_program() {
                     Some(program) => {
+                        let diagnostics = Analyzer::new()
+                            .with_globals(interp.env().borrow().names())
+                            .analyze(&program);
                         if !parser.errors().is_empty() {
                             print_parser_errors(&parser.errors());
+                        } else if !diagnostics.is_empty() {
+                            for d in &diagnostics {
+                                println!("\t{}\n", d);
+                            }
                         } else {
-                            let evaluated = eval(&program);
+                            let evaluated = interp.eval(&program);
                             println!("{}", evaluated.inspect());
                         }
                     }
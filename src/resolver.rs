@@ -0,0 +1,338 @@
+use std::rc::Rc;
+
+use ahash::AHashSet as HashSet;
+
+use crate::ast::{Address, Expr, Function, Ident, MatchArm, Program, Statement, StringPart};
+
+// 词法地址解析: 给每个标识符标上 (depth, slot)
+//
+// 顶层的名字按名字存在全局环境里 (REPL 每次输入都会加新的全局)
+// 函数的参数和函数体里的 let 各占一个槽位 if block 不开新作用域
+// 所以同一个函数里的 let 不管写在哪个分支里 都落在这个函数的环境上
+// match 的每个分支像函数一样有自己的环境 放模式绑定的名字和分支里的 let
+//
+// 顺便检查给 const 赋值 返回错误消息 (一个作用域里同名的绑定只要有一个是 const
+// 就算常量) 之前输入里定义的常量这里看不到 由运行时检查
+pub fn resolve(program: &mut Program) -> Vec<String> {
+    let mut globals = Vec::new();
+    let mut constants = HashSet::new();
+    for stmt in &program.statements {
+        collect_lets(stmt, &mut globals, &mut constants);
+    }
+    let mut r = Resolver {
+        scopes: Vec::new(),
+        constants: vec![constants],
+        errors: Vec::new(),
+    };
+    for stmt in &mut program.statements {
+        r.statement(stmt);
+    }
+    r.errors
+}
+
+struct Resolver {
+    // 从外到内 每个函数作用域的槽位名字
+    scopes: Vec<Vec<String>>,
+    // 每个作用域里用 const 绑定的名字 第一个是全局 后面和 scopes 一一对应
+    constants: Vec<HashSet<String>>,
+    errors: Vec<String>,
+}
+
+impl Resolver {
+    /* ========== statements ========== */
+
+    fn statement(&mut self, stmt: &mut Statement) {
+        match stmt {
+            Statement::Let { pattern, value, .. } => {
+                self.expr(value);
+                for name in pattern.idents_mut() {
+                    self.ident(name);
+                }
+            }
+            Statement::Import { name, .. } => self.ident(name),
+            Statement::Return(r) => self.expr(&mut r.return_value),
+            Statement::Expression(e) => self.expr(&mut e.expression),
+            Statement::Block(block) => {
+                for s in &mut block.statements {
+                    self.statement(s);
+                }
+            }
+            Statement::None => {}
+        }
+    }
+
+    /* ========== expressions ========== */
+
+    fn expr(&mut self, e: &mut Expr) {
+        match e {
+            Expr::Ident(ident) => self.ident(ident),
+            Expr::Assign { target, value, .. } => {
+                self.expr(target);
+                self.expr(value);
+                // 数组和 hash 是值语义 xs[0] = 1 也是在改常量 xs
+                if let Some(ident) = assigned_ident(target)
+                    && self.is_constant(ident)
+                {
+                    self.errors
+                        .push(format!("cannot assign to constant {}", ident.0));
+                }
+            }
+            Expr::Prefix { right, .. } => self.expr(right),
+            Expr::Infix { left, right, .. } | Expr::Coalesce { left, right } => {
+                self.expr(left);
+                self.expr(right);
+            }
+            Expr::Conditional {
+                condition,
+                consequence,
+                alternative,
+            } => {
+                self.expr(condition);
+                self.expr(consequence);
+                self.expr(alternative);
+            }
+            Expr::IfExpression {
+                condition,
+                consequence,
+                alternative,
+            } => {
+                self.expr(condition);
+                self.statement(consequence);
+                if let Some(alternative) = alternative {
+                    self.statement(alternative);
+                }
+            }
+            Expr::Match { subject, arms } => {
+                self.expr(subject);
+                for arm in arms {
+                    self.arm(arm);
+                }
+            }
+            Expr::Fn(func) => self.function(func),
+            Expr::Call {
+                function,
+                arguments,
+            } => {
+                self.expr(function);
+                for a in arguments {
+                    self.expr(a);
+                }
+            }
+            Expr::Array(elements) => {
+                for e in elements {
+                    self.expr(e);
+                }
+            }
+            Expr::Hash(pairs) => {
+                for (k, v) in pairs {
+                    self.expr(k);
+                    self.expr(v);
+                }
+            }
+            Expr::Index { left, index, .. } => {
+                self.expr(left);
+                self.expr(index);
+            }
+            Expr::Member { object, .. } => self.expr(object),
+            Expr::Interpolated(parts) => {
+                for part in parts {
+                    if let StringPart::Expr(e) = part {
+                        self.expr(e);
+                    }
+                }
+            }
+            Expr::Integer(_) | Expr::Float(_) | Expr::Boolean(_) | Expr::String(_) | Expr::None => {
+            }
+        }
+    }
+
+    fn function(&mut self, func: &mut Function) {
+        // 参数在前 (重名参数共用一个槽位 后面的实参覆盖前面的) 然后是函数体里的 let
+        let mut slots = Vec::new();
+        let mut constants = HashSet::new();
+        for name in func.parameters.iter().flat_map(|p| p.pattern.idents()) {
+            push_unique(&mut slots, &name.0);
+        }
+        collect_lets(&func.body, &mut slots, &mut constants);
+
+        self.scopes.push(slots);
+        self.constants.push(constants);
+        for name in func
+            .parameters
+            .iter_mut()
+            .flat_map(|p| p.pattern.idents_mut())
+        {
+            self.ident(name);
+        }
+        self.statement(&mut func.body);
+        self.constants.pop();
+        let slots = self.scopes.pop().expect("no scope");
+
+        func.slots = Rc::from(slots);
+    }
+
+    fn arm(&mut self, arm: &mut MatchArm) {
+        let mut slots = Vec::new();
+        let mut constants = HashSet::new();
+        for name in arm.pattern.idents() {
+            push_unique(&mut slots, &name.0);
+        }
+        if let Some(guard) = &arm.guard {
+            collect_expr_lets(guard, &mut slots, &mut constants);
+        }
+        collect_expr_lets(&arm.body, &mut slots, &mut constants);
+
+        self.scopes.push(slots);
+        self.constants.push(constants);
+        for name in arm.pattern.idents_mut() {
+            self.ident(name);
+        }
+        if let Some(guard) = &mut arm.guard {
+            self.expr(guard);
+        }
+        self.expr(&mut arm.body);
+        self.constants.pop();
+        let slots = self.scopes.pop().expect("no scope");
+
+        arm.slots = Rc::from(slots);
+    }
+
+    // 从内往外找第一个声明了这个名字的作用域 都没有就是全局
+    fn ident(&mut self, ident: &mut Ident) {
+        ident.1 = self
+            .scopes
+            .iter()
+            .rev()
+            .enumerate()
+            .find_map(|(depth, names)| {
+                let slot = names.iter().position(|n| *n == ident.0)?;
+                Some(Address::Local { depth, slot })
+            })
+            .unwrap_or(Address::Global);
+    }
+
+    // 标识符解析到的那个绑定是不是 const
+    fn is_constant(&self, ident: &Ident) -> bool {
+        let scope = match ident.1 {
+            Address::Local { depth, .. } => self.scopes.len() - depth,
+            Address::Global => 0,
+            Address::Unresolved => return false,
+        };
+        self.constants[scope].contains(&ident.0)
+    }
+}
+
+/* ========== helpers ========== */
+
+// 赋值目标最里面的变量 xs[0].name -> xs
+fn assigned_ident(target: &Expr) -> Option<&Ident> {
+    match target {
+        Expr::Ident(ident) => Some(ident),
+        Expr::Index { left, .. } => assigned_ident(left),
+        Expr::Member { object, .. } => assigned_ident(object),
+        _ => None,
+    }
+}
+
+fn push_unique(slots: &mut Vec<String>, name: &str) {
+    if !slots.iter().any(|n| n == name) {
+        slots.push(name.to_string());
+    }
+}
+
+// 收集一个函数体里的 let 和其中的 const (进入 if 分支 不进入内层函数和 match 分支)
+fn collect_lets(stmt: &Statement, slots: &mut Vec<String>, constants: &mut HashSet<String>) {
+    match stmt {
+        Statement::Let {
+            pattern,
+            constant,
+            value,
+            ..
+        } => {
+            collect_expr_lets(value, slots, constants);
+            for name in pattern.idents() {
+                push_unique(slots, &name.0);
+                if *constant {
+                    constants.insert(name.0.clone());
+                }
+            }
+        }
+        Statement::Import { name, .. } => push_unique(slots, &name.0),
+        Statement::Return(r) => collect_expr_lets(&r.return_value, slots, constants),
+        Statement::Expression(e) => collect_expr_lets(&e.expression, slots, constants),
+        Statement::Block(block) => {
+            for s in &block.statements {
+                collect_lets(s, slots, constants);
+            }
+        }
+        Statement::None => {}
+    }
+}
+
+fn collect_expr_lets(e: &Expr, slots: &mut Vec<String>, constants: &mut HashSet<String>) {
+    match e {
+        Expr::IfExpression {
+            condition,
+            consequence,
+            alternative,
+        } => {
+            collect_expr_lets(condition, slots, constants);
+            collect_lets(consequence, slots, constants);
+            if let Some(alternative) = alternative {
+                collect_lets(alternative, slots, constants);
+            }
+        }
+        Expr::Assign { target, value, .. } => {
+            collect_expr_lets(target, slots, constants);
+            collect_expr_lets(value, slots, constants);
+        }
+        Expr::Prefix { right, .. } => collect_expr_lets(right, slots, constants),
+        Expr::Infix { left, right, .. } | Expr::Coalesce { left, right } => {
+            collect_expr_lets(left, slots, constants);
+            collect_expr_lets(right, slots, constants);
+        }
+        Expr::Conditional {
+            condition,
+            consequence,
+            alternative,
+        } => {
+            collect_expr_lets(condition, slots, constants);
+            collect_expr_lets(consequence, slots, constants);
+            collect_expr_lets(alternative, slots, constants);
+        }
+        Expr::Call {
+            function,
+            arguments,
+        } => {
+            collect_expr_lets(function, slots, constants);
+            for a in arguments {
+                collect_expr_lets(a, slots, constants);
+            }
+        }
+        Expr::Array(elements) => {
+            for e in elements {
+                collect_expr_lets(e, slots, constants);
+            }
+        }
+        Expr::Hash(pairs) => {
+            for (k, v) in pairs {
+                collect_expr_lets(k, slots, constants);
+                collect_expr_lets(v, slots, constants);
+            }
+        }
+        Expr::Index { left, index, .. } => {
+            collect_expr_lets(left, slots, constants);
+            collect_expr_lets(index, slots, constants);
+        }
+        Expr::Member { object, .. } => collect_expr_lets(object, slots, constants),
+        Expr::Match { subject, .. } => collect_expr_lets(subject, slots, constants),
+        Expr::Interpolated(parts) => {
+            for part in parts {
+                if let StringPart::Expr(e) = part {
+                    collect_expr_lets(e, slots, constants);
+                }
+            }
+        }
+        _ => {}
+    }
+}
@@ -0,0 +1,66 @@
+use std::{rc::Rc, sync::OnceLock};
+
+use crate::{
+    ast::{Program, Statement},
+    lexer::Lexer,
+    parser::Parser,
+};
+
+// 用 monkey 写的标准库 源码编进二进制里 可以 import "std/list" as list; 按名字加载
+// prelude 在用户代码之前把这些模块的顶层名字放进全局环境
+pub static MODULES: &[(&str, &str)] = &[("std/list", include_str!("std/list.monkey"))];
+
+pub fn source(path: &str) -> Option<&'static str> {
+    MODULES
+        .iter()
+        .find(|(name, _)| *name == path)
+        .map(|(_, source)| *source)
+}
+
+thread_local! {
+    // 每个解释器都要加载 prelude 解析好的模块在线程里缓存一份
+    static PROGRAMS: Vec<(&'static str, Rc<Program>)> = MODULES
+        .iter()
+        .map(|(name, source)| (*name, Rc::new(parse(source))))
+        .collect();
+}
+
+// 解析好的标准库模块
+pub fn program(path: &str) -> Option<Rc<Program>> {
+    PROGRAMS.with(|programs| {
+        programs
+            .iter()
+            .find(|(name, _)| *name == path)
+            .map(|(_, program)| program.clone())
+    })
+}
+
+fn parse(source: &str) -> Program {
+    Parser::new(Lexer::new(source))
+        .parse_program()
+        .expect("std module should parse")
+}
+
+// prelude 定义的名字 (各模块顶层 let 的名字) analyzer 靠它认出 map filter 这些全局
+pub fn prelude_names() -> &'static [String] {
+    static NAMES: OnceLock<Vec<String>> = OnceLock::new();
+    NAMES.get_or_init(|| {
+        let mut names = Vec::new();
+        for (_, source) in MODULES {
+            for stmt in &parse(source).statements {
+                match stmt {
+                    Statement::Let { pattern, .. } => {
+                        names.extend(pattern.idents().into_iter().map(|name| name.0.clone()))
+                    }
+                    Statement::Import { name, .. } => names.push(name.0.clone()),
+                    _ => {}
+                }
+            }
+        }
+        names
+    })
+}
+
+pub fn is_prelude(name: &str) -> bool {
+    prelude_names().iter().any(|n| n == name)
+}
@@ -11,6 +11,7 @@ pub enum TokenType {
     Ident,
     Int,
     Float,
+    String,
 
     // operator
     Assign,
@@ -19,6 +20,11 @@ pub enum TokenType {
     Bang,
     Asterisk,
     Slash,
+    // compound assignment
+    PlusAssign,
+    MinusAssign,
+    AsteriskAssign,
+    SlashAssign,
 
     Lt,
     Gt,
@@ -27,22 +33,40 @@ pub enum TokenType {
     Ge,
     Le,
 
+    // ?: 和空值相关
+    Question,
+    NullCoalesce,
+    OptionalDot,
+    OptionalBracket,
+
     Comma,
     Semicolon,
+    Colon,
+    Arrow,
+    FatArrow,
+    Dot,
+    Ellipsis,
 
     Lparen,
     Rparen,
     Lbrace,
     Rbrace,
+    Lbracket,
+    Rbracket,
 
     // key words
     Function,
     Let,
+    Const,
     If,
+    Match,
     Return,
     Else,
     True,
     False,
+    Import,
+    Export,
+    As,
 }
 
 // 为TokenType 实现fmt方法为了后续
@@ -56,6 +80,7 @@ impl fmt::Display for TokenType {
             Ident => "ident",
             Int => "int",
             Float => "float",
+            String => "string",
 
             // operator
             Assign => "=",
@@ -64,6 +89,10 @@ impl fmt::Display for TokenType {
             Bang => "!",
             Asterisk => "*",
             Slash => "/",
+            PlusAssign => "+=",
+            MinusAssign => "-=",
+            AsteriskAssign => "*=",
+            SlashAssign => "/=",
 
             Lt => "<",
             Gt => ">",
@@ -72,58 +101,87 @@ impl fmt::Display for TokenType {
             Ge => ">=",
             Le => "<=",
 
+            Question => "?",
+            NullCoalesce => "??",
+            OptionalDot => "?.",
+            OptionalBracket => "?[",
+
             Comma => ",",
             Semicolon => ";",
+            Colon => ":",
+            Arrow => "->",
+            FatArrow => "=>",
+            Dot => ".",
+            Ellipsis => "...",
 
             Lparen => "(",
             Rparen => ")",
             Lbrace => "{",
             Rbrace => "}",
+            Lbracket => "[",
+            Rbracket => "]",
 
             // key words
             Function => "fn",
             Let => "let",
+            Const => "const",
             If => "if",
+            Match => "match",
             Return => "return",
             Else => "else",
             True => "true",
             False => "false",
+            Import => "import",
+            Export => "export",
+            As => "as",
         };
         write!(f, "{}", str)
     }
 }
 
-#[derive(Debug, Clone, Default)]
-pub struct Token {
-    pub token_type: TokenType,
-    pub literal: String,
-}
-
-impl Token {
-    // new function for char
-    pub fn new_with_char(token_type: TokenType, literal: char) -> Token {
-        Token {
-            token_type: token_type,
-            literal: literal.to_string(),
+impl TokenType {
+    // 复合赋值对应的运算符 += -> + 不是复合赋值返回 None
+    pub fn compound_op(self) -> Option<TokenType> {
+        match self {
+            TokenType::PlusAssign => Some(TokenType::Plus),
+            TokenType::MinusAssign => Some(TokenType::Minus),
+            TokenType::AsteriskAssign => Some(TokenType::Asterisk),
+            TokenType::SlashAssign => Some(TokenType::Slash),
+            _ => None,
         }
     }
+}
 
-    // new function for string
-    pub fn new_with_string(token_type: TokenType, literal: String) -> Token {
-        Token {
-            token_type: token_type,
-            literal: literal,
-        }
+// 源码位置 行列都从1开始 列按字节计
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Position {
+    pub line: usize,
+    pub column: usize,
+}
+
+impl fmt::Display for Position {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.line, self.column)
     }
 }
 
-impl PartialEq for Token {
-    fn eq(&self, other: &Self) -> bool {
-        self.literal == other.literal && self.token_type == other.token_type
+// Token 借用源码切片 不再为每个token分配String
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Token<'src> {
+    pub token_type: TokenType,
+    pub literal: &'src str,
+}
+
+impl<'src> Token<'src> {
+    pub fn new(token_type: TokenType, literal: &'src str) -> Token<'src> {
+        Token {
+            token_type,
+            literal,
+        }
     }
 }
 
-impl fmt::Display for Token {
+impl fmt::Display for Token<'_> {
     // for decoration
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         // 类型蓝色
@@ -145,15 +203,20 @@ impl fmt::Display for Token {
     }
 }
 
-pub fn lookup_ident(ident: String) -> TokenType {
-    match ident.as_str() {
-        "fn" => return TokenType::Function,
-        "let" => return TokenType::Let,
-        "if" => return TokenType::If,
-        "else" => return TokenType::Else,
-        "return" => return TokenType::Return,
-        "true" => return TokenType::True,
-        "false" => return TokenType::False,
-        _ => return TokenType::Ident,
+pub fn lookup_ident(ident: &str) -> TokenType {
+    match ident {
+        "fn" => TokenType::Function,
+        "let" => TokenType::Let,
+        "const" => TokenType::Const,
+        "if" => TokenType::If,
+        "match" => TokenType::Match,
+        "else" => TokenType::Else,
+        "return" => TokenType::Return,
+        "true" => TokenType::True,
+        "false" => TokenType::False,
+        "import" => TokenType::Import,
+        "export" => TokenType::Export,
+        "as" => TokenType::As,
+        _ => TokenType::Ident,
     }
 }
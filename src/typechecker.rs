@@ -0,0 +1,780 @@
+use ahash::AHashMap as HashMap;
+
+pub use crate::types::Type;
+use crate::{
+    analyzer::Diagnostic,
+    ast::{Expr, Function, MatchArm, Pattern, Program, Statement, StringPart},
+    token::{Position, TokenType},
+};
+
+// let 绑定的多态类型: vars 里的类型变量每次使用时换成新的
+#[derive(Debug, Clone)]
+struct Scheme {
+    vars: Vec<usize>,
+    ty: Type,
+}
+
+/* ========== public entry ========== */
+
+pub fn check(program: &Program) -> Vec<Diagnostic> {
+    let mut c = Checker::new();
+    c.program(program);
+    c.finish()
+}
+
+// 推导整个程序的类型 (最后一个表达式的值) 用于测试和 REPL 展示
+pub fn infer(program: &Program) -> Result<Type, Vec<Diagnostic>> {
+    let mut c = Checker::new();
+    let ty = c.program(program);
+    let ty = c.normalize(&ty);
+    let diagnostics = c.finish();
+    if diagnostics.is_empty() {
+        Ok(ty)
+    } else {
+        Err(diagnostics)
+    }
+}
+
+struct Checker {
+    // 类型变量的绑定 (union-find 风格 prune 时沿着链走)
+    subst: Vec<Option<Type>>,
+    // 每层函数一个作用域 if block 不开新作用域
+    scopes: Vec<HashMap<String, Scheme>>,
+    // 正在推导的函数的返回类型
+    returns: Vec<Type>,
+    pos: Position,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Checker {
+    fn new() -> Self {
+        Checker {
+            subst: Vec::new(),
+            scopes: vec![HashMap::new()],
+            returns: Vec::new(),
+            pos: Position::default(),
+            diagnostics: Vec::new(),
+        }
+    }
+
+    fn finish(mut self) -> Vec<Diagnostic> {
+        self.diagnostics.sort_by_key(|d| d.pos);
+        self.diagnostics
+    }
+
+    fn program(&mut self, program: &Program) -> Type {
+        self.block(&program.statements)
+    }
+
+    /* ========== statements ========== */
+
+    // 块的值和求值器一致: 最后一个表达式语句的值 没有就是 null
+    fn block(&mut self, stmts: &[Statement]) -> Type {
+        let mut last = Type::Null;
+        for stmt in stmts {
+            if let Some(pos) = stmt.pos() {
+                self.pos = pos;
+            }
+            match stmt {
+                Statement::Let {
+                    pattern, ty, value, ..
+                } => self.let_binding(pattern, ty.as_ref(), value),
+                Statement::Expression(e) => last = self.expr(&e.expression),
+                Statement::Block(b) => last = self.block(&b.statements),
+                Statement::Return(r) => {
+                    let ty = self.expr(&r.return_value);
+                    if let Some(ret) = self.returns.last().cloned()
+                        && !self.unify(&ret, &ty)
+                    {
+                        let msg = format!(
+                            "return type mismatch: {} and {}",
+                            self.show(&ret),
+                            self.show(&ty)
+                        );
+                        self.error(msg);
+                    }
+                    // return 之后的语句执行不到 块的值可以是任意类型
+                    return self.fresh();
+                }
+                // 模块的内容不做推导
+                Statement::Import { name, .. } => self.bind(
+                    &name.0,
+                    Scheme {
+                        vars: vec![],
+                        ty: Type::Any,
+                    },
+                ),
+                Statement::None => {}
+            }
+        }
+        last
+    }
+
+    fn branch(&mut self, stmt: &Statement) -> Type {
+        self.block(std::slice::from_ref(stmt))
+    }
+
+    fn let_binding(&mut self, pattern: &Pattern, annotation: Option<&Type>, value: &Expr) {
+        let Some(name) = pattern.as_ident() else {
+            return self.destructure(pattern, annotation, value);
+        };
+        let name = name.0.as_str();
+        let ty = match value {
+            // 函数可以递归调用自己: 先单态地绑定一个类型变量
+            Expr::Fn(_) => {
+                let var = self.fresh();
+                self.bind(
+                    name,
+                    Scheme {
+                        vars: vec![],
+                        ty: var.clone(),
+                    },
+                );
+                let ty = self.expr(value);
+                self.unify(&var, &ty);
+                // 去掉单态的占位 不然它会挡住泛化
+                self.scopes.last_mut().expect("no scope").remove(name);
+                ty
+            }
+            _ => self.expr(value),
+        };
+        if let Some(ann) = annotation
+            && !self.unify(ann, &ty)
+        {
+            let msg = format!(
+                "let {}: expected {}, got {}",
+                name,
+                self.show(ann),
+                self.show(&ty)
+            );
+            self.error(msg);
+        }
+        let scheme = self.generalize(&ty);
+        self.bind(name, scheme);
+    }
+
+    // let [a, b] = ... / let {name} = ... 每个名字单独泛化
+    fn destructure(&mut self, pattern: &Pattern, annotation: Option<&Type>, value: &Expr) {
+        let ty = self.expr(value);
+        if let Some(ann) = annotation
+            && !self.unify(ann, &ty)
+        {
+            let msg = format!(
+                "let {}: expected {}, got {}",
+                pattern.string(),
+                self.show(ann),
+                self.show(&ty)
+            );
+            self.error(msg);
+        }
+        let mut bindings = Vec::new();
+        self.pattern_types(pattern, &ty, &mut bindings);
+        for (name, ty) in bindings {
+            let scheme = self.generalize(&ty);
+            self.bind(&name, scheme);
+        }
+    }
+
+    // 模式里每个名字的类型 数组模式约束值是数组 hash 的值类型不做推导
+    fn pattern_types(&mut self, pattern: &Pattern, ty: &Type, out: &mut Vec<(String, Type)>) {
+        match pattern {
+            Pattern::Ident(ident) => out.push((ident.0.clone(), ty.clone())),
+            Pattern::Wildcard => {}
+            Pattern::Literal(lit) => {
+                let lit_ty = self.expr(lit);
+                if !self.unify(ty, &lit_ty) {
+                    let msg = format!(
+                        "pattern {} cannot match type {}",
+                        pattern.string(),
+                        self.show(ty)
+                    );
+                    self.error(msg);
+                }
+            }
+            Pattern::Array { elements, rest } => {
+                let mut elem = self.fresh();
+                if !self.unify(ty, &Type::Array(Box::new(elem.clone()))) {
+                    let msg = format!(
+                        "cannot destructure {} of type {}",
+                        pattern.string(),
+                        self.show(ty)
+                    );
+                    self.error(msg);
+                    elem = Type::Any;
+                }
+                for p in elements {
+                    self.pattern_types(p, &elem, out);
+                }
+                if let Some(rest) = rest {
+                    out.push((rest.0.clone(), Type::Array(Box::new(elem))));
+                }
+            }
+            Pattern::Hash(pairs) => {
+                if let ty @ (Type::Int
+                | Type::Bool
+                | Type::String
+                | Type::Null
+                | Type::Array(_)
+                | Type::Function(..)) = self.prune(ty)
+                {
+                    let msg = format!(
+                        "cannot destructure {} of type {}",
+                        pattern.string(),
+                        self.show(&ty)
+                    );
+                    self.error(msg);
+                }
+                for (_, p) in pairs {
+                    self.pattern_types(p, &Type::Any, out);
+                }
+            }
+        }
+    }
+
+    /* ========== expressions ========== */
+
+    fn expr(&mut self, e: &Expr) -> Type {
+        match e {
+            Expr::Integer(_) => Type::Int,
+            Expr::Boolean(_) => Type::Bool,
+            Expr::String(_) => Type::String,
+            // 每一段都用 inspect 转成字符串 什么类型都可以
+            Expr::Interpolated(parts) => {
+                for part in parts {
+                    if let StringPart::Expr(e) = part {
+                        self.expr(e);
+                    }
+                }
+                Type::String
+            }
+            Expr::Array(elements) => {
+                let elem = self.fresh();
+                for e in elements {
+                    let ty = self.expr(e);
+                    if !self.unify(&elem, &ty) {
+                        let msg = format!(
+                            "array elements have different types: {} and {}",
+                            self.show(&elem),
+                            self.show(&ty)
+                        );
+                        self.error(msg);
+                        return Type::Array(Box::new(Type::Any));
+                    }
+                }
+                Type::Array(Box::new(elem))
+            }
+            // hash 的键值类型不做推导
+            Expr::Hash(pairs) => {
+                for (k, v) in pairs {
+                    self.expr(k);
+                    self.expr(v);
+                }
+                Type::Any
+            }
+            Expr::Index {
+                left,
+                index,
+                optional,
+            } => {
+                let container = self.expr(left);
+                let index = self.expr(index);
+                // xs?[i] 允许 xs 是 null
+                if *optional && self.prune(&container) == Type::Null {
+                    return Type::Null;
+                }
+                self.index(&left.string(), &container, &index)
+            }
+            // 和 if 一样 条件不约束 两边要是同一个类型
+            Expr::Conditional {
+                condition,
+                consequence,
+                alternative,
+            } => {
+                self.expr(condition);
+                let cons = self.expr(consequence);
+                let alt = self.expr(alternative);
+                if !self.unify(&cons, &alt) {
+                    let msg = format!(
+                        "?: branches have different types: {} and {}",
+                        self.show(&cons),
+                        self.show(&alt)
+                    );
+                    self.error(msg);
+                    return Type::Any;
+                }
+                cons
+            }
+            // 左边是 null 时得到右边 否则两边应该是同一个类型 (值或者默认值)
+            Expr::Coalesce { left, right } => {
+                let left = self.expr(left);
+                let right = self.expr(right);
+                if self.prune(&left) == Type::Null {
+                    return right;
+                }
+                if self.unify(&left, &right) {
+                    left
+                } else {
+                    Type::Any
+                }
+            }
+            Expr::Member { object, .. } => {
+                self.expr(object);
+                Type::Any
+            }
+            Expr::Float(_) | Expr::None => Type::Null,
+            // 找不到的名字 (后面才定义的全局 或者真的未定义) 交给 analyzer 当作 any
+            Expr::Ident(ident) => match self.lookup(&ident.0) {
+                Some(scheme) => self.instantiate(&scheme),
+                None => Type::Any,
+            },
+            // 赋值不改变变量的类型 复合赋值先按中缀运算推导
+            Expr::Assign { target, op, value } => {
+                let target_ty = self.expr(target);
+                let value_ty = self.expr(value);
+                let errors = self.diagnostics.len();
+                let ty = match op.compound_op() {
+                    Some(op) => self.infix(&op, &target_ty, &value_ty),
+                    None => value_ty,
+                };
+                // 运算本身已经报过错了 不再重复报一次赋值
+                if self.diagnostics.len() > errors {
+                    return target_ty;
+                }
+                if !self.unify(&target_ty, &ty) {
+                    let msg = format!(
+                        "assignment to {}: expected {}, got {}",
+                        target.string(),
+                        self.show(&target_ty),
+                        self.show(&ty)
+                    );
+                    self.error(msg);
+                }
+                ty
+            }
+            Expr::Prefix { op, right } => {
+                let right = self.expr(right);
+                match op {
+                    TokenType::Bang => Type::Bool,
+                    _ => {
+                        if !self.unify(&right, &Type::Int) {
+                            let msg = format!("unknown operator: {}{}", op, self.show(&right));
+                            self.error(msg);
+                        }
+                        Type::Int
+                    }
+                }
+            }
+            Expr::Infix { left, op, right } => {
+                let left = self.expr(left);
+                let right = self.expr(right);
+                self.infix(op, &left, &right)
+            }
+            Expr::IfExpression {
+                condition,
+                consequence,
+                alternative,
+            } => {
+                // 条件是 int/bool/null 都可以 不约束
+                self.expr(condition);
+                let cons = self.branch(consequence);
+                let Some(alternative) = alternative else {
+                    // 没有 else 时值可能是 null
+                    return Type::Any;
+                };
+                let alt = self.branch(alternative);
+                if !self.unify(&cons, &alt) {
+                    let msg = format!(
+                        "if branches have different types: {} and {}",
+                        self.show(&cons),
+                        self.show(&alt)
+                    );
+                    self.error(msg);
+                    return Type::Any;
+                }
+                cons
+            }
+            Expr::Match { subject, arms } => self.match_expr(subject, arms),
+            Expr::Fn(func) => self.function(func),
+            Expr::Call {
+                function,
+                arguments,
+            } => {
+                let callee = self.expr(function);
+                let args: Vec<Type> = arguments.iter().map(|a| self.expr(a)).collect();
+                self.call(&function.string(), &callee, &args)
+            }
+        }
+    }
+
+    // 数组下标必须是 int 其它类型 (hash 或者还不知道) 不做约束
+    fn index(&mut self, name: &str, container: &Type, index: &Type) -> Type {
+        match self.prune(container) {
+            Type::Array(elem) => {
+                if !self.unify(index, &Type::Int) {
+                    let msg = format!("index of {}: expected int, got {}", name, self.show(index));
+                    self.error(msg);
+                }
+                *elem
+            }
+            Type::String => {
+                if !self.unify(index, &Type::Int) {
+                    let msg = format!("index of {}: expected int, got {}", name, self.show(index));
+                    self.error(msg);
+                }
+                Type::String
+            }
+            ty @ (Type::Int | Type::Bool | Type::Null | Type::Function(..)) => {
+                let msg = format!("cannot index {} of type {}", name, self.show(&ty));
+                self.error(msg);
+                Type::Any
+            }
+            _ => Type::Any,
+        }
+    }
+
+    fn infix(&mut self, op: &TokenType, left: &Type, right: &Type) -> Type {
+        let is_string = |t: Type| t == Type::String;
+        let (ok, ty) = match op {
+            // 有一边已知是 string 时 + 是拼接
+            TokenType::Plus if is_string(self.prune(left)) || is_string(self.prune(right)) => {
+                let ok = self.unify(left, &Type::String) & self.unify(right, &Type::String);
+                (ok, Type::String)
+            }
+            TokenType::Plus | TokenType::Minus | TokenType::Asterisk | TokenType::Slash => {
+                let ok = self.unify(left, &Type::Int) & self.unify(right, &Type::Int);
+                (ok, Type::Int)
+            }
+            TokenType::Lt | TokenType::Gt | TokenType::Le | TokenType::Ge => {
+                let ok = self.unify(left, &Type::Int) & self.unify(right, &Type::Int);
+                (ok, Type::Bool)
+            }
+            TokenType::Eq | TokenType::NotEq => (self.unify(left, right), Type::Bool),
+            _ => (false, Type::Any),
+        };
+        if !ok {
+            let msg = format!(
+                "type mismatch: {} {} {}",
+                self.show(left),
+                op,
+                self.show(right)
+            );
+            self.error(msg);
+        }
+        ty
+    }
+
+    fn function(&mut self, func: &Function) -> Type {
+        // 有注解就用注解 没有就是新的类型变量
+        let params: Vec<Type> = func
+            .parameters
+            .iter()
+            .map(|p| p.ty.clone().unwrap_or_else(|| self.fresh()))
+            .collect();
+        let ret = match &func.return_type {
+            Some(ty) => ty.clone(),
+            None => self.fresh(),
+        };
+
+        let mut bindings = Vec::new();
+        for (p, ty) in func.parameters.iter().zip(&params) {
+            self.pattern_types(&p.pattern, ty, &mut bindings);
+        }
+        let scope = bindings
+            .into_iter()
+            .map(|(name, ty)| (name, Scheme { vars: vec![], ty }))
+            .collect();
+        self.scopes.push(scope);
+        self.returns.push(ret.clone());
+
+        let saved = self.pos;
+        let body = self.branch(&func.body);
+        if !self.unify(&ret, &body) {
+            let msg = format!(
+                "return type mismatch: {} and {}",
+                self.show(&ret),
+                self.show(&body)
+            );
+            self.error(msg);
+        }
+        self.pos = saved;
+
+        self.returns.pop();
+        self.scopes.pop();
+        Type::Function(params, Box::new(ret))
+    }
+
+    // 分支的模式检查的是同一种值时 约束主体的类型 (比如都是整数字面量)
+    // 有的分支匹配数字 有的匹配数组时 主体是动态的 当作 any
+    fn match_expr(&mut self, subject: &Expr, arms: &[MatchArm]) -> Type {
+        let mut subject_ty = self.expr(subject);
+        let mut shapes = arms.iter().filter_map(|arm| pattern_shape(&arm.pattern));
+        if let Some(first) = shapes.next()
+            && shapes.any(|shape| shape != first)
+        {
+            subject_ty = Type::Any;
+        }
+
+        let result = self.fresh();
+        let mut ok = true;
+        for arm in arms {
+            let mut bindings = Vec::new();
+            self.pattern_types(&arm.pattern, &subject_ty, &mut bindings);
+            let scope = bindings
+                .into_iter()
+                .map(|(name, ty)| (name, Scheme { vars: vec![], ty }))
+                .collect();
+            self.scopes.push(scope);
+            if let Some(guard) = &arm.guard {
+                self.expr(guard);
+            }
+            let ty = self.expr(&arm.body);
+            self.scopes.pop();
+            if ok && !self.unify(&result, &ty) {
+                let msg = format!(
+                    "match arms have different types: {} and {}",
+                    self.show(&result),
+                    self.show(&ty)
+                );
+                self.error(msg);
+                ok = false;
+            }
+        }
+        if ok { result } else { Type::Any }
+    }
+
+    fn call(&mut self, name: &str, callee: &Type, args: &[Type]) -> Type {
+        match self.prune(callee) {
+            Type::Function(params, ret) => {
+                if params.len() != args.len() {
+                    self.error(format!(
+                        "wrong number of arguments to {}: want={}, got={}",
+                        name,
+                        params.len(),
+                        args.len()
+                    ));
+                    return *ret;
+                }
+                for (i, (p, a)) in params.iter().zip(args).enumerate() {
+                    if !self.unify(p, a) {
+                        let msg = format!(
+                            "argument {} of {}: expected {}, got {}",
+                            i + 1,
+                            name,
+                            self.show(p),
+                            self.show(a)
+                        );
+                        self.error(msg);
+                    }
+                }
+                *ret
+            }
+            var @ Type::Var(_) => {
+                let ret = self.fresh();
+                if !self.unify(&var, &Type::Function(args.to_vec(), Box::new(ret.clone()))) {
+                    // 只有 occurs check 会失败 比如 f(f)
+                    self.error(format!(
+                        "cannot infer a type for {}: it would contain itself",
+                        name
+                    ));
+                    return Type::Any;
+                }
+                ret
+            }
+            Type::Any => Type::Any,
+            other => {
+                let msg = format!("cannot call {} of type {}", name, self.show(&other));
+                self.error(msg);
+                Type::Any
+            }
+        }
+    }
+
+    /* ========== unification ========== */
+
+    fn fresh(&mut self) -> Type {
+        self.subst.push(None);
+        Type::Var(self.subst.len() - 1)
+    }
+
+    // 沿着类型变量的绑定走到头
+    fn prune(&self, ty: &Type) -> Type {
+        match ty {
+            Type::Var(v) => match &self.subst[*v] {
+                Some(t) => self.prune(t),
+                None => ty.clone(),
+            },
+            _ => ty.clone(),
+        }
+    }
+
+    // 失败时不报错 由调用方给出带上下文的信息
+    fn unify(&mut self, a: &Type, b: &Type) -> bool {
+        let (a, b) = (self.prune(a), self.prune(b));
+        match (&a, &b) {
+            (Type::Var(x), Type::Var(y)) if x == y => true,
+            // 类型变量碰到 any 也绑定成 any: 推不出来的地方保持渐进
+            (Type::Var(v), t) | (t, Type::Var(v)) => {
+                if self.occurs(*v, t) {
+                    return false;
+                }
+                self.subst[*v] = Some(t.clone());
+                true
+            }
+            (Type::Any, _) | (_, Type::Any) => true,
+            (Type::Array(x), Type::Array(y)) => self.unify(x, y),
+            (Type::Function(p1, r1), Type::Function(p2, r2)) => {
+                if p1.len() != p2.len() {
+                    return false;
+                }
+                let mut ok = true;
+                for (x, y) in p1.iter().zip(p2) {
+                    ok &= self.unify(x, y);
+                }
+                ok & self.unify(r1, r2)
+            }
+            _ => a == b,
+        }
+    }
+
+    fn occurs(&self, v: usize, ty: &Type) -> bool {
+        match self.prune(ty) {
+            Type::Var(w) => v == w,
+            Type::Array(elem) => self.occurs(v, &elem),
+            Type::Function(params, ret) => {
+                params.iter().any(|p| self.occurs(v, p)) || self.occurs(v, &ret)
+            }
+            _ => false,
+        }
+    }
+
+    // 把已经确定的类型变量全部替换掉
+    fn resolve(&self, ty: &Type) -> Type {
+        match self.prune(ty) {
+            Type::Array(elem) => Type::Array(Box::new(self.resolve(&elem))),
+            Type::Function(params, ret) => Type::Function(
+                params.iter().map(|p| self.resolve(p)).collect(),
+                Box::new(self.resolve(&ret)),
+            ),
+            other => other,
+        }
+    }
+
+    // 报错和展示用: 剩下的类型变量按出现顺序重新编号成 'a 'b ...
+    fn normalize(&self, ty: &Type) -> Type {
+        fn rename(ty: Type, seen: &mut Vec<usize>) -> Type {
+            match ty {
+                Type::Var(v) => match seen.iter().position(|&s| s == v) {
+                    Some(i) => Type::Var(i),
+                    None => {
+                        seen.push(v);
+                        Type::Var(seen.len() - 1)
+                    }
+                },
+                Type::Array(elem) => Type::Array(Box::new(rename(*elem, seen))),
+                Type::Function(params, ret) => Type::Function(
+                    params.into_iter().map(|p| rename(p, seen)).collect(),
+                    Box::new(rename(*ret, seen)),
+                ),
+                other => other,
+            }
+        }
+        rename(self.resolve(ty), &mut Vec::new())
+    }
+
+    fn show(&self, ty: &Type) -> String {
+        self.normalize(ty).to_string()
+    }
+
+    /* ========== schemes ========== */
+
+    fn free_vars(&self, ty: &Type, out: &mut Vec<usize>) {
+        match self.prune(ty) {
+            Type::Var(v) if !out.contains(&v) => out.push(v),
+            Type::Array(elem) => self.free_vars(&elem, out),
+            Type::Function(params, ret) => {
+                for p in &params {
+                    self.free_vars(p, out);
+                }
+                self.free_vars(&ret, out);
+            }
+            _ => {}
+        }
+    }
+
+    // 环境里还在用的类型变量不能泛化 (比如外层函数的参数)
+    fn generalize(&self, ty: &Type) -> Scheme {
+        let mut in_env = Vec::new();
+        for scope in &self.scopes {
+            for scheme in scope.values() {
+                let mut free = Vec::new();
+                self.free_vars(&scheme.ty, &mut free);
+                in_env.extend(free.into_iter().filter(|v| !scheme.vars.contains(v)));
+            }
+        }
+        for ret in &self.returns {
+            self.free_vars(ret, &mut in_env);
+        }
+
+        let mut vars = Vec::new();
+        self.free_vars(ty, &mut vars);
+        vars.retain(|v| !in_env.contains(v));
+        Scheme {
+            vars,
+            ty: self.resolve(ty),
+        }
+    }
+
+    fn instantiate(&mut self, scheme: &Scheme) -> Type {
+        fn replace(ty: &Type, map: &[(usize, Type)]) -> Type {
+            match ty {
+                Type::Var(v) => map
+                    .iter()
+                    .find(|(w, _)| w == v)
+                    .map(|(_, t)| t.clone())
+                    .unwrap_or(Type::Var(*v)),
+                Type::Array(elem) => Type::Array(Box::new(replace(elem, map))),
+                Type::Function(params, ret) => Type::Function(
+                    params.iter().map(|p| replace(p, map)).collect(),
+                    Box::new(replace(ret, map)),
+                ),
+                other => other.clone(),
+            }
+        }
+        let map: Vec<(usize, Type)> = scheme.vars.iter().map(|&v| (v, self.fresh())).collect();
+        replace(&self.resolve(&scheme.ty), &map)
+    }
+
+    /* ========== scopes ========== */
+
+    fn bind(&mut self, name: &str, scheme: Scheme) {
+        self.scopes
+            .last_mut()
+            .expect("no scope")
+            .insert(name.to_string(), scheme);
+    }
+
+    fn lookup(&self, name: &str) -> Option<Scheme> {
+        self.scopes.iter().rev().find_map(|s| s.get(name)).cloned()
+    }
+
+    fn error(&mut self, message: String) {
+        self.diagnostics.push(Diagnostic {
+            pos: self.pos,
+            message,
+        });
+    }
+}
+
+/* ========== helpers ========== */
+
+// match 分支的模式检查的是哪种值 名字和 _ 什么都匹配
+fn pattern_shape(pattern: &Pattern) -> Option<&'static str> {
+    match pattern {
+        Pattern::Ident(_) | Pattern::Wildcard => None,
+        Pattern::Literal(Expr::Integer(_)) => Some("int"),
+        Pattern::Literal(Expr::Boolean(_)) => Some("bool"),
+        Pattern::Literal(_) => Some("string"),
+        Pattern::Array { .. } => Some("array"),
+        Pattern::Hash(_) => Some("hash"),
+    }
+}
@@ -0,0 +1,98 @@
+use std::fmt;
+
+use crate::object::{Object, ObjectType};
+
+// 静态类型 Var 是推导过程中的类型变量 Any 是推不出来时的渐进类型 和什么都兼容
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Type {
+    Int,
+    Bool,
+    String,
+    Null,
+    Any,
+    Array(Box<Type>),
+    Function(Vec<Type>, Box<Type>),
+    Var(usize),
+}
+
+impl Type {
+    // 注解里的类型名
+    pub fn from_name(name: &str) -> Option<Type> {
+        match name {
+            "int" => Some(Type::Int),
+            "bool" => Some(Type::Bool),
+            "string" => Some(Type::String),
+            "null" => Some(Type::Null),
+            "any" => Some(Type::Any),
+            _ => None,
+        }
+    }
+
+    // 运行时检查值是否符合注解 数组逐个检查元素 函数只看类别 不检查参数类型
+    pub fn admits(&self, value: &Object) -> bool {
+        self.mismatch(value).is_none()
+    }
+
+    // 第一个不符合注解的地方: 数组下标路径 (从外到内) 那里期望的类型和实际的类别
+    pub fn mismatch(&self, value: &Object) -> Option<(Vec<usize>, &Type, ObjectType)> {
+        match (self, value) {
+            (Type::Any | Type::Var(_), _) => None,
+            (Type::Array(elem), Object::Array(elements)) => {
+                elements.iter().enumerate().find_map(|(i, v)| {
+                    let (mut path, expected, got) = elem.mismatch(v)?;
+                    path.insert(0, i);
+                    Some((path, expected, got))
+                })
+            }
+            _ if self.object_type() == Some(value.object_type()) => None,
+            _ => Some((Vec::new(), self, value.object_type())),
+        }
+    }
+
+    // 对应的运行时类别 Any 和类型变量没有
+    pub fn object_type(&self) -> Option<ObjectType> {
+        match self {
+            Type::Int => Some(ObjectType::Integer),
+            Type::Bool => Some(ObjectType::Boolean),
+            Type::Null => Some(ObjectType::Null),
+            Type::String => Some(ObjectType::String),
+            Type::Array(_) => Some(ObjectType::Array),
+            Type::Function(..) => Some(ObjectType::Function),
+            _ => None,
+        }
+    }
+}
+
+// 运行时类别在注解里的写法 报错时和期望的类型对得上 hash 和模块没有注解
+pub fn annotation_name(t: ObjectType) -> &'static str {
+    match t {
+        ObjectType::Integer => "int",
+        ObjectType::Boolean => "bool",
+        ObjectType::String => "string",
+        ObjectType::Null => "null",
+        ObjectType::Array => "[any]",
+        ObjectType::Function => "fn",
+        ObjectType::Hash => "hash",
+        ObjectType::Module => "module",
+        ObjectType::Error => "error",
+    }
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Type::Int => write!(f, "int"),
+            Type::Bool => write!(f, "bool"),
+            Type::String => write!(f, "string"),
+            Type::Null => write!(f, "null"),
+            Type::Any => write!(f, "any"),
+            Type::Array(elem) => write!(f, "[{}]", elem),
+            Type::Function(params, ret) => {
+                let params: Vec<String> = params.iter().map(Type::to_string).collect();
+                write!(f, "fn({}) -> {}", params.join(", "), ret)
+            }
+            Type::Var(v) if *v < 26 => write!(f, "'{}", (b'a' + *v as u8) as char),
+            Type::Var(v) => write!(f, "'t{}", v),
+        }
+    }
+}
@@ -0,0 +1,346 @@
+use std::rc::Rc;
+
+use crate::{
+    ast::Program,
+    code::{Opcode, read_u16},
+    compiler::{Bytecode, Compiler},
+    evaluator::{eval_infix_expression, eval_prefix_expression, is_truthy},
+    object::{Closure, CompiledFunction, Object},
+    token::TokenType,
+};
+
+pub const STACK_SIZE: usize = 2048;
+pub const MAX_FRAMES: usize = 1024;
+
+// 调用帧: 正在执行的闭包 + 指令指针 + 局部变量在栈上的起点
+#[derive(Debug)]
+struct Frame {
+    cl: Rc<Closure>,
+    ip: usize,
+    base_pointer: usize,
+}
+
+#[derive(Debug)]
+pub struct Vm {
+    constants: Vec<Object>,
+    // 局部变量槽位在 let 执行前是 None 和树遍历的环境一样 读到时报 identifier not found
+    stack: Vec<Option<Object>>,
+    globals: Vec<Option<Object>>,
+    global_names: Vec<String>,
+    frames: Vec<Frame>,
+    last_popped: Object,
+}
+
+/* ========== public entry ========== */
+
+// 编译并执行 和 evaluator::eval 一样把错误折叠成 Object::Error
+pub fn run(program: &Program) -> Object {
+    let mut compiler = Compiler::new();
+    if let Err(e) = compiler.compile(program) {
+        return Object::Error(e);
+    }
+    Vm::new(compiler.bytecode()).run()
+}
+
+impl Vm {
+    pub fn new(bytecode: Bytecode) -> Vm {
+        let main_fn = Rc::new(CompiledFunction {
+            instructions: bytecode.instructions,
+            num_locals: 0,
+            num_parameters: 0,
+            local_names: Vec::new(),
+            lines: bytecode.lines,
+        });
+        let main_frame = Frame {
+            cl: Rc::new(Closure {
+                func: main_fn,
+                free: Vec::new(),
+            }),
+            ip: 0,
+            base_pointer: 0,
+        };
+
+        let mut frames = Vec::with_capacity(MAX_FRAMES);
+        frames.push(main_frame);
+
+        Vm {
+            constants: bytecode.constants,
+            stack: Vec::with_capacity(STACK_SIZE),
+            globals: vec![None; bytecode.global_names.len()],
+            global_names: bytecode.global_names,
+            frames,
+            last_popped: Object::Null,
+        }
+    }
+
+    // 执行到结束 返回最后弹出的值 或顶层 return 的值
+    pub fn run(&mut self) -> Object {
+        match self.execute() {
+            Ok(v) => v,
+            Err(e) => Object::Error(e),
+        }
+    }
+
+    /* ========== core loop ========== */
+
+    fn execute(&mut self) -> Result<Object, String> {
+        loop {
+            let frame = self.frames.last_mut().expect("no frame");
+            let ins = &frame.cl.func.instructions;
+            if frame.ip >= ins.len() {
+                // 只有主帧会走到指令末尾 函数体总是以 ReturnValue 结束
+                return Ok(std::mem::replace(&mut self.last_popped, Object::Null));
+            }
+
+            let op = Opcode::from_byte(ins[frame.ip])
+                .ok_or_else(|| format!("unknown opcode: {}", ins[frame.ip]))?;
+            let ip = frame.ip + 1;
+            // 操作数直接在这里解码 避免每条指令分配 Vec
+            let (a, b) = match op.operand_widths() {
+                [2] => (read_u16(&ins[ip..]) as usize, 0),
+                [1] => (ins[ip] as usize, 0),
+                [2, 1] => (read_u16(&ins[ip..]) as usize, ins[ip + 2] as usize),
+                _ => (0, 0),
+            };
+            frame.ip = ip + op.operand_widths().iter().sum::<usize>();
+
+            match op {
+                Opcode::Constant => self.push(self.constants[a].clone())?,
+                Opcode::Pop => self.last_popped = self.pop()?,
+                Opcode::Null => self.push(Object::Null)?,
+                Opcode::True => self.push(Object::Boolean(true))?,
+                Opcode::False => self.push(Object::Boolean(false))?,
+
+                Opcode::Add
+                | Opcode::Sub
+                | Opcode::Mul
+                | Opcode::Div
+                | Opcode::Equal
+                | Opcode::NotEqual
+                | Opcode::LessThan
+                | Opcode::GreaterThan
+                | Opcode::LessEqual
+                | Opcode::GreaterEqual => {
+                    let right = self.pop()?;
+                    let left = self.pop()?;
+                    let result = eval_infix_expression(&infix_token(op), left, right);
+                    self.push_result(result)?;
+                }
+
+                Opcode::Minus | Opcode::Plus | Opcode::Bang => {
+                    let right = self.pop()?;
+                    let token = match op {
+                        Opcode::Minus => TokenType::Minus,
+                        Opcode::Plus => TokenType::Plus,
+                        _ => TokenType::Bang,
+                    };
+                    self.push_result(eval_prefix_expression(&token, right))?;
+                }
+
+                Opcode::Jump => self.current_frame().ip = a,
+                Opcode::JumpNotTruthy => {
+                    let cond = self.pop()?;
+                    if !is_truthy(&cond) {
+                        self.current_frame().ip = a;
+                    }
+                }
+
+                Opcode::SetGlobal => self.globals[a] = Some(self.pop()?),
+                Opcode::GetGlobal => match &self.globals[a] {
+                    Some(v) => self.push(v.clone())?,
+                    None => return Err(format!("identifier not found: {}", self.global_names[a])),
+                },
+                Opcode::SetLocal => {
+                    let value = self.pop()?;
+                    let bp = self.current_frame().base_pointer;
+                    let slot = self.stack.get_mut(bp + a);
+                    *slot.ok_or_else(|| format!("local {} out of range", a))? = Some(value);
+                }
+                Opcode::GetLocal => {
+                    let frame = self.frames.last().expect("no frame");
+                    let slot = self.stack.get(frame.base_pointer + a);
+                    match slot
+                        .ok_or_else(|| format!("local {} out of range", a))?
+                        .clone()
+                    {
+                        Some(v) => self.push(v)?,
+                        None => {
+                            let name = frame.cl.func.local_names.get(a).map_or("", |n| n);
+                            return Err(format!("identifier not found: {}", name));
+                        }
+                    }
+                }
+                Opcode::GetFree => {
+                    let v = self.current_frame().cl.free.get(a).cloned();
+                    self.push(v.ok_or_else(|| format!("free variable {} out of range", a))?)?;
+                }
+                Opcode::CurrentClosure => {
+                    let cl = self.current_frame().cl.clone();
+                    self.push(Object::Closure(cl))?;
+                }
+
+                Opcode::Closure => self.push_closure(a, b)?,
+                Opcode::Call => {
+                    if self.in_tail_position() {
+                        self.tail_call(a)?;
+                    } else {
+                        self.call(a)?;
+                    }
+                }
+                Opcode::ReturnValue => {
+                    let value = self.pop()?;
+                    let frame = self.frames.pop().expect("no frame");
+                    if self.frames.is_empty() {
+                        // 顶层 return: 直接结束程序
+                        return Ok(value);
+                    }
+                    // 弹掉参数/局部变量和被调用的闭包本身
+                    self.stack.truncate(frame.base_pointer - 1);
+                    self.push(value)?;
+                }
+            }
+        }
+    }
+
+    /* ========== calls ========== */
+
+    fn call(&mut self, argc: usize) -> Result<(), String> {
+        if self.stack.len() <= argc {
+            return Err("stack underflow".to_string());
+        }
+        let callee = self.stack[self.stack.len() - 1 - argc].clone();
+        let cl = match callee {
+            Some(Object::Closure(cl)) => cl,
+            Some(other) => return Err(format!("not a function: {:?}", other.object_type())),
+            None => return Err("not a function".to_string()),
+        };
+        if cl.func.num_parameters != argc {
+            return Err(format!(
+                "wrong number of arguments: want={}, got={}",
+                cl.func.num_parameters, argc
+            ));
+        }
+        if self.frames.len() >= MAX_FRAMES {
+            return Err("stack overflow".to_string());
+        }
+
+        let base_pointer = self.stack.len() - argc;
+        // 参数已经在栈上 剩下的局部变量等 let 执行时再赋值
+        for _ in argc..cl.func.num_locals {
+            if self.stack.len() >= STACK_SIZE {
+                return Err("stack overflow".to_string());
+            }
+            self.stack.push(None);
+        }
+        self.frames.push(Frame {
+            cl,
+            ip: 0,
+            base_pointer,
+        });
+        Ok(())
+    }
+
+    // 调用后面 (顺着向前的 Jump) 紧跟 ReturnValue 就是尾调用 和树遍历一样复用当前帧
+    // 主帧没有闭包槽位 不参与
+    fn in_tail_position(&self) -> bool {
+        let frame = self.frames.last().expect("no frame");
+        if self.frames.len() == 1 {
+            return false;
+        }
+        let ins = &frame.cl.func.instructions;
+        let mut ip = frame.ip;
+        loop {
+            match ins.get(ip).and_then(|&b| Opcode::from_byte(b)) {
+                Some(Opcode::ReturnValue) => return true,
+                Some(Opcode::Jump) if ip + 2 < ins.len() => {
+                    let target = read_u16(&ins[ip + 1..]) as usize;
+                    if target <= ip {
+                        return false;
+                    }
+                    ip = target;
+                }
+                _ => return false,
+            }
+        }
+    }
+
+    // 把被调用的闭包和参数挪到当前帧的闭包槽位上 再弹掉当前帧
+    fn tail_call(&mut self, argc: usize) -> Result<(), String> {
+        // 被调用的闭包和参数必须都在当前帧自己的栈上 手工构造的 .mkc 可能不是
+        let base_pointer = self.frames.last().expect("no frame").base_pointer;
+        if self.stack.len() < base_pointer + argc + 1 {
+            return Err("stack underflow".to_string());
+        }
+        let frame = self.frames.pop().expect("no frame");
+        let args = self.stack.split_off(self.stack.len() - 1 - argc);
+        self.stack.truncate(frame.base_pointer - 1);
+        self.stack.extend(args);
+        self.call(argc)
+    }
+
+    fn push_closure(&mut self, const_index: usize, num_free: usize) -> Result<(), String> {
+        let func = match &self.constants[const_index] {
+            Object::CompiledFunction(f) => f.clone(),
+            other => return Err(format!("not a function: {:?}", other)),
+        };
+        if self.stack.len() < num_free {
+            return Err("stack underflow".to_string());
+        }
+        // 编译器只会捕获 GetLocal/GetFree 压上来的值 空槽位说明指令是手工构造的
+        let free = self
+            .stack
+            .split_off(self.stack.len() - num_free)
+            .into_iter()
+            .collect::<Option<Vec<_>>>()
+            .ok_or_else(|| "stack underflow".to_string())?;
+        self.push(Object::Closure(Rc::new(Closure { func, free })))
+    }
+
+    /* ========== stack helpers ========== */
+
+    #[inline(always)]
+    fn current_frame(&mut self) -> &mut Frame {
+        self.frames.last_mut().expect("no frame")
+    }
+
+    #[inline(always)]
+    fn push(&mut self, obj: Object) -> Result<(), String> {
+        if self.stack.len() >= STACK_SIZE {
+            return Err("stack overflow".to_string());
+        }
+        self.stack.push(Some(obj));
+        Ok(())
+    }
+
+    // 编译器生成的指令不会弹空 这里防的是手工构造的 .mkc
+    #[inline(always)]
+    fn pop(&mut self) -> Result<Object, String> {
+        self.stack
+            .pop()
+            .flatten()
+            .ok_or_else(|| "stack underflow".to_string())
+    }
+
+    // 共享的运算函数用 Object::Error 表示失败 在 vm 里转成终止执行
+    fn push_result(&mut self, result: Object) -> Result<(), String> {
+        match result {
+            Object::Error(e) => Err(e),
+            v => self.push(v),
+        }
+    }
+}
+
+fn infix_token(op: Opcode) -> TokenType {
+    match op {
+        Opcode::Add => TokenType::Plus,
+        Opcode::Sub => TokenType::Minus,
+        Opcode::Mul => TokenType::Asterisk,
+        Opcode::Div => TokenType::Slash,
+        Opcode::Equal => TokenType::Eq,
+        Opcode::NotEqual => TokenType::NotEq,
+        Opcode::LessThan => TokenType::Lt,
+        Opcode::GreaterThan => TokenType::Gt,
+        Opcode::LessEqual => TokenType::Le,
+        _ => TokenType::Ge,
+    }
+}
@@ -0,0 +1,191 @@
+#[cfg(test)]
+mod analyzer_test {
+    use monkeycc::{
+        analyzer::{Analyzer, analyze},
+        ast::Program,
+        lexer::Lexer,
+        parser::Parser,
+    };
+
+    fn parse(input: &str) -> Program {
+        let mut p = Parser::new(Lexer::new(input));
+        let program = p.parse_program().unwrap();
+        assert!(p.errors().is_empty(), "{:?}", p.errors());
+        program
+    }
+
+    fn messages(input: &str) -> Vec<String> {
+        analyze(&parse(input))
+            .into_iter()
+            .map(|d| d.message)
+            .collect()
+    }
+
+    #[test]
+    fn test_clean_programs() {
+        let tests = [
+            "let x = 5; x + 1;",
+            "let add = fn(a, b) { a + b }; add(1, 2);",
+            // 函数体里可以引用后面才定义的全局 调用时已经绑定了
+            "let f = fn() { g() }; let g = fn() { 1 }; f();",
+            "let fact = fn(n) { if (n < 2) { 1 } else { n * fact(n - 1) } }; fact(5);",
+            "let adder = fn(x) { fn(y) { x + y } }; adder(1)(2);",
+            "fn(x) { x }(1);",
+            // 重新绑定成不同参数个数的函数 不再检查
+            "let f = fn(a) { a }; if (true) { let f = fn(a, b) { a }; } f(1, 2);",
+            "return 1;",
+            // 内建函数和 prelude 里的名字 用户可以覆盖内建函数
+            "puts(len([1]), map([1], fn(x) { x }));",
+            "let len = fn(a, b) { a }; len(1, 2);",
+        ];
+
+        for input in tests {
+            assert_eq!(messages(input), Vec::<String>::new(), "input: {}", input);
+        }
+    }
+
+    #[test]
+    fn test_undefined_identifiers() {
+        let tests = [
+            ("x + 1;", vec!["undefined identifier: x"]),
+            ("let y = y + 1;", vec!["undefined identifier: y"]),
+            ("y; let y = 1;", vec!["undefined identifier: y"]),
+            ("fn(a) { a + b }", vec!["undefined identifier: b"]),
+            // 参数只在函数体里可见
+            ("let f = fn(a) { a }; a;", vec!["undefined identifier: a"]),
+            ("fn() { z; let z = 1; }", vec!["undefined identifier: z"]),
+        ];
+
+        for (input, expected) in tests {
+            assert_eq!(messages(input), expected, "input: {}", input);
+        }
+    }
+
+    #[test]
+    fn test_duplicate_parameters() {
+        assert_eq!(
+            messages("let f = fn(a, b, a) { a };"),
+            vec!["duplicate parameter: a"]
+        );
+    }
+
+    #[test]
+    fn test_destructured_names() {
+        assert!(messages("let [a, {b}] = [1, {\"b\": 2}]; a + b").is_empty());
+        assert_eq!(
+            messages("let f = fn([a, b], a) { a };"),
+            vec!["duplicate parameter: a"]
+        );
+        assert_eq!(
+            messages("let [a] = [1]; c"),
+            vec!["undefined identifier: c"]
+        );
+    }
+
+    #[test]
+    fn test_match_arms() {
+        assert!(messages("let x = 1; match (x) { [a, ...b] if a => b, n => n + x }").is_empty());
+        // 分支绑定的名字出了分支就没有了
+        assert_eq!(
+            messages("match (1) { n => n }; n"),
+            vec!["undefined identifier: n"]
+        );
+        assert_eq!(
+            messages("match (1) { n if m => n }"),
+            vec!["undefined identifier: m"]
+        );
+    }
+
+    #[test]
+    fn test_calling_non_functions() {
+        let tests = [
+            ("5(1);", "cannot call non-function 5"),
+            ("true();", "cannot call non-function true"),
+            ("(1 + 2)(3);", "cannot call non-function (1 + 2)"),
+            (r#""f"();"#, r#"cannot call non-function "f""#),
+            ("[1](0);", "cannot call non-function [1]"),
+        ];
+
+        for (input, expected) in tests {
+            assert_eq!(messages(input), vec![expected], "input: {}", input);
+        }
+    }
+
+    #[test]
+    fn test_arity_mismatch() {
+        let tests = [
+            (
+                "let add = fn(a, b) { a + b }; add(1);",
+                "wrong number of arguments to add: want=2, got=1",
+            ),
+            (
+                "fn(x) { x }(1, 2);",
+                "wrong number of arguments to fn(x) x: want=1, got=2",
+            ),
+            (
+                "let f = fn() { g(1) }; let g = fn() { 1 };",
+                "wrong number of arguments to g: want=0, got=1",
+            ),
+            (
+                "len(1, 2);",
+                "wrong number of arguments to len: want=1, got=2",
+            ),
+        ];
+
+        for (input, expected) in tests {
+            assert_eq!(messages(input), vec![expected], "input: {}", input);
+        }
+    }
+
+    #[test]
+    fn test_assignment() {
+        let tests = [
+            ("let x = 1; x = 2; x += 1;", vec![]),
+            ("let xs = [1]; xs[0] = fn(a) { a };", vec![]),
+            // 闭包给外层的变量赋值
+            ("let n = 0; let inc = fn() { n += 1 }; inc();", vec![]),
+            // 赋值之后不知道是几个参数的函数了
+            (
+                "let f = fn(a) { a }; f = fn(a, b) { a + b }; f(1, 2);",
+                vec![],
+            ),
+            ("y = 1;", vec!["assignment to undeclared variable: y"]),
+            (
+                "fn() { z = 1; let z = 0; }",
+                vec!["assignment to undeclared variable: z"],
+            ),
+            // 内建函数不在环境里 不能赋值
+            ("len = 1;", vec!["assignment to undeclared variable: len"]),
+            ("missing[0] = 1;", vec!["undefined identifier: missing"]),
+            ("let h = {}; h[k] = 1;", vec!["undefined identifier: k"]),
+        ];
+
+        for (input, expected) in tests {
+            assert_eq!(messages(input), expected, "input: {}", input);
+        }
+    }
+
+    #[test]
+    fn test_top_level_return() {
+        let program = parse("let f = fn() { return 1; }; return f();");
+        assert!(analyze(&program).is_empty());
+
+        let diagnostics = Analyzer::new()
+            .allow_top_level_return(false)
+            .analyze(&program);
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].message, "return outside of a function");
+        assert_eq!(
+            diagnostics[0].to_string(),
+            "1:29: return outside of a function"
+        );
+    }
+
+    #[test]
+    fn test_known_globals_and_positions() {
+        let program = parse("let a = 1;\nb + c;");
+        let diagnostics = Analyzer::new().with_globals(["b"]).analyze(&program);
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].to_string(), "2:1: undefined identifier: c");
+    }
+}
@@ -0,0 +1,187 @@
+#[cfg(test)]
+mod artifact_test {
+    use std::rc::Rc;
+
+    use monkeycc::{
+        artifact::{self, MAGIC, VERSION},
+        code::{Opcode, make},
+        compiler::{Bytecode, Compiler},
+        lexer::Lexer,
+        object::{CompiledFunction, Object},
+        parser::Parser,
+        vm::Vm,
+    };
+
+    fn compile(input: &str) -> Bytecode {
+        let program = Parser::new(Lexer::new(input)).parse_program().unwrap();
+        let mut compiler = Compiler::new();
+        compiler.compile(&program).unwrap();
+        compiler.bytecode()
+    }
+
+    const FIB: &str = r"
+        let fib = fn(n) {
+            if (n < 2) { return n; }
+            fib(n - 1) + fib(n - 2)
+        };
+        fib(10);";
+
+    #[test]
+    fn test_round_trip() {
+        let bytecode = compile(FIB);
+        let bytes = artifact::encode(&bytecode).unwrap();
+        assert!(artifact::is_artifact(&bytes));
+
+        let loaded = artifact::decode(&bytes).unwrap();
+        assert_eq!(loaded.instructions, bytecode.instructions);
+        assert_eq!(loaded.global_names, bytecode.global_names);
+        assert_eq!(loaded.lines, bytecode.lines);
+        assert_eq!(loaded.disassemble(), bytecode.disassemble());
+        assert_eq!(Vm::new(loaded).run().inspect(), "55");
+    }
+
+    #[test]
+    fn test_line_table() {
+        let bytecode = compile(FIB);
+        // 主程序: let fib 在第2行 调用在第6行
+        let lines: Vec<usize> = bytecode.lines.iter().map(|&(_, line)| line).collect();
+        assert_eq!(lines, vec![2, 6]);
+    }
+
+    #[test]
+    fn test_rejects_bad_input() {
+        let bytes = artifact::encode(&compile("1 + 2")).unwrap();
+
+        let mut bad_magic = bytes.clone();
+        bad_magic[0] = b'X';
+        assert!(artifact::decode(&bad_magic).unwrap_err().contains("magic"));
+
+        let mut bad_version = bytes.clone();
+        bad_version[MAGIC.len()..MAGIC.len() + 2].copy_from_slice(&(VERSION + 1).to_be_bytes());
+        assert!(
+            artifact::decode(&bad_version)
+                .unwrap_err()
+                .contains("version")
+        );
+
+        let truncated = &bytes[..bytes.len() - 3];
+        assert!(artifact::decode(truncated).is_err());
+    }
+
+    // 一条指令: 操作码 + 操作数
+    type Ins<'a> = (Opcode, &'a [usize]);
+
+    // 手工拼一个 .mkc: 主程序指令 + 函数常量 (局部变量个数, 指令)
+    fn craft(main: &[Ins], functions: &[(usize, &[Ins])]) -> Vec<u8> {
+        let code = |ins: &[Ins]| -> Vec<u8> {
+            ins.iter()
+                .flat_map(|(op, operands)| make(*op, operands))
+                .collect()
+        };
+        let constants = functions
+            .iter()
+            .map(|(num_locals, body)| {
+                Object::CompiledFunction(Rc::new(CompiledFunction {
+                    instructions: code(body),
+                    num_locals: *num_locals,
+                    num_parameters: 0,
+                    local_names: Vec::new(),
+                    lines: Vec::new(),
+                }))
+            })
+            .collect();
+        let bytecode = Bytecode {
+            instructions: code(main),
+            constants,
+            global_names: Vec::new(),
+            lines: Vec::new(),
+        };
+        artifact::encode(&bytecode).unwrap()
+    }
+
+    #[test]
+    fn test_rejects_bad_references() {
+        let tests: Vec<(Vec<u8>, &str)> = vec![
+            // 主程序没有局部变量
+            (
+                craft(&[(Opcode::GetLocal, &[5])], &[]),
+                "local 5 out of range",
+            ),
+            (
+                craft(&[(Opcode::True, &[]), (Opcode::SetLocal, &[0])], &[]),
+                "local 0 out of range",
+            ),
+            // 函数只有一个局部变量
+            (
+                craft(
+                    &[(Opcode::Closure, &[0, 0]), (Opcode::Call, &[0])],
+                    &[(1, &[(Opcode::GetLocal, &[5]), (Opcode::ReturnValue, &[])])],
+                ),
+                "local 5 out of range",
+            ),
+            // 创建时没有捕获任何自由变量
+            (
+                craft(
+                    &[(Opcode::Closure, &[0, 0]), (Opcode::Call, &[0])],
+                    &[(0, &[(Opcode::GetFree, &[0]), (Opcode::ReturnValue, &[])])],
+                ),
+                "free variable 0 out of range",
+            ),
+            (
+                craft(&[(Opcode::GetFree, &[0])], &[]),
+                "free variable 0 out of range",
+            ),
+            // 跳到 OpConstant 的操作数中间
+            (
+                craft(
+                    &[(Opcode::Jump, &[4]), (Opcode::Constant, &[0])],
+                    &[(0, &[])],
+                ),
+                "jump target 4 out of range",
+            ),
+        ];
+        for (bytes, expected) in tests {
+            let err = artifact::decode(&bytes).unwrap_err();
+            assert!(err.contains(expected), "{}", err);
+        }
+    }
+
+    // 弹空栈过不了加载检查 vm 运行时报错而不是崩溃
+    #[test]
+    fn test_stack_underflow() {
+        let tests = [
+            craft(&[(Opcode::Pop, &[])], &[]),
+            craft(&[(Opcode::Add, &[])], &[]),
+            craft(&[(Opcode::Call, &[3])], &[]),
+            craft(
+                &[(Opcode::Closure, &[0, 2])],
+                &[(0, &[(Opcode::Null, &[]), (Opcode::ReturnValue, &[])])],
+            ),
+            craft(
+                &[(Opcode::Closure, &[0, 0]), (Opcode::Call, &[0])],
+                &[(
+                    0,
+                    &[
+                        (Opcode::Pop, &[]),
+                        (Opcode::Pop, &[]),
+                        (Opcode::ReturnValue, &[]),
+                    ],
+                )],
+            ),
+            // 尾位置的调用 参数比当前帧栈上的值多
+            craft(
+                &[(Opcode::Closure, &[0, 0]), (Opcode::Call, &[0])],
+                &[(0, &[(Opcode::Call, &[5]), (Opcode::ReturnValue, &[])])],
+            ),
+            // 捕获一个还没赋值的局部变量槽位
+            craft(
+                &[(Opcode::Closure, &[0, 0]), (Opcode::Call, &[0])],
+                &[(1, &[(Opcode::Closure, &[0, 1]), (Opcode::ReturnValue, &[])])],
+            ),
+        ];
+        for bytes in tests {
+            let loaded = artifact::decode(&bytes).unwrap();
+            assert_eq!(Vm::new(loaded).run().inspect(), "Error: stack underflow");
+        }
+    }
+}
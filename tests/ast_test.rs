@@ -1,6 +1,7 @@
 #[cfg(test)]
 mod ast_test {
-    use monkeycc::ast::{Expr, Ident, Program, Statement};
+    use monkeycc::ast::{Expr, Ident, Pattern, Program, Statement};
+    use monkeycc::token::Position;
 
     // 测试String功能是否正常
     #[test]
@@ -9,8 +10,11 @@ mod ast_test {
             statements: Vec::new(),
         };
         let let_stmt = Statement::Let {
-            name: Ident("myVar".to_string()),
-            value: Expr::Ident(Ident("anotherVar".to_string())),
+            pattern: Pattern::Ident(Ident::new("myVar")),
+            constant: false,
+            ty: None,
+            value: Expr::Ident(Ident::new("anotherVar")),
+            pos: Position::default(),
         };
         program.statements.push(let_stmt);
         assert_eq!("let myVar = anotherVar;".to_string(), program.string())
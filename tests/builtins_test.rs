@@ -0,0 +1,214 @@
+#[cfg(test)]
+mod builtins_test {
+    use std::rc::Rc;
+
+    use monkeycc::{
+        builtins::{self, BUILTINS},
+        evaluator::eval,
+        lexer::Lexer,
+        object::Object,
+        parser::Parser,
+    };
+
+    fn run(input: &str) -> String {
+        let program = Parser::new(Lexer::new(input)).parse_program().unwrap();
+        eval(&program).inspect()
+    }
+
+    #[test]
+    fn test_builtins() {
+        let tests = [
+            (r#"len("")"#, "0"),
+            (r#"len("héllo, 世界")"#, "9"),
+            ("len([1, 2, 3])", "3"),
+            (r#"len({"a": 1})"#, "1"),
+            ("first([1, 2, 3])", "1"),
+            ("first([])", "null"),
+            ("last([1, 2, 3])", "3"),
+            ("rest([1, 2, 3])", "[2, 3]"),
+            ("rest([1])", "[]"),
+            ("rest([])", "null"),
+            ("push([], 1)", "[1]"),
+            // push 不改原来的数组
+            (
+                "let xs = [1]; let ys = push(xs, 2); [xs, ys]",
+                "[[1], [1, 2]]",
+            ),
+            ("array(3, 0)", "[0, 0, 0]"),
+            ("array(0, 1)", "[]"),
+            ("let xs = array(2, []); xs[1] = 5; xs", "[[], 5]"),
+            ("puts()", "null"),
+            ("len", "<builtin len>"),
+            // 用户的定义优先
+            ("let len = fn(x) { 0 }; len([1])", "0"),
+        ];
+        for (input, expected) in tests {
+            assert_eq!(run(input), expected, "input: {}", input);
+        }
+    }
+
+    #[test]
+    fn test_argument_checks() {
+        let tests = [
+            ("len()", "wrong number of arguments to len: want=1, got=0"),
+            (
+                "len([1], [2])",
+                "wrong number of arguments to len: want=1, got=2",
+            ),
+            (
+                "len(1)",
+                "argument 1 to len must be String, Array or Hash, got Integer",
+            ),
+            (
+                r#"first("ab")"#,
+                "argument 1 to first must be Array, got String",
+            ),
+            (
+                "push(1, [])",
+                "argument 1 to push must be Array, got Integer",
+            ),
+            (
+                "push([])",
+                "wrong number of arguments to push: want=2, got=1",
+            ),
+            ("array(-1, 0)", "array length must not be negative, got -1"),
+        ];
+        for (input, expected) in tests {
+            assert_eq!(
+                run(input),
+                format!("Error: {}", expected),
+                "input: {}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn test_string_builtins() {
+        let tests = [
+            (r#"split("a,b,,c", ",")"#, "[a, b, , c]"),
+            (r#"split("héllo", "")"#, "[h, é, l, l, o]"),
+            (r#"join(["a", 1, true, [2]], "-")"#, "a-1-true-[2]"),
+            (r#"join([], ", ")"#, ""),
+            (r#"trim("  hi \n")"#, "hi"),
+            (r#"upper("straße")"#, "STRASSE"),
+            (r#"lower("ÀB")"#, "àb"),
+            (r#"contains("monkey", "key")"#, "true"),
+            (r#"contains("monkey", "")"#, "true"),
+            (r#"starts_with("monkey", "mon")"#, "true"),
+            (r#"starts_with("monkey", "key")"#, "false"),
+            (r#"replace("a-b-c", "-", "+")"#, "a+b+c"),
+            (r#"replace("abc", "", "x")"#, "abc"),
+            (r#"substr("héllo wörld", 1, 4)"#, "éllo"),
+            (r#"substr("héllo", 3, 100)"#, "lo"),
+            (r#"substr("héllo", 10, 2)"#, ""),
+            (r#"slice("日本語です", 1, 3)"#, "本語"),
+            (r#"slice("abc", -5, 2)"#, "ab"),
+            (r#"slice("abc", 2, 1)"#, ""),
+            ("slice([1, 2, 3, 4], 1, 3)", "[2, 3]"),
+            ("slice([1, 2, 3], 2, 10)", "[3]"),
+            ("slice([1, 2, 3], 3, 1)", "[]"),
+            (r#"chars("añb")"#, "[a, ñ, b]"),
+            (r#"chars("")"#, "[]"),
+            (r#"parse_int("42")"#, "42"),
+            (r#"parse_int(" -7 ")"#, "-7"),
+            (r#"parse_int("4x")"#, "null"),
+            ("to_string(12)", "12"),
+            (r#"to_string([1, "a"])"#, "[1, a]"),
+            (r#"format("{} + {} = {}", 1, 2, 1 + 2)"#, "1 + 2 = 3"),
+            (r#"format("{{}} {}", "x")"#, "{} x"),
+            (r#"format("no placeholders")"#, "no placeholders"),
+            (r#""añb"[1]"#, "ñ"),
+            (r#""añb"[3]"#, "null"),
+            (r#"len("👋🏽")"#, "2"),
+        ];
+        for (input, expected) in tests {
+            assert_eq!(run(input), expected, "input: {}", input);
+        }
+
+        let tests = [
+            (
+                r#"split("a", 1)"#,
+                "argument 2 to split must be String, got Integer",
+            ),
+            (
+                r#"slice({}, 0, 1)"#,
+                "argument 1 to slice must be String or Array, got Hash",
+            ),
+            (
+                r#"substr("abc", "1", 2)"#,
+                "argument 2 to substr must be Integer, got String",
+            ),
+            (
+                "format()",
+                "wrong number of arguments to format: want at least 1, got=0",
+            ),
+            (
+                r#"format(1, 2)"#,
+                "argument 1 to format must be String, got Integer",
+            ),
+            (
+                r#"format("{} {}", 1)"#,
+                r#"format: not enough arguments for "{} {}""#,
+            ),
+            (
+                r#"format("{}", 1, 2)"#,
+                r#"format: 1 placeholder(s) in "{}" but 2 argument(s)"#,
+            ),
+        ];
+        for (input, expected) in tests {
+            assert_eq!(
+                run(input),
+                format!("Error: {}", expected),
+                "input: {}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn test_registry() {
+        assert_eq!(builtins::lookup("len").unwrap().arity(), Some(1));
+        assert_eq!(builtins::lookup("puts").unwrap().arity(), None);
+        assert!(builtins::lookup("nope").is_none());
+
+        // 每个参数至少有一个位置 名字不重复
+        for (i, b) in BUILTINS.iter().enumerate() {
+            assert!(!b.params.is_empty(), "{}", b.name);
+            assert!(BUILTINS[..i].iter().all(|other| other.name != b.name));
+        }
+        assert!(matches!(
+            builtins::lookup("first")
+                .unwrap()
+                .call(vec![Object::array(vec![])]),
+            Object::Null
+        ));
+    }
+
+    #[test]
+    fn test_push_in_place() {
+        let push = builtins::lookup("push").unwrap();
+        let ptr = |o: &Object| match o {
+            Object::Array(elements) => Rc::as_ptr(elements),
+            _ => panic!("not an array: {:?}", o),
+        };
+
+        // 没有别人引用的数组原地追加
+        let xs = Object::array(vec![Object::Integer(1)]);
+        let before = ptr(&xs);
+        let ys = push.call(vec![xs, Object::Integer(2)]);
+        assert_eq!(ptr(&ys), before);
+        assert_eq!(ys.inspect(), "[1, 2]");
+
+        // 共享的数组先复制 原来的不变
+        let zs = push.call(vec![ys.clone(), Object::Integer(3)]);
+        assert_ne!(ptr(&zs), ptr(&ys));
+        assert_eq!(ys.inspect(), "[1, 2]");
+        assert_eq!(zs.inspect(), "[1, 2, 3]");
+
+        // 递归返回的数组是独占的 一路原地追加
+        let input =
+            "let fill = fn(n) { if (n == 0) { [] } else { push(fill(n - 1), n) } }; fill(5)";
+        assert_eq!(run(input), "[1, 2, 3, 4, 5]");
+    }
+}
@@ -0,0 +1,94 @@
+#[cfg(test)]
+mod code_test {
+    use monkeycc::code::{Opcode, check_operands, disassemble, make, read_operands};
+
+    #[test]
+    fn test_make() {
+        let tests: Vec<(Opcode, Vec<usize>, Vec<u8>)> = vec![
+            (
+                Opcode::Constant,
+                vec![65534],
+                vec![Opcode::Constant as u8, 255, 254],
+            ),
+            (Opcode::Add, vec![], vec![Opcode::Add as u8]),
+            (
+                Opcode::GetLocal,
+                vec![255],
+                vec![Opcode::GetLocal as u8, 255],
+            ),
+            (
+                Opcode::Closure,
+                vec![65534, 255],
+                vec![Opcode::Closure as u8, 255, 254, 255],
+            ),
+        ];
+
+        for (op, operands, expected) in tests {
+            assert_eq!(make(op, &operands), expected, "{}", op.name());
+        }
+    }
+
+    #[test]
+    fn test_read_operands() {
+        let tests: Vec<(Opcode, Vec<usize>, usize)> = vec![
+            (Opcode::Constant, vec![65535], 2),
+            (Opcode::GetLocal, vec![255], 1),
+            (Opcode::Closure, vec![65535, 255], 3),
+        ];
+
+        for (op, operands, bytes_read) in tests {
+            let ins = make(op, &operands);
+            let (read, n) = read_operands(op, &ins[1..]);
+            assert_eq!(n, bytes_read);
+            assert_eq!(read, operands);
+        }
+    }
+
+    #[test]
+    fn test_check_operands() {
+        assert!(check_operands(Opcode::Constant, &[65535]).is_ok());
+        assert!(check_operands(Opcode::Closure, &[65535, 255]).is_ok());
+        assert!(check_operands(Opcode::GetLocal, &[255]).is_ok());
+
+        let tests: Vec<(Opcode, Vec<usize>, &str)> = vec![
+            (Opcode::Constant, vec![65536], "constant index 65536"),
+            (Opcode::Jump, vec![70000], "jump target 70000"),
+            (Opcode::SetLocal, vec![256], "local index 256"),
+            (Opcode::Call, vec![300], "argument count 300"),
+            (Opcode::Closure, vec![1, 256], "free variable count 256"),
+        ];
+        for (op, operands, expected) in tests {
+            let err = check_operands(op, &operands).unwrap_err();
+            assert!(err.contains(expected), "{}", err);
+        }
+    }
+
+    #[test]
+    fn test_opcode_round_trip() {
+        let mut byte = 0u8;
+        while let Some(op) = Opcode::from_byte(byte) {
+            assert_eq!(op as u8, byte, "{}", op.name());
+            byte += 1;
+        }
+        assert_eq!(byte, Opcode::Closure as u8 + 1);
+    }
+
+    #[test]
+    fn test_disassemble() {
+        let ins: Vec<u8> = [
+            make(Opcode::Add, &[]),
+            make(Opcode::GetLocal, &[1]),
+            make(Opcode::Constant, &[2]),
+            make(Opcode::Constant, &[65535]),
+            make(Opcode::Closure, &[65535, 255]),
+        ]
+        .concat();
+
+        let expected = "0000 OpAdd\n\
+                        0001 OpGetLocal 1\n\
+                        0003 OpConstant 2\n\
+                        0006 OpConstant 65535\n\
+                        0009 OpClosure 65535 255\n";
+        assert_eq!(disassemble(&ins, &[]), expected);
+    }
+}
@@ -0,0 +1,130 @@
+#[cfg(test)]
+mod gc_test {
+    use monkeycc::{
+        ast::Program,
+        evaluator::Interpreter,
+        lexer::Lexer,
+        object::{FunctionObject, Object},
+        parser::Parser,
+    };
+
+    fn parse(input: &str) -> Program {
+        Parser::new(Lexer::new(input)).parse_program().unwrap()
+    }
+
+    // 每一圈都在调用环境里定义一个递归闭包 环境 -> f -> 环境 是一个循环
+    const LOOP: &str = "
+        let spin = fn(n) {
+            if (n == 0) {
+                0
+            } else {
+                let f = fn(x) { if (x == 0) { 0 } else { f(x - 1) } };
+                f(3);
+                spin(n - 1)
+            }
+        };";
+
+    #[test]
+    fn test_recursive_closures_stay_bounded() {
+        let limit = 64 * 1024;
+        let mut interp = Interpreter::new().max_memory(limit);
+        interp.run(&parse(LOOP)).unwrap();
+        let baseline = interp.memory().used;
+
+        let result = interp.run(&parse("spin(100000)"));
+        assert!(matches!(result, Ok(Object::Integer(0))), "{:?}", result);
+        let usage = interp.memory();
+        assert!(usage.peak <= limit);
+        // run 结束时回收过一次 循环全部拆掉
+        assert_eq!(usage.used, baseline);
+    }
+
+    // 闭包放在数组和 hash 里 环境 -> 容器 -> 闭包 -> 环境 也是循环
+    const HELD: &str = "
+        let spin = fn(n) {
+            if (n == 0) {
+                0
+            } else {
+                let xs = [fn(x) { xs }];
+                let h = {\"f\": fn(x) { h }, \"nested\": [[fn() { h }]]};
+                len(xs[0](1)) + len(h.f(1));
+                spin(n - 1)
+            }
+        };";
+
+    #[test]
+    fn test_closures_in_containers_are_collected() {
+        let limit = 64 * 1024;
+        let mut interp = Interpreter::new().max_memory(limit);
+        interp.run(&parse(HELD)).unwrap();
+        let baseline = interp.memory().used;
+
+        let result = interp.run(&parse("spin(20000)"));
+        assert!(matches!(result, Ok(Object::Integer(0))), "{:?}", result);
+        let usage = interp.memory();
+        assert!(usage.peak <= limit);
+        assert_eq!(usage.used, baseline);
+    }
+
+    #[test]
+    fn test_closures_in_containers_survive() {
+        let mut interp = Interpreter::new();
+        interp
+            .run(&parse(&format!(
+                "{}
+                 let make = fn(n) {{ let xs = [fn() {{ xs[0] }}, fn() {{ n }}]; let h = {{\"get\": fn() {{ n + 1 }}}}; [xs, h] }};
+                 let kept = make(7);",
+                HELD
+            )))
+            .unwrap();
+
+        // 全局还引用着的容器里的闭包和它们的环境不能被拆
+        interp.run(&parse("spin(5000)")).unwrap();
+        assert_eq!(interp.collect_garbage(), 0);
+        assert!(matches!(
+            interp.run(&parse("kept[0][1]()")),
+            Ok(Object::Integer(7))
+        ));
+        assert!(matches!(
+            interp.run(&parse("kept[1].get()")),
+            Ok(Object::Integer(8))
+        ));
+    }
+
+    #[test]
+    fn test_collect_garbage() {
+        let mut interp = Interpreter::new();
+        interp.run(&parse(LOOP)).unwrap();
+        assert_eq!(interp.collect_garbage(), 0);
+
+        // 每一圈留下一个循环 但 run 结束时已经回收了
+        interp.run(&parse("spin(10)")).unwrap();
+        assert_eq!(interp.collect_garbage(), 0);
+        assert_eq!(interp.memory().used, size_of::<FunctionObject>());
+    }
+
+    #[test]
+    fn test_reachable_closures_survive() {
+        let mut interp = Interpreter::new();
+        interp
+            .run(&parse(&format!(
+                "{}
+                 let counter = fn(n) {{ let get = fn(k) {{ if (k == 0) {{ n }} else {{ get(k - 1) }} }}; get }};
+                 let make = fn(n) {{ let f = fn() {{ n }}; f }};
+                 let c = counter(5);
+                 let m = make(7);",
+                LOOP
+            )))
+            .unwrap();
+
+        // 中间回收过很多次 全局还引用着的闭包和它们的环境不能被拆
+        interp.run(&parse("spin(10000)")).unwrap();
+        assert!(matches!(interp.run(&parse("m()")), Ok(Object::Integer(7))));
+        assert!(matches!(interp.run(&parse("c(3)")), Ok(Object::Integer(5))));
+
+        // 求值过程中还在用的闭包也不能被拆 (参数 返回值都在 rust 栈上)
+        let input = "let apply = fn(g, n) { spin(2000); g(n) };
+                     apply(fn(x) { let h = fn(y) { h }; x + 40 }, 0) + apply(fn(x) { m() + x }, 1)";
+        assert!(matches!(interp.run(&parse(input)), Ok(Object::Integer(48))));
+    }
+}
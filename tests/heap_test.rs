@@ -0,0 +1,49 @@
+#[cfg(test)]
+mod heap_test {
+    use monkeycc::heap::{Heap, MemoryUsage};
+
+    #[test]
+    fn test_alloc_and_drop() {
+        let heap = Heap::new();
+        let a = heap.alloc(100).unwrap();
+        let b = heap.alloc(50).unwrap();
+        assert_eq!(a.bytes(), 100);
+        assert_eq!(heap.usage().used, 150);
+
+        drop(a);
+        assert_eq!(
+            heap.usage(),
+            MemoryUsage {
+                used: 50,
+                peak: 150,
+                limit: None
+            }
+        );
+
+        // clone 再记一次 两份都 drop 后归零
+        let c = b.clone();
+        assert_eq!(heap.usage().used, 100);
+        drop(b);
+        drop(c);
+        assert_eq!(heap.usage().used, 0);
+        assert_eq!(heap.usage().peak, 150);
+    }
+
+    #[test]
+    fn test_limit() {
+        let heap = Heap::new();
+        heap.set_limit(Some(100));
+
+        let a = heap.alloc(60).unwrap();
+        assert!(heap.alloc(41).is_none());
+        // 失败的分配不记账
+        assert_eq!(heap.usage().used, 60);
+        let b = heap.alloc(40).unwrap();
+        assert_eq!(heap.usage().used, 100);
+
+        drop(a);
+        assert!(heap.alloc(60).is_some());
+        drop(b);
+        assert!(heap.alloc(usize::MAX).is_none());
+    }
+}
@@ -1,7 +1,7 @@
 #[cfg(test)]
 mod lexer_test {
     use monkeycc::lexer::Lexer;
-    use monkeycc::token::{Token, TokenType};
+    use monkeycc::token::{Position, Token, TokenType};
 
     #[test]
     fn test_next_token() {
@@ -26,299 +26,299 @@ mod lexer_test {
         let tests = vec![
             Token {
                 token_type: TokenType::Let,
-                literal: "let".to_string(),
+                literal: "let",
             },
             Token {
                 token_type: TokenType::Ident,
-                literal: "five".to_string(),
+                literal: "five",
             },
             Token {
                 token_type: TokenType::Assign,
-                literal: "=".to_string(),
+                literal: "=",
             },
             Token {
                 token_type: TokenType::Int,
-                literal: "5".to_string(),
+                literal: "5",
             },
             Token {
                 token_type: TokenType::Semicolon,
-                literal: ";".to_string(),
+                literal: ";",
             },
             Token {
                 token_type: TokenType::Let,
-                literal: "let".to_string(),
+                literal: "let",
             },
             Token {
                 token_type: TokenType::Ident,
-                literal: "ten".to_string(),
+                literal: "ten",
             },
             Token {
                 token_type: TokenType::Assign,
-                literal: "=".to_string(),
+                literal: "=",
             },
             Token {
                 token_type: TokenType::Int,
-                literal: "10".to_string(),
+                literal: "10",
             },
             Token {
                 token_type: TokenType::Semicolon,
-                literal: ";".to_string(),
+                literal: ";",
             },
             Token {
                 token_type: TokenType::Let,
-                literal: "let".to_string(),
+                literal: "let",
             },
             Token {
                 token_type: TokenType::Ident,
-                literal: "add".to_string(),
+                literal: "add",
             },
             Token {
                 token_type: TokenType::Assign,
-                literal: "=".to_string(),
+                literal: "=",
             },
             Token {
                 token_type: TokenType::Function,
-                literal: "fn".to_string(),
+                literal: "fn",
             },
             Token {
                 token_type: TokenType::Lparen,
-                literal: "(".to_string(),
+                literal: "(",
             },
             Token {
                 token_type: TokenType::Ident,
-                literal: "x".to_string(),
+                literal: "x",
             },
             Token {
                 token_type: TokenType::Comma,
-                literal: ",".to_string(),
+                literal: ",",
             },
             Token {
                 token_type: TokenType::Ident,
-                literal: "y".to_string(),
+                literal: "y",
             },
             Token {
                 token_type: TokenType::Rparen,
-                literal: ")".to_string(),
+                literal: ")",
             },
             Token {
                 token_type: TokenType::Lbrace,
-                literal: "{".to_string(),
+                literal: "{",
             },
             Token {
                 token_type: TokenType::Ident,
-                literal: "x".to_string(),
+                literal: "x",
             },
             Token {
                 token_type: TokenType::Plus,
-                literal: "+".to_string(),
+                literal: "+",
             },
             Token {
                 token_type: TokenType::Ident,
-                literal: "y".to_string(),
+                literal: "y",
             },
             Token {
                 token_type: TokenType::Semicolon,
-                literal: ";".to_string(),
+                literal: ";",
             },
             Token {
                 token_type: TokenType::Rbrace,
-                literal: "}".to_string(),
+                literal: "}",
             },
             Token {
                 token_type: TokenType::Semicolon,
-                literal: ";".to_string(),
+                literal: ";",
             },
             Token {
                 token_type: TokenType::Let,
-                literal: "let".to_string(),
+                literal: "let",
             },
             Token {
                 token_type: TokenType::Ident,
-                literal: "result".to_string(),
+                literal: "result",
             },
             Token {
                 token_type: TokenType::Assign,
-                literal: "=".to_string(),
+                literal: "=",
             },
             Token {
                 token_type: TokenType::Ident,
-                literal: "add".to_string(),
+                literal: "add",
             },
             Token {
                 token_type: TokenType::Lparen,
-                literal: "(".to_string(),
+                literal: "(",
             },
             Token {
                 token_type: TokenType::Ident,
-                literal: "five".to_string(),
+                literal: "five",
             },
             Token {
                 token_type: TokenType::Comma,
-                literal: ",".to_string(),
+                literal: ",",
             },
             Token {
                 token_type: TokenType::Ident,
-                literal: "ten".to_string(),
+                literal: "ten",
             },
             Token {
                 token_type: TokenType::Rparen,
-                literal: ")".to_string(),
+                literal: ")",
             },
             Token {
                 token_type: TokenType::Semicolon,
-                literal: ";".to_string(),
+                literal: ";",
             },
             Token {
                 token_type: TokenType::Bang,
-                literal: "!".to_string(),
+                literal: "!",
             },
             Token {
                 token_type: TokenType::Minus,
-                literal: "-".to_string(),
+                literal: "-",
             },
             Token {
                 token_type: TokenType::Slash,
-                literal: "/".to_string(),
+                literal: "/",
             },
             Token {
                 token_type: TokenType::Asterisk,
-                literal: "*".to_string(),
+                literal: "*",
             },
             Token {
                 token_type: TokenType::Int,
-                literal: "5".to_string(),
+                literal: "5",
             },
             Token {
                 token_type: TokenType::Semicolon,
-                literal: ";".to_string(),
+                literal: ";",
             },
             Token {
                 token_type: TokenType::Int,
-                literal: "5".to_string(),
+                literal: "5",
             },
             Token {
                 token_type: TokenType::Lt,
-                literal: "<".to_string(),
+                literal: "<",
             },
             Token {
                 token_type: TokenType::Int,
-                literal: "10".to_string(),
+                literal: "10",
             },
             Token {
                 token_type: TokenType::Gt,
-                literal: ">".to_string(),
+                literal: ">",
             },
             Token {
                 token_type: TokenType::Int,
-                literal: "5".to_string(),
+                literal: "5",
             },
             Token {
                 token_type: TokenType::Semicolon,
-                literal: ";".to_string(),
+                literal: ";",
             },
             Token {
                 token_type: TokenType::If,
-                literal: "if".to_string(),
+                literal: "if",
             },
             Token {
                 token_type: TokenType::Lparen,
-                literal: "(".to_string(),
+                literal: "(",
             },
             Token {
                 token_type: TokenType::Int,
-                literal: "5".to_string(),
+                literal: "5",
             },
             Token {
                 token_type: TokenType::Lt,
-                literal: "<".to_string(),
+                literal: "<",
             },
             Token {
                 token_type: TokenType::Int,
-                literal: "10".to_string(),
+                literal: "10",
             },
             Token {
                 token_type: TokenType::Rparen,
-                literal: ")".to_string(),
+                literal: ")",
             },
             Token {
                 token_type: TokenType::Lbrace,
-                literal: "{".to_string(),
+                literal: "{",
             },
             Token {
                 token_type: TokenType::Return,
-                literal: "return".to_string(),
+                literal: "return",
             },
             Token {
                 token_type: TokenType::True,
-                literal: "true".to_string(),
+                literal: "true",
             },
             Token {
                 token_type: TokenType::Semicolon,
-                literal: ";".to_string(),
+                literal: ";",
             },
             Token {
                 token_type: TokenType::Rbrace,
-                literal: "}".to_string(),
+                literal: "}",
             },
             Token {
                 token_type: TokenType::Else,
-                literal: "else".to_string(),
+                literal: "else",
             },
             Token {
                 token_type: TokenType::Lbrace,
-                literal: "{".to_string(),
+                literal: "{",
             },
             Token {
                 token_type: TokenType::Return,
-                literal: "return".to_string(),
+                literal: "return",
             },
             Token {
                 token_type: TokenType::False,
-                literal: "false".to_string(),
+                literal: "false",
             },
             Token {
                 token_type: TokenType::Semicolon,
-                literal: ";".to_string(),
+                literal: ";",
             },
             Token {
                 token_type: TokenType::Rbrace,
-                literal: "}".to_string(),
+                literal: "}",
             },
             Token {
                 token_type: TokenType::Int,
-                literal: "10".to_string(),
+                literal: "10",
             },
             Token {
                 token_type: TokenType::Eq,
-                literal: "==".to_string(),
+                literal: "==",
             },
             Token {
                 token_type: TokenType::Int,
-                literal: "10".to_string(),
+                literal: "10",
             },
             Token {
                 token_type: TokenType::Semicolon,
-                literal: ";".to_string(),
+                literal: ";",
             },
             Token {
                 token_type: TokenType::Int,
-                literal: "10".to_string(),
+                literal: "10",
             },
             Token {
                 token_type: TokenType::NotEq,
-                literal: "!=".to_string(),
+                literal: "!=",
             },
             Token {
                 token_type: TokenType::Int,
-                literal: "9".to_string(),
+                literal: "9",
             },
             Token {
                 token_type: TokenType::Semicolon,
-                literal: ";".to_string(),
+                literal: ";",
             },
             Token {
                 token_type: TokenType::Eof,
-                literal: "".to_string(),
+                literal: "",
             },
         ];
 
@@ -333,4 +333,241 @@ mod lexer_test {
             assert_eq!(tt, tok);
         }
     }
+
+    #[test]
+    fn test_tokens_borrow_source() {
+        let input = "let x = 10 >= 9; é";
+        let range = input.as_bytes().as_ptr_range();
+
+        let tokens: Vec<Token> = Lexer::new(input).collect();
+        // 每个literal 都是input上的切片 没有额外分配
+        for tok in &tokens {
+            assert!(range.contains(&tok.literal.as_ptr()), "{:?}", tok);
+        }
+
+        let last = tokens.last().unwrap();
+        assert_eq!(last.token_type, TokenType::Illegal);
+        assert_eq!(last.literal, "é");
+    }
+
+    #[test]
+    fn test_token_position() {
+        let input = "let x = 5;\n  x + 10;";
+        let mut l = Lexer::new(input);
+
+        let expected = [
+            (1, 1),
+            (1, 5),
+            (1, 7),
+            (1, 9),
+            (1, 10),
+            (2, 3),
+            (2, 5),
+            (2, 7),
+        ];
+        for (line, column) in expected {
+            l.next_token();
+            assert_eq!(l.token_position(), Position { line, column });
+        }
+    }
+
+    #[test]
+    fn test_annotation_tokens() {
+        let input = "fn(a: int) -> int { a - -1 }";
+        let types: Vec<TokenType> = Lexer::new(input).map(|t| t.token_type).collect();
+        assert_eq!(
+            types,
+            vec![
+                TokenType::Function,
+                TokenType::Lparen,
+                TokenType::Ident,
+                TokenType::Colon,
+                TokenType::Ident,
+                TokenType::Rparen,
+                TokenType::Arrow,
+                TokenType::Ident,
+                TokenType::Lbrace,
+                TokenType::Ident,
+                TokenType::Minus,
+                TokenType::Minus,
+                TokenType::Int,
+                TokenType::Rbrace,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_strings_and_brackets() {
+        let input = r#""foo bar" "" "say \"hi\"" "中文" [1, 2]; {"a": 1} "open"#;
+        let tokens: Vec<(TokenType, &str)> = Lexer::new(input)
+            .map(|t| (t.token_type, t.literal))
+            .collect();
+        assert_eq!(
+            tokens,
+            vec![
+                (TokenType::String, "foo bar"),
+                (TokenType::String, ""),
+                (TokenType::String, r#"say \"hi\""#),
+                (TokenType::String, "中文"),
+                (TokenType::Lbracket, "["),
+                (TokenType::Int, "1"),
+                (TokenType::Comma, ","),
+                (TokenType::Int, "2"),
+                (TokenType::Rbracket, "]"),
+                (TokenType::Semicolon, ";"),
+                (TokenType::Lbrace, "{"),
+                (TokenType::String, "a"),
+                (TokenType::Colon, ":"),
+                (TokenType::Int, "1"),
+                (TokenType::Rbrace, "}"),
+                // 没有右引号 一直读到结尾
+                (TokenType::String, "open"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_assignment_tokens() {
+        let input = "x += 1; x -= 2; x *= 3; x /= 4; x = -1 - -2; fn() -> int";
+        let tokens: Vec<(TokenType, &str)> = Lexer::new(input)
+            .map(|t| (t.token_type, t.literal))
+            .filter(|(t, _)| !matches!(t, TokenType::Ident | TokenType::Int | TokenType::Semicolon))
+            .collect();
+        assert_eq!(
+            tokens,
+            vec![
+                (TokenType::PlusAssign, "+="),
+                (TokenType::MinusAssign, "-="),
+                (TokenType::AsteriskAssign, "*="),
+                (TokenType::SlashAssign, "/="),
+                (TokenType::Assign, "="),
+                (TokenType::Minus, "-"),
+                (TokenType::Minus, "-"),
+                (TokenType::Minus, "-"),
+                (TokenType::Function, "fn"),
+                (TokenType::Lparen, "("),
+                (TokenType::Rparen, ")"),
+                (TokenType::Arrow, "->"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_ellipsis() {
+        let input = "let [a, ...rest] = xs; x.y";
+        let tokens: Vec<TokenType> = Lexer::new(input).map(|t| t.token_type).collect();
+        assert_eq!(
+            tokens,
+            vec![
+                TokenType::Let,
+                TokenType::Lbracket,
+                TokenType::Ident,
+                TokenType::Comma,
+                TokenType::Ellipsis,
+                TokenType::Ident,
+                TokenType::Rbracket,
+                TokenType::Assign,
+                TokenType::Ident,
+                TokenType::Semicolon,
+                TokenType::Ident,
+                TokenType::Dot,
+                TokenType::Ident,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_match_tokens() {
+        let input = "match (x) { _ => y, n if n >= 1 => n } x == y; x = >";
+        let tokens: Vec<(TokenType, &str)> = Lexer::new(input)
+            .map(|t| (t.token_type, t.literal))
+            .filter(|(t, _)| {
+                matches!(
+                    t,
+                    TokenType::Match
+                        | TokenType::FatArrow
+                        | TokenType::Eq
+                        | TokenType::Assign
+                        | TokenType::Ge
+                        | TokenType::Gt
+                )
+            })
+            .collect();
+        assert_eq!(
+            tokens,
+            vec![
+                (TokenType::Match, "match"),
+                (TokenType::FatArrow, "=>"),
+                (TokenType::Ge, ">="),
+                (TokenType::FatArrow, "=>"),
+                (TokenType::Eq, "=="),
+                (TokenType::Assign, "="),
+                (TokenType::Gt, ">"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_null_operators() {
+        let input = "c ? a : b; a ?? b; p?.name; xs?[0]; c ? [1] : [2]";
+        let tokens: Vec<(TokenType, &str)> = Lexer::new(input)
+            .map(|t| (t.token_type, t.literal))
+            .filter(|(t, _)| !matches!(t, TokenType::Ident | TokenType::Int | TokenType::Semicolon))
+            .collect();
+        assert_eq!(
+            tokens,
+            vec![
+                (TokenType::Question, "?"),
+                (TokenType::Colon, ":"),
+                (TokenType::NullCoalesce, "??"),
+                (TokenType::OptionalDot, "?."),
+                (TokenType::OptionalBracket, "?["),
+                (TokenType::Rbracket, "]"),
+                (TokenType::Question, "?"),
+                (TokenType::Lbracket, "["),
+                (TokenType::Rbracket, "]"),
+                (TokenType::Colon, ":"),
+                (TokenType::Lbracket, "["),
+                (TokenType::Rbracket, "]"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_interpolated_strings() {
+        // ${} 里的引号和花括号不结束外面的字符串
+        let input = r#""hi ${name}" "${f("}")} and ${ {"a": 1}["a"] }" "\${x}" "${open"#;
+        let tokens: Vec<(TokenType, &str)> = Lexer::new(input)
+            .map(|t| (t.token_type, t.literal))
+            .collect();
+        assert_eq!(
+            tokens,
+            vec![
+                (TokenType::String, "hi ${name}"),
+                (TokenType::String, r#"${f("}")} and ${ {"a": 1}["a"] }"#),
+                (TokenType::String, r"\${x}"),
+                (TokenType::String, "${open"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_module_tokens() {
+        let input = r#"import "m" as m; export m.f"#;
+        let tokens: Vec<TokenType> = Lexer::new(input).map(|t| t.token_type).collect();
+        assert_eq!(
+            tokens,
+            vec![
+                TokenType::Import,
+                TokenType::String,
+                TokenType::As,
+                TokenType::Ident,
+                TokenType::Semicolon,
+                TokenType::Export,
+                TokenType::Ident,
+                TokenType::Dot,
+                TokenType::Ident,
+            ]
+        );
+    }
 }
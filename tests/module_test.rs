@@ -0,0 +1,188 @@
+#[cfg(test)]
+mod module_test {
+    use std::{
+        fs,
+        path::{Path, PathBuf},
+        rc::Rc,
+    };
+
+    use monkeycc::{
+        ast::Program,
+        evaluator::{Interpreter, RuntimeError},
+        lexer::Lexer,
+        object::Object,
+        parser::Parser,
+    };
+
+    fn parse(input: &str) -> Program {
+        Parser::new(Lexer::new(input)).parse_program().unwrap()
+    }
+
+    // 每个测试一个临时目录 files 是 (相对路径, 内容)
+    fn project(name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("monkeycc-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        for (path, source) in files {
+            let path = dir.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, source).unwrap();
+        }
+        dir
+    }
+
+    fn run_main(dir: &Path) -> Result<Object, RuntimeError> {
+        let main = dir.join("main.monkey");
+        let source = fs::read_to_string(&main).unwrap();
+        Interpreter::new().script_path(main).run(&parse(&source))
+    }
+
+    #[test]
+    fn test_import_and_member_access() {
+        let dir = project(
+            "import",
+            &[
+                (
+                    "main.monkey",
+                    r#"import "lib/math.monkey" as math;
+                       math.cube(3) + math.square(2)"#,
+                ),
+                (
+                    "lib/math.monkey",
+                    r#"import "util.monkey" as util;
+                       export let square = fn(x) { util.times(x, x) };
+                       let cube = fn(x) { util.times(x, square(x)) };"#,
+                ),
+                // 相对于 lib/math.monkey 而不是入口脚本
+                ("lib/util.monkey", "let times = fn(a, b) { a * b };"),
+            ],
+        );
+        assert!(matches!(run_main(&dir), Ok(Object::Integer(31))));
+    }
+
+    #[test]
+    fn test_modules_are_cached_and_isolated() {
+        let dir = project(
+            "cache",
+            &[
+                (
+                    "main.monkey",
+                    r#"import "lib/a.monkey" as a;
+                       import "./lib/../lib/a.monkey" as again;
+                       import "lib/b.monkey" as b;"#,
+                ),
+                (
+                    "lib/a.monkey",
+                    "let secret = 42; let get = fn() { secret };",
+                ),
+                ("lib/b.monkey", r#"import "a.monkey" as a;"#),
+            ],
+        );
+        let main = dir.join("main.monkey");
+        let mut interp = Interpreter::new().script_path(&main);
+        interp
+            .run(&parse(&fs::read_to_string(&main).unwrap()))
+            .unwrap();
+
+        // 三次 import 拿到的是同一个模块
+        let mut module = |input: &str| match interp.eval(&parse(input)) {
+            Object::Module(m) => m,
+            other => panic!("not a module: {:?}", other),
+        };
+        let a = module("a");
+        assert!(Rc::ptr_eq(&a, &module("again")));
+        assert!(Rc::ptr_eq(&a, &module("b.a")));
+        assert_eq!(interp.eval(&parse("a")).inspect(), "<module lib/a.monkey>");
+
+        // 模块的顶层名字不会漏到 import 它的文件里
+        assert!(matches!(
+            interp.eval(&parse("a.get()")),
+            Object::Integer(42)
+        ));
+        assert_eq!(
+            interp.eval(&parse("secret")).inspect(),
+            "Error: identifier not found: secret"
+        );
+        assert_eq!(
+            interp.eval(&parse("a.missing")).inspect(),
+            "Error: module lib/a.monkey has no member missing"
+        );
+    }
+
+    #[test]
+    fn test_circular_imports() {
+        let dir = project(
+            "cycle",
+            &[
+                ("main.monkey", r#"import "a.monkey" as a;"#),
+                ("a.monkey", r#"import "sub/b.monkey" as b;"#),
+                ("sub/b.monkey", r#"import "../a.monkey" as a;"#),
+            ],
+        );
+        let err = run_main(&dir).unwrap_err();
+        assert_eq!(
+            err,
+            RuntimeError::CircularImport {
+                cycle: vec![
+                    "a.monkey".to_string(),
+                    "sub/b.monkey".to_string(),
+                    "../a.monkey".to_string()
+                ]
+            }
+        );
+        assert_eq!(
+            err.to_string(),
+            "circular import: a.monkey -> sub/b.monkey -> ../a.monkey"
+        );
+
+        // 入口脚本自己也算
+        let dir = project("self", &[("main.monkey", r#"import "main.monkey" as me;"#)]);
+        assert!(matches!(
+            run_main(&dir),
+            Err(RuntimeError::CircularImport { .. })
+        ));
+    }
+
+    #[test]
+    fn test_import_errors() {
+        let dir = project(
+            "errors",
+            &[
+                (
+                    "main.monkey",
+                    r#"let secret = 1; import "leak.monkey" as leak;"#,
+                ),
+                // 模块看不到 import 它的文件里的名字
+                ("leak.monkey", "let get = fn() { secret };"),
+                ("broken.monkey", "let = 5;"),
+                ("fails.monkey", "let x = 1 + true;"),
+            ],
+        );
+        let err = run_main(&dir).unwrap_err().to_string();
+        assert_eq!(
+            err,
+            r#"cannot import "leak.monkey": 1:18: undefined identifier: secret"#
+        );
+
+        let main = dir.join("main.monkey");
+        let mut interp = Interpreter::new().script_path(main);
+        let cases = [
+            (
+                r#"import "missing.monkey" as m;"#,
+                r#"cannot import "missing.monkey": "#,
+            ),
+            (
+                r#"import "broken.monkey" as b;"#,
+                r#"cannot import "broken.monkey": Expected next token to be Ident"#,
+            ),
+            (
+                r#"import "fails.monkey" as f;"#,
+                "type mismatch: Integer(1) + Boolean(true)",
+            ),
+            ("5.x", "member access not supported: Integer.x"),
+        ];
+        for (input, expected) in cases {
+            let msg = interp.run(&parse(input)).unwrap_err().to_string();
+            assert!(msg.starts_with(expected), "{}: {}", input, msg);
+        }
+    }
+}
@@ -0,0 +1,120 @@
+#[cfg(test)]
+mod optimizer_test {
+    use monkeycc::{
+        ast::Program, evaluator::eval, lexer::Lexer, optimizer::optimize, parser::Parser,
+    };
+
+    fn parse(input: &str) -> Program {
+        let mut p = Parser::new(Lexer::new(input));
+        let program = p.parse_program().unwrap();
+        assert!(p.errors().is_empty(), "{:?}", p.errors());
+        program
+    }
+
+    #[test]
+    fn test_constant_folding() {
+        let tests = [
+            ("2 * 2 * 2 * 2 * 2", "32"),
+            ("(5 + 10 * 2 + 15 / 3) * 2 + -10", "50"),
+            ("-(-5)", "5"),
+            ("!true", "false"),
+            ("!0", "true"),
+            ("1 < 2 == true", "true"),
+            ("true != false", "true"),
+            // 部分折叠: 变量挡住了左边 右边的子表达式照样折叠
+            ("x + 2 * 3", "(x + 6)"),
+            ("fn(x) { x * (1 + 1) }", "fn(x) (x * 2)"),
+            ("f(1 + 2, 3 * 4)", "f(3, 12)"),
+            ("let y = 10 - 4;", "let y = 6;"),
+        ];
+
+        for (input, expected) in tests {
+            assert_eq!(
+                optimize(parse(input)).string(),
+                expected,
+                "input: {}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn test_keeps_error_semantics() {
+        // 这些在运行时才报错 不能在编译期吃掉
+        let tests = [
+            ("1 / 0", "(1 / 0)"),
+            ("2 * 3 / (1 - 1)", "(6 / 0)"),
+            ("5 + true", "(5 + true)"),
+            ("-true", "(-true)"),
+            ("true + false", "(true + false)"),
+            ("9223372036854775807 + 1", "(9223372036854775807 + 1)"),
+        ];
+
+        for (input, expected) in tests {
+            assert_eq!(
+                optimize(parse(input)).string(),
+                expected,
+                "input: {}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn test_prunes_constant_if() {
+        let tests = [
+            ("if (1 < 2) { 10 } else { 20 }", "10"),
+            ("if (1 > 2) { 10 } else { 20 }", "20"),
+            ("if (0) { 10 } else { 2 * 10 }", "20"),
+            ("if (false) { 10 }", "iftrue "),
+            ("if (true) { let a = 1; a }", "iftrue let a = 1;a"),
+            ("if (x) { 1 + 1 } else { 2 + 2 }", "ifx 2 else 4"),
+            ("if (false) { 1 } else if (true) { 2 } else { 3 }", "2"),
+            (
+                "if (false) { 1 } else if (x) { 2 } else { 3 }",
+                "ifx 2 else 3",
+            ),
+            ("if (x) { 1 } else if (false) { 2 }", "ifx 1 else iftrue "),
+            ("1 < 2 ? x : y", "x"),
+            ("0 ? x : y + 1", "(y + 1)"),
+            ("c ? 1 + 1 : 2", "(c ? 2 : 2)"),
+            ("1 ?? f()", "1"),
+            ("x ?? 1 + 1", "(x ?? 2)"),
+        ];
+
+        for (input, expected) in tests {
+            assert_eq!(
+                optimize(parse(input)).string(),
+                expected,
+                "input: {}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn test_same_result_as_unoptimized() {
+        let inputs = [
+            "(5 + 10 * 2 + 15 / 3) * 2 + -10",
+            "if (1 > 2) { 10 }",
+            "if (10 > 1) { if (10 > 1) { return 10 } return 1; }",
+            "if (1 > 2) { 10 } else if (2 > 3) { 20 }",
+            "let h = {}; (1 > 2 ? h : 3) ?? 4",
+            "9; return 2 * 5; 9;",
+            "1 / 0;",
+            "5 + true; 5;",
+            "let f = fn(x) { if (true) { return x * (2 + 3); } 0 }; f(2)",
+        ];
+
+        for input in inputs {
+            let program = parse(input);
+            let expected = eval(&program).inspect();
+            assert_eq!(
+                eval(&optimize(program)).inspect(),
+                expected,
+                "input: {}",
+                input
+            );
+        }
+    }
+}
@@ -4,7 +4,7 @@ mod parser_test {
 
     use monkeycc::ast::{Expr, Statement};
     use monkeycc::lexer::Lexer;
-    use monkeycc::parser::Parser;
+    use monkeycc::parser::{MAX_NESTING_DEPTH, Parser};
     use monkeycc::token::TokenType;
 
     #[test]
@@ -17,7 +17,7 @@ mod parser_test {
 
         struct IdentValue(&'static str, &'static str);
 
-        let tests = vec![
+        let tests = [
             IdentValue("x", "5"),
             IdentValue("y", "10"),
             IdentValue("footbar", "838383"),
@@ -43,8 +43,8 @@ mod parser_test {
                 );
                 for (t, i_v) in p.statements.iter().zip(tests.iter()) {
                     match &t {
-                        &Statement::Let { name, value } => {
-                            assert_eq!(name.string(), i_v.0);
+                        &Statement::Let { pattern, value, .. } => {
+                            assert_eq!(pattern.string(), i_v.0);
                             assert_eq!(value.string(), i_v.1);
                         }
                         _ => {
@@ -97,7 +97,6 @@ mod parser_test {
             }
             None => {
                 eprintln!("Error parser_program return None");
-                return;
             }
         }
     }
@@ -519,7 +518,7 @@ mod parser_test {
                 {
                     assert_eq!("(x < y)", &condition.string());
                     assert_eq!("x", &consequence.string());
-                    assert_eq!("None", &alternative.string())
+                    assert!(alternative.is_none());
                 }
             }
             _ => panic!("Not a Expression"),
@@ -551,7 +550,7 @@ mod parser_test {
                 } => {
                     assert_eq!("(x < y)", &condition.string());
                     assert_eq!("x", &consequence.string());
-                    assert_eq!("y", &alternative.string());
+                    assert_eq!("y", &alternative.expect("no else branch").string());
                 }
                 _ => {
                     panic!("Not a if expression")
@@ -563,6 +562,58 @@ mod parser_test {
         }
     }
 
+    #[test]
+    pub fn test_else_if_expression() {
+        let input = "if (a) { 1 } else if (b) { 2 } else { 3 }";
+        let mut p = Parser::new(Lexer::new(input));
+        let program = p.parse_program().unwrap();
+        check_parser_errors(&p);
+        assert_eq!(program.string(), "ifa 1 else ifb 2 else 3");
+
+        let Statement::Expression(stmt) = &program.statements[0] else {
+            panic!("Not a Expression");
+        };
+        let Expr::IfExpression { alternative, .. } = &stmt.expression else {
+            panic!("Not a if expression");
+        };
+        // else if 是包着下一个 if 的表达式语句
+        match alternative.as_deref() {
+            Some(Statement::Expression(next)) => match &next.expression {
+                Expr::IfExpression {
+                    condition,
+                    alternative,
+                    ..
+                } => {
+                    assert_eq!("b", &condition.string());
+                    assert_eq!("3", &alternative.as_ref().expect("no else").string());
+                }
+                other => panic!("expected a nested if, got {}", other.string()),
+            },
+            _ => panic!("expected else if"),
+        }
+
+        let tests = [
+            (
+                "if (a) { 1 } else if b { 2 }",
+                "Expected next token to be Lparen, got Ident instead",
+            ),
+            (
+                "if (a) { 1 } else 2",
+                "Expected next token to be Lbrace, got Int instead",
+            ),
+        ];
+        for (input, expected) in tests {
+            let mut p = Parser::new(Lexer::new(input));
+            p.parse_program().unwrap();
+            assert_eq!(
+                p.errors().first().map(String::as_str),
+                Some(expected),
+                "input: {}",
+                input
+            );
+        }
+    }
+
     // test function literal
     #[test]
     pub fn test_fn_literal() {
@@ -703,11 +754,521 @@ mod parser_test {
         }
     }
 
+    // 类型注解: let / 参数 / 返回值
+    #[test]
+    pub fn test_type_annotations() {
+        let tests = [
+            ("let x: int = 5;", "let x: int = 5;"),
+            ("let ok: bool = true;", "let ok: bool = true;"),
+            (
+                "fn(a: int, b) -> int { a + b }",
+                "fn(a: int, b) -> int (a + b)",
+            ),
+            (
+                "let apply: fn(fn(int) -> bool, int) -> any = fn(f, x) { f(x) };",
+                "let apply: fn(fn(int) -> bool, int) -> any = fn(f, x) f(x);",
+            ),
+            ("fn() -> null { }", "fn() -> null "),
+        ];
+
+        for (input, expected) in tests {
+            let mut p = Parser::new(Lexer::new(input));
+            let program = p.parse_program().unwrap();
+            check_parser_errors(&p);
+            assert_eq!(program.string(), expected);
+        }
+
+        let mut p = Parser::new(Lexer::new("let x: float = 1;"));
+        p.parse_program();
+        assert_eq!(p.errors(), vec!["unknown type: float".to_string()]);
+    }
+
+    #[test]
+    pub fn test_collection_literals() {
+        let tests = [
+            (r#""hello world""#, r#""hello world""#),
+            (r#""a\tb\n\"c\"\\ \q""#, r#""a\tb\n\"c\"\\ \\q""#),
+            ("[1, 2 * 2, 3 + 3]", "[1, (2 * 2), (3 + 3)]"),
+            ("[]", "[]"),
+            ("myArray[1 + 1]", "(myArray[(1 + 1)])"),
+            (
+                "a * [1, 2, 3, 4][b * c] * d",
+                "((a * ([1, 2, 3, 4][(b * c)])) * d)",
+            ),
+            (
+                "add(a * b[2], b[1], 2 * [1, 2][1])",
+                "add((a * (b[2])), (b[1]), (2 * ([1, 2][1])))",
+            ),
+            ("f(x)[0]", "(f(x)[0])"),
+            ("{}", "{}"),
+            (
+                r#"{"one": 1, true: 2, 3: 1 + 2}"#,
+                r#"{"one": 1, true: 2, 3: (1 + 2)}"#,
+            ),
+            ("let xs: [int] = [1];", "let xs: [int] = [1];"),
+            ("const PI = 3;", "const PI = 3;"),
+            ("const xs: [int] = [];", "const xs: [int] = [];"),
+            ("export const E = 2;", "const E = 2;"),
+        ];
+
+        for (input, expected) in tests {
+            let mut p = Parser::new(Lexer::new(input));
+            let program = p.parse_program().unwrap();
+            check_parser_errors(&p);
+            assert_eq!(program.string(), expected, "input: {}", input);
+        }
+
+        match &Parser::new(Lexer::new(r#""中文\n""#))
+            .parse_program()
+            .unwrap()
+            .statements[0]
+        {
+            Statement::Expression(e) => match &e.expression {
+                Expr::String(s) => assert_eq!(&**s, "中文\n"),
+                other => panic!("not a string: {:?}", other),
+            },
+            other => panic!("not an expression: {:?}", other),
+        }
+
+        for input in ["[1, 2", "{1 2}", "{1: 2", "xs[1"] {
+            let mut p = Parser::new(Lexer::new(input));
+            p.parse_program().unwrap();
+            assert!(!p.errors().is_empty(), "{}", input);
+        }
+    }
+
+    #[test]
+    pub fn test_assignment() {
+        let tests = [
+            ("x = 5;", "(x = 5)"),
+            ("x = y = 1 + 2", "(x = (y = (1 + 2)))"),
+            (
+                "x += 1; x -= 2; x *= 3; x /= 4;",
+                "(x += 1)(x -= 2)(x *= 3)(x /= 4)",
+            ),
+            ("xs[i + 1] = f(x)", "((xs[(i + 1)]) = f(x))"),
+            (r#"h["a"]["b"] = 1"#, r#"(((h["a"])["b"]) = 1)"#),
+            ("p.name = n == 1", "(p.name = (n == 1))"),
+            ("let a = b = 2;", "let a = (b = 2);"),
+            ("f(x = 1)", "f((x = 1))"),
+        ];
+        for (input, expected) in tests {
+            let mut p = Parser::new(Lexer::new(input));
+            let program = p.parse_program().unwrap();
+            check_parser_errors(&p);
+            assert_eq!(program.string(), expected, "input: {}", input);
+        }
+
+        let tests = [
+            ("1 = 2", "invalid assignment target: 1"),
+            ("a + b = c", "invalid assignment target: (a + b)"),
+            ("f()[0] = 1", "invalid assignment target: (f()[0])"),
+            ("-x += 1", "invalid assignment target: (-x)"),
+        ];
+        for (input, expected) in tests {
+            let mut p = Parser::new(Lexer::new(input));
+            p.parse_program().unwrap();
+            assert_eq!(
+                p.errors().first().map(String::as_str),
+                Some(expected),
+                "input: {}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    pub fn test_destructuring() {
+        let tests = [
+            ("let [a, b] = xs;", "let [a, b] = xs;"),
+            ("let [first, ...rest] = xs;", "let [first, ...rest] = xs;"),
+            ("let [] = xs;", "let [] = xs;"),
+            (
+                r#"let {name, age: a, "full name": n} = p;"#,
+                "let {name, age: a, full name: n} = p;",
+            ),
+            ("const [x, {y}] = pt;", "const [x, {y}] = pt;"),
+            (
+                "let f = fn([x, y], {z}) { x };",
+                "let f = fn([x, y], {z}) x;",
+            ),
+        ];
+        for (input, expected) in tests {
+            let mut p = Parser::new(Lexer::new(input));
+            let program = p.parse_program().unwrap();
+            check_parser_errors(&p);
+            assert_eq!(program.string(), expected, "input: {}", input);
+        }
+
+        let tests = [
+            ("let [a, a] = xs;", "duplicate name in pattern: a"),
+            ("let {a, b: [a]} = h;", "duplicate name in pattern: a"),
+            ("let [1] = xs;", "expected a name or a pattern, got Int"),
+            (
+                "let {[a]} = h;",
+                "expected a hash key in pattern, got Lbracket",
+            ),
+            (
+                "let = 5;",
+                "Expected next token to be Ident, got Assign instead",
+            ),
+        ];
+        for (input, expected) in tests {
+            let mut p = Parser::new(Lexer::new(input));
+            p.parse_program().unwrap();
+            assert_eq!(
+                p.errors().first().map(String::as_str),
+                Some(expected),
+                "input: {}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    pub fn test_match_expression() {
+        let tests = [
+            (
+                r#"match (x) { 0 => "zero", [a, b] => a + b, {kind: "circle", r} => r * r, _ => "other" }"#,
+                r#"match (x) { 0 => "zero", [a, b] => (a + b), {kind: "circle", r} => (r * r), _ => "other" }"#,
+            ),
+            (
+                "match (n + 1) { n if n > 10 => n, -1 => 0, true => 1, }",
+                "match ((n + 1)) { n if (n > 10) => n, -1 => 0, true => 1 }",
+            ),
+            (
+                "let y = match (x) { [_, _] => 2 };",
+                "let y = match (x) { [_, _] => 2 };",
+            ),
+            ("match (x) {}", "match (x) {  }"),
+        ];
+        for (input, expected) in tests {
+            let mut p = Parser::new(Lexer::new(input));
+            let program = p.parse_program().unwrap();
+            check_parser_errors(&p);
+            assert_eq!(program.string(), expected, "input: {}", input);
+        }
+
+        let tests = [
+            (
+                "match (x) { 1 2 }",
+                "Expected next token to be FatArrow, got Int instead",
+            ),
+            ("match (x) { [a, a] => a }", "duplicate name in pattern: a"),
+            (
+                r#"match (x) { "${y}" => 1 }"#,
+                "string interpolation is not allowed in patterns",
+            ),
+            // 字面量和 _ 只在 match 里能用
+            ("let [_, 1] = xs;", "expected a name or a pattern, got Int"),
+        ];
+        for (input, expected) in tests {
+            let mut p = Parser::new(Lexer::new(input));
+            p.parse_program().unwrap();
+            assert_eq!(
+                p.errors().first().map(String::as_str),
+                Some(expected),
+                "input: {}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    pub fn test_conditional_and_null_operators() {
+        let tests = [
+            ("a ? b : c", "(a ? b : c)"),
+            ("a ? b : c ? d : e", "(a ? b : (c ? d : e))"),
+            ("a == 1 ? b + 1 : c * 2", "((a == 1) ? (b + 1) : (c * 2))"),
+            ("a ?? b ?? c", "((a ?? b) ?? c)"),
+            ("a ?? b == c", "(a ?? (b == c))"),
+            ("a ?? b ? c : d", "((a ?? b) ? c : d)"),
+            ("x = a ? b : c", "(x = (a ? b : c))"),
+            ("p?.name", "p?.name"),
+            ("p?.pet.kind", "p?.pet.kind"),
+            ("xs?[i + 1]", "(xs?[(i + 1)])"),
+            ("f()?[0] ?? 1", "((f()?[0]) ?? 1)"),
+            (r#"{"a": c ? 1 : 2}"#, r#"{"a": (c ? 1 : 2)}"#),
+        ];
+        for (input, expected) in tests {
+            let mut p = Parser::new(Lexer::new(input));
+            let program = p.parse_program().unwrap();
+            check_parser_errors(&p);
+            assert_eq!(program.string(), expected, "input: {}", input);
+        }
+
+        let tests = [
+            ("a ? b", "Expected next token to be Colon, got Eof instead"),
+            ("p?.name = 1", "invalid assignment target: p?.name"),
+            ("xs?[0] = 1", "invalid assignment target: (xs?[0])"),
+            ("a ? b : c = 1", "invalid assignment target: (a ? b : c)"),
+        ];
+        for (input, expected) in tests {
+            let mut p = Parser::new(Lexer::new(input));
+            p.parse_program().unwrap();
+            assert_eq!(
+                p.errors().first().map(String::as_str),
+                Some(expected),
+                "input: {}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    pub fn test_string_interpolation() {
+        let tests = [
+            (r#""hello ${name}!""#, r#""hello ${name}!""#),
+            (r#""${a + b * 2}""#, r#""${(a + (b * 2))}""#),
+            (r#""x${f("}")}y""#, r#""x${f("}")}y""#),
+            (r#""${"in ${x}"}""#, r#""${"in ${x}"}""#),
+            (r#""\${x} \t${x}""#, r#""\${x} \t${x}""#),
+            (r#""$x {y}""#, r#""$x {y}""#),
+            (r#""a" + "${b}""#, r#"("a" + "${b}")"#),
+        ];
+        for (input, expected) in tests {
+            let mut p = Parser::new(Lexer::new(input));
+            let program = p.parse_program().unwrap();
+            check_parser_errors(&p);
+            assert_eq!(program.string(), expected, "input: {}", input);
+        }
+
+        let tests = [
+            (r#""${}""#, "empty ${} in string literal"),
+            (r#""${ }""#, "empty ${} in string literal"),
+            (r#""${a b}""#, "unexpected b in ${a b}"),
+            (r#""${a"#, "unterminated ${ in string literal"),
+        ];
+        for (input, expected) in tests {
+            let mut p = Parser::new(Lexer::new(input));
+            p.parse_program().unwrap();
+            assert_eq!(
+                p.errors().first().map(String::as_str),
+                Some(expected),
+                "input: {}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    pub fn test_imports_and_members() {
+        let tests = [
+            (
+                r#"import "lib/math.monkey" as math;"#,
+                r#"import "lib/math.monkey" as math;"#,
+            ),
+            ("export let x = 1;", "let x = 1;"),
+            ("math.sqrt(x)", "math.sqrt(x)"),
+            ("-m.x * 2", "((-m.x) * 2)"),
+            ("a.b.c[0]", "(a.b.c[0])"),
+            ("xs[0].name", "(xs[0]).name"),
+        ];
+        for (input, expected) in tests {
+            let mut p = Parser::new(Lexer::new(input));
+            let program = p.parse_program().unwrap();
+            check_parser_errors(&p);
+            assert_eq!(program.string(), expected, "input: {}", input);
+        }
+
+        let tests = [
+            (
+                r#"let f = fn() { import "x" as x; };"#,
+                "import is only allowed at the top level",
+            ),
+            (
+                "let f = fn() { export let y = 1; };",
+                "export is only allowed at the top level",
+            ),
+            (
+                r#"import "x";"#,
+                "Expected next token to be As, got Semicolon instead",
+            ),
+            ("m.1", "Expected next token to be Ident, got Int instead"),
+        ];
+        for (input, expected) in tests {
+            let mut p = Parser::new(Lexer::new(input));
+            p.parse_program().unwrap();
+            assert_eq!(p.errors().first().map(String::as_str), Some(expected));
+        }
+    }
+
+    #[test]
+    pub fn test_nesting_limit() {
+        let too_deep = format!("expression nested too deeply (limit {})", MAX_NESTING_DEPTH);
+
+        // 上限以内正常解析
+        let depth = MAX_NESTING_DEPTH - 1;
+        let input = format!("{}1{}", "(".repeat(depth), ")".repeat(depth));
+        let mut p = Parser::new(Lexer::new(&input));
+        let program = p.parse_program().unwrap();
+        check_parser_errors(&p);
+        assert_eq!(program.string(), "1");
+
+        // 10 万个 ( 只报一个错误 不会把栈撑爆
+        let inputs = [
+            "(".repeat(100_000),
+            format!("{}1{}", "(".repeat(100_000), ")".repeat(100_000)),
+            format!("{}1", "-".repeat(100_000)),
+            format!("{}1{}", "fn() {".repeat(100_000), "}".repeat(100_000)),
+            format!("{}1{}", "if (true) {".repeat(100_000), "}".repeat(100_000)),
+            format!("let f = fn(x) {{ x }}; {}1", "f(".repeat(100_000)),
+        ];
+        for input in &inputs {
+            let mut p = Parser::new(Lexer::new(input));
+            p.parse_program().unwrap();
+            assert_eq!(p.errors(), vec![too_deep.clone()]);
+        }
+    }
+
+    // 左结合的长链不走递归 但建出来的 AST 一样深 也要算
+    #[test]
+    pub fn test_left_deep_chains() {
+        let too_deep = format!("expression nested too deeply (limit {})", MAX_NESTING_DEPTH);
+
+        let terms = MAX_NESTING_DEPTH - 1;
+        let input = vec!["1"; terms].join(" + ");
+        let mut p = Parser::new(Lexer::new(&input));
+        p.parse_program().unwrap();
+        check_parser_errors(&p);
+
+        let n = 20_000;
+        let inputs = [
+            vec!["1"; n].join(" + "),
+            format!("let a = 1; {}", vec!["a"; n].join(" ?? ")),
+            format!("let f = fn() {{ f }}; f{}", "()".repeat(n)),
+            format!("let xs = [1]; xs{}", "[0]".repeat(n)),
+            format!("let h = {{}}; h{}", ".a".repeat(n)),
+        ];
+        for input in &inputs {
+            let mut p = Parser::new(Lexer::new(input));
+            p.parse_program().unwrap();
+            assert_eq!(p.errors(), vec![too_deep.clone()]);
+        }
+    }
+
+    #[test]
+    pub fn test_else_if_chain_limit() {
+        let too_deep = format!("expression nested too deeply (limit {})", MAX_NESTING_DEPTH);
+        let chain = |n: usize| {
+            format!(
+                "if (true) {{ 0 }}{} else {{ 1 }}",
+                " else if (true) { 0 }".repeat(n)
+            )
+        };
+
+        let input = chain(100);
+        let mut p = Parser::new(Lexer::new(&input));
+        p.parse_program().unwrap();
+        check_parser_errors(&p);
+
+        let input = chain(20_000);
+        let mut p = Parser::new(Lexer::new(&input));
+        p.parse_program().unwrap();
+        assert_eq!(p.errors(), vec![too_deep]);
+    }
+
+    #[test]
+    pub fn test_type_nesting_limit() {
+        let too_deep = format!("type nested too deeply (limit {})", MAX_NESTING_DEPTH);
+
+        let depth = MAX_NESTING_DEPTH - 1;
+        let input = format!("let x: {}int{} = 1;", "[".repeat(depth), "]".repeat(depth));
+        let mut p = Parser::new(Lexer::new(&input));
+        p.parse_program().unwrap();
+        check_parser_errors(&p);
+
+        let n = 100_000;
+        let inputs = [
+            format!("let x: {}int{} = 1;", "[".repeat(n), "]".repeat(n)),
+            format!("fn(x: {}int{}) {{ x }}", "[".repeat(n), "]".repeat(n)),
+            format!("let f: {}int = 1;", "fn() -> ".repeat(n)),
+            format!("let f: {}int{} = 1;", "fn(".repeat(n), ") -> int".repeat(n)),
+        ];
+        for input in &inputs {
+            let mut p = Parser::new(Lexer::new(input));
+            p.parse_program().unwrap();
+            assert_eq!(p.errors(), vec![too_deep.clone()]);
+        }
+    }
+
+    #[test]
+    pub fn test_pattern_nesting_limit() {
+        let too_deep = format!("pattern nested too deeply (limit {})", MAX_NESTING_DEPTH);
+
+        let depth = MAX_NESTING_DEPTH - 1;
+        let input = format!("let {}a{} = 1;", "[".repeat(depth), "]".repeat(depth));
+        let mut p = Parser::new(Lexer::new(&input));
+        p.parse_program().unwrap();
+        check_parser_errors(&p);
+
+        let n = 100_000;
+        let inputs = [
+            format!("let {}a{} = 1;", "[".repeat(n), "]".repeat(n)),
+            format!("fn({}a{}) {{ 1 }}", "[".repeat(n), "]".repeat(n)),
+            format!("let {}a{} = 1;", "{a: ".repeat(n), "}".repeat(n)),
+        ];
+        for input in &inputs {
+            let mut p = Parser::new(Lexer::new(input));
+            p.parse_program().unwrap();
+            assert_eq!(p.errors(), vec![too_deep.clone()]);
+        }
+    }
+
+    #[test]
+    pub fn test_match_pattern_nesting_limit() {
+        let too_deep = format!("pattern nested too deeply (limit {})", MAX_NESTING_DEPTH);
+
+        // match 自己已经是一层表达式
+        let depth = MAX_NESTING_DEPTH - 2;
+        let input = format!(
+            "match (1) {{ {}a{} => 1, _ => 2 }}",
+            "[".repeat(depth),
+            "]".repeat(depth)
+        );
+        let mut p = Parser::new(Lexer::new(&input));
+        p.parse_program().unwrap();
+        check_parser_errors(&p);
+
+        // 字面量 通配符和 hash 模式嵌在里面也一样
+        let n = 100_000;
+        let inputs = [
+            format!("match (1) {{ {}a{} => 1 }}", "[".repeat(n), "]".repeat(n)),
+            format!(
+                "match (1) {{ _ => 1, {}_{} => 2 }}",
+                "[".repeat(n),
+                "]".repeat(n)
+            ),
+            format!(
+                "match (1) {{ {}-1{} => 1 }}",
+                "[{k: ".repeat(n),
+                "}]".repeat(n)
+            ),
+        ];
+        for input in &inputs {
+            let mut p = Parser::new(Lexer::new(input));
+            p.parse_program().unwrap();
+            assert_eq!(p.errors(), vec![too_deep.clone()]);
+        }
+    }
+
+    #[test]
+    pub fn test_syntax_errors_do_not_panic() {
+        let inputs = [
+            "f(1, 2", "if (x", "if (x) 1", "fn(x", "fn(x) x", "let = 5;", "let x 5;",
+        ];
+        for input in inputs {
+            let mut p = Parser::new(Lexer::new(input));
+            p.parse_program().unwrap();
+            assert!(!p.errors().is_empty(), "{}", input);
+        }
+    }
+
     // 辅助函数检查是否需要check_parser_errors()
     pub fn check_parser_errors(p: &Parser) {
         let errors = p.errors();
 
-        if errors.len() == 0 {
+        if errors.is_empty() {
             return;
         }
 
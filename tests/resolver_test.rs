@@ -0,0 +1,232 @@
+#[cfg(test)]
+mod resolver_test {
+    use monkeycc::{
+        ast::{Address, Expr, Program, Statement},
+        lexer::Lexer,
+        parser::Parser,
+    };
+
+    fn parse(input: &str) -> Program {
+        let mut p = Parser::new(Lexer::new(input));
+        let program = p.parse_program().unwrap();
+        assert!(p.errors().is_empty(), "{:?}", p.errors());
+        program
+    }
+
+    // 按出现顺序收集所有标识符的地址 (包括 let 的名字和参数)
+    fn addresses(program: &Program) -> Vec<(String, Address)> {
+        let mut out = Vec::new();
+        for stmt in &program.statements {
+            collect_statement(stmt, &mut out);
+        }
+        out
+    }
+
+    fn collect_statement(stmt: &Statement, out: &mut Vec<(String, Address)>) {
+        match stmt {
+            Statement::Let { pattern, value, .. } => {
+                collect_expr(value, out);
+                for name in pattern.idents() {
+                    out.push((name.0.clone(), name.1));
+                }
+            }
+            Statement::Import { name, .. } => out.push((name.0.clone(), name.1)),
+            Statement::Return(r) => collect_expr(&r.return_value, out),
+            Statement::Expression(e) => collect_expr(&e.expression, out),
+            Statement::Block(b) => b.statements.iter().for_each(|s| collect_statement(s, out)),
+            Statement::None => {}
+        }
+    }
+
+    fn collect_expr(e: &Expr, out: &mut Vec<(String, Address)>) {
+        match e {
+            Expr::Ident(i) => out.push((i.0.clone(), i.1)),
+            Expr::Prefix { right, .. } => collect_expr(right, out),
+            Expr::Infix { left, right, .. } => {
+                collect_expr(left, out);
+                collect_expr(right, out);
+            }
+            Expr::IfExpression {
+                condition,
+                consequence,
+                alternative,
+            } => {
+                collect_expr(condition, out);
+                collect_statement(consequence, out);
+                alternative.iter().for_each(|a| collect_statement(a, out));
+            }
+            Expr::Fn(f) => {
+                f.parameters
+                    .iter()
+                    .flat_map(|p| p.pattern.idents())
+                    .for_each(|name| out.push((name.0.clone(), name.1)));
+                collect_statement(&f.body, out);
+            }
+            Expr::Call {
+                function,
+                arguments,
+            } => {
+                collect_expr(function, out);
+                arguments.iter().for_each(|a| collect_expr(a, out));
+            }
+            Expr::Match { subject, arms } => {
+                collect_expr(subject, out);
+                for arm in arms {
+                    arm.pattern
+                        .idents()
+                        .into_iter()
+                        .for_each(|name| out.push((name.0.clone(), name.1)));
+                    arm.guard.iter().for_each(|g| collect_expr(g, out));
+                    collect_expr(&arm.body, out);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn local(depth: usize, slot: usize) -> Address {
+        Address::Local { depth, slot }
+    }
+
+    #[test]
+    fn test_addresses() {
+        let program = parse(
+            "let g = 1;
+             let f = fn(a, b) {
+                 let c = a + g;
+                 fn(d) { a + c + d }
+             };",
+        );
+        let expected = vec![
+            ("g", Address::Global),
+            ("a", local(0, 0)),
+            ("b", local(0, 1)),
+            ("a", local(0, 0)),
+            ("g", Address::Global),
+            ("c", local(0, 2)),
+            ("d", local(0, 0)),
+            ("a", local(1, 0)),
+            ("c", local(1, 2)),
+            ("d", local(0, 0)),
+            ("f", Address::Global),
+        ];
+        let actual = addresses(&program);
+        let actual: Vec<(&str, Address)> = actual.iter().map(|(n, a)| (n.as_str(), *a)).collect();
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn test_function_slots() {
+        let program =
+            parse("fn(x, y, x) { if (x) { let z = 1; } let w = 2; fn(q) { let inner = q; } }");
+        let Statement::Expression(e) = &program.statements[0] else {
+            panic!("expected expression statement");
+        };
+        let Expr::Fn(f) = &e.expression else {
+            panic!("expected function literal");
+        };
+        // 重名参数共用槽位 if 分支里的 let 也算 内层函数的 let 不算
+        assert_eq!(&*f.slots, ["x", "y", "z", "w"]);
+    }
+
+    #[test]
+    fn test_match_arms() {
+        // 每个分支一个环境: 模式的名字在前 分支里的 let 在后
+        let program = parse(
+            "let f = fn(x) {
+                 match (x) {
+                     [a, ...b] if a => a + x,
+                     n => if (n) { let m = n; m },
+                 }
+             };",
+        );
+        let expected = vec![
+            ("x", local(0, 0)),
+            ("x", local(0, 0)),
+            ("a", local(0, 0)),
+            ("b", local(0, 1)),
+            ("a", local(0, 0)),
+            ("a", local(0, 0)),
+            ("x", local(1, 0)),
+            ("n", local(0, 0)),
+            ("n", local(0, 0)),
+            ("n", local(0, 0)),
+            ("m", local(0, 1)),
+            ("m", local(0, 1)),
+            ("f", Address::Global),
+        ];
+        let actual = addresses(&program);
+        let actual: Vec<(&str, Address)> = actual.iter().map(|(n, a)| (n.as_str(), *a)).collect();
+        assert_eq!(actual, expected);
+
+        let Statement::Let { value, .. } = &program.statements[0] else {
+            panic!("expected let statement");
+        };
+        let Expr::Fn(f) = value else {
+            panic!("expected function literal");
+        };
+        // 分支里的名字不占外面函数的槽位
+        assert_eq!(&*f.slots, ["x"]);
+    }
+
+    #[test]
+    fn test_constant_assignment() {
+        let errors = |input: &str| {
+            let mut p = Parser::new(Lexer::new(input));
+            p.parse_program().unwrap();
+            p.errors()
+        };
+        let clean = [
+            "const PI = 3; let x = 1; x = PI;",
+            // 内层作用域可以遮住常量 赋值给的是内层的绑定
+            "const PI = 3; let f = fn() { let PI = 4; PI = 5; };",
+            "const PI = 3; let f = fn(PI) { PI += 1 };",
+            "let f = fn() { const n = 1; fn(n) { n = 2 } };",
+            "const k = 1; match (2) { k => k = 3 };",
+        ];
+        for input in clean {
+            assert_eq!(errors(input), Vec::<String>::new(), "input: {}", input);
+        }
+
+        let tests = [
+            ("const PI = 3; PI = 4;", "cannot assign to constant PI"),
+            (
+                "const PI = 3; let f = fn() { PI += 1 };",
+                "cannot assign to constant PI",
+            ),
+            (
+                "let f = fn() { const n = 1; fn() { n = 2 } };",
+                "cannot assign to constant n",
+            ),
+            // 函数可以在常量定义之前写 调用时常量已经绑定了
+            (
+                "let f = fn() { LIMIT = 0 }; const LIMIT = 10;",
+                "cannot assign to constant LIMIT",
+            ),
+            ("const xs = [1]; xs[0] = 2;", "cannot assign to constant xs"),
+            (
+                r#"const cfg = {"a": {}}; cfg.a.b = 1;"#,
+                "cannot assign to constant cfg",
+            ),
+        ];
+        for (input, expected) in tests {
+            assert_eq!(errors(input), vec![expected], "input: {}", input);
+        }
+
+        // 给常量赋值不是语法错误 命令行按静态检查的错误报
+        let mut p = Parser::new(Lexer::new("const a = 1; a = 2; let = 3;"));
+        p.parse_program().unwrap();
+        assert_eq!(p.resolve_errors(), vec!["cannot assign to constant a"]);
+        assert_eq!(
+            p.syntax_errors(),
+            vec!["Expected next token to be Ident, got Assign instead"]
+        );
+        assert_eq!(
+            p.errors(),
+            vec![
+                "Expected next token to be Ident, got Assign instead",
+                "cannot assign to constant a"
+            ]
+        );
+    }
+}
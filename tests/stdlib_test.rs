@@ -0,0 +1,134 @@
+#[cfg(test)]
+mod stdlib_test {
+    use monkeycc::{
+        analyzer::Analyzer,
+        ast::Program,
+        evaluator::{Interpreter, eval},
+        lexer::Lexer,
+        parser::Parser,
+        stdlib,
+    };
+
+    fn parse(input: &str) -> Program {
+        let mut p = Parser::new(Lexer::new(input));
+        let program = p.parse_program().unwrap();
+        assert!(p.errors().is_empty(), "{:?}", p.errors());
+        program
+    }
+
+    fn run(input: &str) -> String {
+        eval(&parse(input)).inspect()
+    }
+
+    #[test]
+    fn test_modules_are_clean() {
+        for (name, source) in stdlib::MODULES {
+            let diagnostics = Analyzer::new().prelude(false).analyze(&parse(source));
+            assert!(diagnostics.is_empty(), "{}: {:?}", name, diagnostics);
+        }
+        let names = stdlib::prelude_names();
+        for name in ["map", "filter", "reduce", "range", "zip", "sort"] {
+            assert!(names.iter().any(|n| n == name), "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_prelude_functions() {
+        let tests = [
+            ("map([1, 2, 3], fn(x) { x * 2 })", "[2, 4, 6]"),
+            ("map([], fn(x) { x })", "[]"),
+            (
+                "filter(range(0, 10), fn(x) { x / 3 * 3 == x })",
+                "[0, 3, 6, 9]",
+            ),
+            ("reduce([1, 2, 3, 4], 0, fn(acc, x) { acc + x })", "10"),
+            ("reduce([], 7, fn(acc, x) { acc + x })", "7"),
+            ("range(3, 6)", "[3, 4, 5]"),
+            ("range(6, 3)", "[]"),
+            (r#"zip([1, 2, 3], ["a", "b"])"#, "[[1, a], [2, b]]"),
+            ("sort([5, 3, 9, 1, 1, 0, -4])", "[-4, 0, 1, 1, 3, 5, 9]"),
+            ("sort([])", "[]"),
+            (
+                "sort_by([[2, 1], [1, 2], [2, 3], [1, 4]], fn(a, b) { a[0] < b[0] })",
+                "[[1, 2], [1, 4], [2, 1], [2, 3]]",
+            ),
+            (r#"join(["a", "b", "c"], ", ")"#, "a, b, c"),
+            (r#"join([], ", ")"#, ""),
+        ];
+        for (input, expected) in tests {
+            assert_eq!(run(input), expected, "input: {}", input);
+        }
+    }
+
+    // 一个用到多个函数的程序 顺便确认尾递归的循环在长数组上不会爆栈
+    #[test]
+    fn test_pipeline() {
+        let input = r#"
+            let words = ["pear", "fig", "apple", "kiwi", "banana"];
+            let lengths = map(words, fn(w) { len(w) });
+            let long = filter(zip(words, lengths), fn(p) { p[1] > 3 });
+            let total = reduce(long, 0, fn(acc, p) { acc + p[1] });
+            let xs = sort(map(range(0, 3000), fn(x) { (x * 7919) - (x * 7919) / 3001 * 3001 }));
+            [join(map(long, fn(p) { p[0] }), "+"), total, len(xs), first(xs), last(xs)]
+        "#;
+        assert_eq!(run(input), "[pear+apple+kiwi+banana, 19, 3000, 0, 3000]");
+    }
+
+    // 结果数组先开好再用下标填 长度几千到几万也是线性的
+    #[test]
+    fn test_large_inputs() {
+        let input = "
+            let xs = range(0, 20000);
+            let doubled = map(xs, fn(x) { x * 2 });
+            let small = filter(doubled, fn(x) { x < 10000 });
+            let pairs = zip(xs, small);
+            let sorted = sort(map(range(0, 5000), fn(x) { 0 - x }));
+            [len(xs), last(doubled), len(small), len(pairs), last(pairs), first(sorted), last(sorted)]
+        ";
+        assert_eq!(
+            run(input),
+            "[20000, 39998, 5000, 5000, [4999, 9998], -4999, 0]"
+        );
+    }
+
+    #[test]
+    fn test_prelude_flag() {
+        let mut interp = Interpreter::new();
+        interp.run(&parse("1")).unwrap();
+        assert!(interp.env().borrow().get("map").is_some());
+
+        let mut interp = Interpreter::new().prelude(false);
+        let err = interp.run(&parse("map([1], fn(x) { x })")).unwrap_err();
+        assert_eq!(err.to_string(), "identifier not found: map");
+        // 内建函数不属于 prelude
+        assert_eq!(interp.eval(&parse("len([1])")).inspect(), "1");
+
+        let program = parse("map([1], fn(x) { x })");
+        assert!(Analyzer::new().analyze(&program).is_empty());
+        let diagnostics = Analyzer::new().prelude(false).analyze(&program);
+        assert_eq!(diagnostics[0].message, "undefined identifier: map");
+    }
+
+    #[test]
+    fn test_prelude_is_isolated() {
+        // 覆盖 prelude 用到的名字不影响它 覆盖 prelude 自己的名字也可以
+        assert_eq!(
+            run(
+                "let len = fn(x) { 0 }; let push = 1; let array = 1; let slice = 1;
+                 filter(map([1, 2], fn(x) { x + 1 }), fn(x) { x > 2 })"
+            ),
+            "[3]"
+        );
+        assert_eq!(run("let map = 5; map"), "5");
+        // 也可以当模块 import 和 prelude 是同一份
+        assert_eq!(
+            run(r#"import "std/list" as list; list.map(list.range(0, 3), fn(x) { -x })"#),
+            "[0, -1, -2]"
+        );
+
+        // prelude 的闭包不算在内存上限里
+        let mut interp = Interpreter::new().max_memory(64);
+        assert_eq!(interp.eval(&parse("1")).inspect(), "1");
+        assert_eq!(interp.memory().used, 0);
+    }
+}
@@ -0,0 +1,317 @@
+#[cfg(test)]
+mod typechecker_test {
+    use monkeycc::{
+        ast::Program,
+        lexer::Lexer,
+        object::ObjectType,
+        parser::Parser,
+        typechecker::{Type, check, infer},
+    };
+
+    fn parse(input: &str) -> Program {
+        let mut p = Parser::new(Lexer::new(input));
+        let program = p.parse_program().unwrap();
+        assert!(p.errors().is_empty(), "{:?}", p.errors());
+        program
+    }
+
+    fn infer_type(input: &str) -> String {
+        match infer(&parse(input)) {
+            Ok(ty) => ty.to_string(),
+            Err(diagnostics) => panic!("input: {} errors: {:?}", input, diagnostics),
+        }
+    }
+
+    fn errors(input: &str) -> Vec<String> {
+        check(&parse(input))
+            .into_iter()
+            .map(|d| d.message)
+            .collect()
+    }
+
+    #[test]
+    fn test_infer_expressions() {
+        let tests = [
+            ("5", "int"),
+            ("!5", "bool"),
+            ("1 + 2 * 3", "int"),
+            ("1 < 2", "bool"),
+            ("true == false", "bool"),
+            ("if (true) { 1 } else { 2 }", "int"),
+            ("if (true) { 1 } else if (false) { 2 } else { 3 }", "int"),
+            ("let x = 5;", "null"),
+            ("let x = 5; x", "int"),
+        ];
+
+        for (input, expected) in tests {
+            assert_eq!(infer_type(input), expected, "input: {}", input);
+        }
+    }
+
+    #[test]
+    fn test_infer_functions() {
+        let tests = [
+            ("fn(x) { x + 1 }", "fn(int) -> int"),
+            ("fn(x) { x }", "fn('a) -> 'a"),
+            ("fn(f, x) { f(x) }", "fn(fn('a) -> 'b, 'a) -> 'b"),
+            ("fn(x) { fn(y) { x == y } }", "fn('a) -> fn('a) -> bool"),
+            ("fn() { let y = 1; }", "fn() -> null"),
+            // 递归
+            (
+                "let fib = fn(n) { if (n < 2) { return n; } fib(n - 1) + fib(n - 2) }; fib",
+                "fn(int) -> int",
+            ),
+            // let 多态: id 在两处分别用成 int 和 bool
+            (
+                "let id = fn(x) { x }; if (id(true)) { id(1) } else { 2 }",
+                "int",
+            ),
+            // 推不出来的名字当作 any
+            ("fn(x) { later(x) }", "fn('a) -> any"),
+        ];
+
+        for (input, expected) in tests {
+            assert_eq!(infer_type(input), expected, "input: {}", input);
+        }
+    }
+
+    #[test]
+    fn test_type_errors() {
+        let tests = [
+            ("1 + true;", "type mismatch: int + bool"),
+            ("-true;", "unknown operator: -bool"),
+            ("1 == false;", "type mismatch: int == bool"),
+            (
+                "if (true) { 1 } else { false };",
+                "if branches have different types: int and bool",
+            ),
+            (
+                "if (true) { 1 } else if (false) { 2 } else { false };",
+                "if branches have different types: int and bool",
+            ),
+            (
+                "let f = fn(x) { if (x) { return 1; } true };",
+                "return type mismatch: int and bool",
+            ),
+            (
+                "let add = fn(a, b) { a + b }; add(1, true);",
+                "argument 2 of add: expected int, got bool",
+            ),
+            (
+                "let add = fn(a, b) { a + b }; add(1);",
+                "wrong number of arguments to add: want=2, got=1",
+            ),
+            ("let x = 1; x(2);", "cannot call x of type int"),
+            (
+                "fn(f) { f(f) };",
+                "cannot infer a type for f: it would contain itself",
+            ),
+        ];
+
+        for (input, expected) in tests {
+            assert_eq!(errors(input), vec![expected], "input: {}", input);
+        }
+    }
+
+    #[test]
+    fn test_annotations() {
+        let tests = [
+            ("fn(x: bool) { x }", "fn(bool) -> bool"),
+            ("fn(x) -> int { x }", "fn(int) -> int"),
+            ("let x: int = 5; x", "int"),
+            ("let f: fn(int) -> int = fn(x) { x }; f", "fn(int) -> int"),
+            ("fn(x: any) { x + 1 }", "fn(any) -> int"),
+        ];
+        for (input, expected) in tests {
+            assert_eq!(infer_type(input), expected, "input: {}", input);
+        }
+
+        let tests = [
+            ("let x: bool = 5;", "let x: expected bool, got int"),
+            ("fn(x: bool) { x + 1 };", "type mismatch: bool + int"),
+            (
+                "fn(x) -> int { x == 1 };",
+                "return type mismatch: int and bool",
+            ),
+        ];
+        for (input, expected) in tests {
+            assert_eq!(errors(input), vec![expected], "input: {}", input);
+        }
+    }
+
+    #[test]
+    fn test_error_positions() {
+        let diagnostics = check(&parse("let a = 1;\nlet b = a + true;\nlet c = !b;"));
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].to_string(), "2:1: type mismatch: int + bool");
+    }
+
+    #[test]
+    fn test_object_types() {
+        assert_eq!(Type::Int.object_type(), Some(ObjectType::Integer));
+        assert_eq!(Type::Bool.object_type(), Some(ObjectType::Boolean));
+        assert_eq!(
+            Type::Function(vec![], Box::new(Type::Int)).object_type(),
+            Some(ObjectType::Function)
+        );
+        assert_eq!(Type::String.object_type(), Some(ObjectType::String));
+        assert_eq!(
+            Type::Array(Box::new(Type::Int)).object_type(),
+            Some(ObjectType::Array)
+        );
+        assert_eq!(Type::Any.object_type(), None);
+    }
+
+    #[test]
+    fn test_assignment() {
+        let tests = [
+            ("let x = 1; x = 2", "int"),
+            ("let x = 1; x += 2", "int"),
+            (r#"let s = "a"; s += "b""#, "string"),
+            ("let xs = [1, 2]; xs[0] = 5", "int"),
+            (r#"let h = {"a": 1}; h["b"] = true"#, "bool"),
+            (
+                "let f = fn(xs: [int]) { xs[0] = 1; xs }; f",
+                "fn([int]) -> [int]",
+            ),
+        ];
+        for (input, expected) in tests {
+            assert_eq!(infer_type(input), expected, "input: {}", input);
+        }
+
+        let tests = [
+            (
+                r#"let x = 1; x = "a""#,
+                "assignment to x: expected int, got string",
+            ),
+            (
+                "let xs = [1]; xs[0] = true",
+                "assignment to (xs[0]): expected int, got bool",
+            ),
+            (r#"let x = 1; x += "a""#, "type mismatch: int + string"),
+        ];
+        for (input, expected) in tests {
+            assert_eq!(
+                errors(input),
+                vec![expected.to_string()],
+                "input: {}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn test_destructuring() {
+        let tests = [
+            ("let [a, b] = [1, 2]; a", "int"),
+            ("let [a, ...rest] = [true]; rest", "[bool]"),
+            ("let f = fn([x, y]) { x + y }; f", "fn([int]) -> int"),
+        ];
+        for (input, expected) in tests {
+            assert_eq!(infer_type(input), expected, "input: {}", input);
+        }
+        assert_eq!(
+            errors("let [a] = 5;"),
+            vec!["cannot destructure [a] of type int".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_match() {
+        let tests = [
+            (r#"match (1) { 0 => "zero", n => "other" }"#, "string"),
+            ("match ([1, 2]) { [a, b] => a + b, _ => 0 }", "int"),
+            (
+                "let f = fn(x) { match (x) { 0 => true, _ => false } }; f",
+                "fn(int) -> bool",
+            ),
+            // 有的分支匹配数字 有的匹配数组 主体当作 any
+            ("match (1) { 0 => 0, [a] => a, {r} => r * r }", "int"),
+        ];
+        for (input, expected) in tests {
+            assert_eq!(infer_type(input), expected, "input: {}", input);
+        }
+
+        let tests = [
+            (
+                r#"match (1) { 0 => "zero", _ => 1 }"#,
+                "match arms have different types: string and int",
+            ),
+            (
+                r#"match ("s") { 0 => 1, _ => 2 }"#,
+                "pattern 0 cannot match type string",
+            ),
+        ];
+        for (input, expected) in tests {
+            assert_eq!(
+                errors(input),
+                vec![expected.to_string()],
+                "input: {}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn test_conditional_and_null_operators() {
+        let tests = [
+            ("true ? 1 : 2", "int"),
+            (
+                r#"let f = fn(x) { x > 0 ? "pos" : "neg" }; f"#,
+                "fn(int) -> string",
+            ),
+            ("let x = 1; x ?? 2", "int"),
+            (r#"let h = {"a": 1}; h["a"] ?? 0"#, "any"),
+            ("let xs = [1]; xs?[0]", "int"),
+        ];
+        for (input, expected) in tests {
+            assert_eq!(infer_type(input), expected, "input: {}", input);
+        }
+        assert_eq!(
+            errors("true ? 1 : false"),
+            vec!["?: branches have different types: int and bool".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_strings_and_arrays() {
+        let tests = [
+            (r#""a" + "b""#, "string"),
+            (r#""a" == "b""#, "bool"),
+            ("[1, 2, 3]", "[int]"),
+            ("[[true], []]", "[[bool]]"),
+            ("[1, 2][0]", "int"),
+            ("fn(s) { s + \"!\" }", "fn(string) -> string"),
+            ("fn(xs: [int]) { xs[0] * 2 }", "fn([int]) -> int"),
+            (r#""abc"[1]"#, "string"),
+            (r#"{"a": 1}["a"]"#, "any"),
+            (r#"fn(x) { "x = ${x}" }"#, "fn('a) -> string"),
+        ];
+        for (input, expected) in tests {
+            assert_eq!(infer_type(input), expected, "input: {}", input);
+        }
+
+        let tests = [
+            (
+                r#"[1, "a"]"#,
+                "array elements have different types: int and string",
+            ),
+            (r#"[1][true]"#, "index of [1]: expected int, got bool"),
+            ("let n = 5; n[0]", "cannot index n of type int"),
+            (
+                r#"let s = "a"; s[true]"#,
+                "index of s: expected int, got bool",
+            ),
+            (r#""a" + 1"#, "type mismatch: string + int"),
+            (r#""a" < "b""#, "type mismatch: string < string"),
+        ];
+        for (input, expected) in tests {
+            assert_eq!(
+                errors(input),
+                vec![expected.to_string()],
+                "input: {}",
+                input
+            );
+        }
+    }
+}
@@ -0,0 +1,219 @@
+#[cfg(test)]
+mod vm_test {
+    use monkeycc::{evaluator::eval, lexer::Lexer, object::Object, parser::Parser, vm};
+
+    // 两个后端的结果必须一致 evaluator_test 里每个 test_eval 的输入也会在 vm 上对比一遍
+    const PARITY_CASES: &[&str] = &[
+        // integer
+        "5",
+        "-10",
+        "5 + 5 + 5 + 5 - 10",
+        "2 * 2 * 2 * 2 * 2",
+        "-50 + 100 + -50",
+        "20 + 2 * -10",
+        "50 / 2 * 2 + 10",
+        "3 * (3 * 3) + 10",
+        "(5 + 10 * 2 + 15 / 3) * 2 + -10",
+        // boolean
+        "true",
+        "1 < 2",
+        "1 > 1",
+        "1 <= 1",
+        "2 >= 3",
+        "1 != 2",
+        "true == false",
+        "(1 < 2) == true",
+        "(1 > 2) == false",
+        "!5",
+        "!!true",
+        // if
+        "if (true) {10}",
+        "if (false) {10}",
+        "if (1) { 10 }",
+        "if (1 > 2) { 10 } else { 20 }",
+        "if (1 < 2) { 10 } else { 20 }",
+        "if (true) { let x = 1; }",
+        "if (true) { 1; let x = 2; }",
+        "if (1 > 2) { 10 } else if (2 > 1) { 20 } else { 30 }",
+        "if (false) { 10 } else if (false) { 20 }",
+        "1 < 2 ? 10 : 20",
+        "0 ? 10 : false ? 20 : 30",
+        // return
+        "return 10; 9;",
+        "9; return 2 * 5; 9;",
+        "if (10 > 1) { if (10 > 1) { return 10 } return 1; }",
+        // errors
+        "5 + true;",
+        "5 + true; 5;",
+        "-true",
+        "true + false;",
+        "5; true + false; 5;",
+        "if (10 > 1) { true + false; }",
+        "if (10 > 1) { if (10 > 1) { return true + false; } return 1; }",
+        "1 / 0;",
+        "foobar",
+        "5(1)",
+        "fn(x) { x }()",
+        "let f = fn() { g }; f()",
+        // let
+        "let a = 5; a;",
+        "let a = 5; let b = a; let c = a + b + 5; c;",
+        "let a = 1; let a = a + 1; a;",
+        "5; let a = 1;",
+        // functions & closures
+        "let identity = fn(x) { x; }; identity(5);",
+        "let add = fn(x, y) { x + y; }; add(5 + 5, add(5, 5));",
+        "fn(x) { x; }(5)",
+        "let early = fn(x) { if (x > 1) { return 1; } return 2; }; early(5) + early(0);",
+        "let noop = fn() { }; noop();",
+        "let newAdder = fn(x) { fn(y) { x + y } }; let addTwo = newAdder(2); addTwo(2);",
+        "let newAdder = fn(a, b) { let c = a + b; fn(d) { let e = d + c; fn(f) { e + f } } }; newAdder(1, 2)(3)(4)",
+        "let fib = fn(n) { if (n < 2) { return n; } fib(n - 1) + fib(n - 2) }; fib(15);",
+        "let wrapper = fn() { let countDown = fn(x) { if (x == 0) { return 0; } countDown(x - 1) }; countDown(3) }; wrapper();",
+        "let f = fn() { g() }; let g = fn() { 7 }; f()",
+        // truthiness: 函数和闭包都是真
+        "!fn() { 1 }",
+        "let f = fn() { 1 }; if (f) { 1 } else { 2 }",
+        "let f = fn(x) { fn() { x } }; f(1) ? 1 : 2",
+        "!0",
+        "if (0) { 1 } else { 2 }",
+        // 尾调用复用帧 同名参数后面的生效 没执行到的 let 读不到
+        "let f = fn(n) { if (n == 0) { 0 } else { f(n - 1) } }; f(100000)",
+        "let f = fn(n) { n == 0 ? 0 : f(n - 1) }; f(100000)",
+        "let f = fn(a, a) { a }; f(1, 2)",
+        "let f = fn(c) { if (c) { let y = 5; } y }; f(false)",
+        "let f = fn(c) { if (c) { let y = 5; } y }; f(true)",
+        // 没有被捕获的局部变量可以重新 let
+        "let g = fn() { let x = 1; let x = x + 1; x }; g()",
+        "let g = fn() { let x = 1; let h = fn() { x }; h() + 1 }; g()",
+        // 和内建函数同名的绑定照常编译
+        "let len = fn(x) { x + 1 }; len(2)",
+    ];
+
+    #[test]
+    fn test_vm_matches_evaluator() {
+        for input in PARITY_CASES {
+            let program = Parser::new(Lexer::new(input)).parse_program().unwrap();
+            let tree = eval(&program);
+            let vm = vm::run(&program);
+            assert_eq!(
+                tree.inspect(),
+                vm.inspect(),
+                "\ninput: {}\ntree: {:?}\nvm:   {:?}",
+                input,
+                tree,
+                vm
+            );
+        }
+    }
+
+    // vm 还没实现的语法要明确报不支持 不能悄悄算出别的结果
+    #[test]
+    fn test_unsupported_features() {
+        let tests = [
+            ("let f = fn(x) { x * 2 }; [f(2), f(1), f(0)]", "array"),
+            (r#""a" + "b""#, "string"),
+            ("let [a, b] = [1, 2]; a", "destructuring"),
+            ("let f = fn([a]) { a }; 1", "destructuring"),
+            ("match (1) { 1 => 2, _ => 3 }", "match"),
+            ("1 ?? 2", "??"),
+            ("puts(1)", "builtin"),
+            ("let x = 1; len(x)", "builtin"),
+            (
+                "let g = fn() { let x = 1; let h = fn() { x }; let x = 2; h() }; g()",
+                "re-declaring a captured local",
+            ),
+            (
+                "let g = fn(x) { let h = fn() { fn() { x } }; let x = 2; h()() }; g(1)",
+                "re-declaring a captured local",
+            ),
+        ];
+        for (input, feature) in tests {
+            let msg = compile_error(input);
+            let expected = format!("not supported by the vm yet: {} `", feature);
+            assert!(msg.starts_with(&expected), "{}", msg);
+        }
+    }
+
+    #[test]
+    fn test_recursion_too_deep() {
+        // 尾调用会复用帧 这里的递归不在尾位置
+        let input = "let f = fn(n) { f(n + 1) + 1 }; f(0)";
+        let program = Parser::new(Lexer::new(input)).parse_program().unwrap();
+        match vm::run(&program) {
+            Object::Error(msg) => assert!(msg.contains("stack overflow"), "{}", msg),
+            other => panic!("expected stack overflow, got {:?}", other),
+        }
+    }
+
+    // 标识符不能带数字 用字母拼出第 i 个变量名
+    fn var(i: usize) -> String {
+        let hi = (b'a' + (i / 26) as u8) as char;
+        let lo = (b'a' + (i % 26) as u8) as char;
+        format!("v{}{}", hi, lo)
+    }
+
+    fn compile_error(input: &str) -> String {
+        let program = Parser::new(Lexer::new(input)).parse_program().unwrap();
+        match vm::run(&program) {
+            Object::Error(msg) => msg,
+            other => panic!("expected a compile error, got {:?}", other),
+        }
+    }
+
+    // 操作数放不进编码宽度时必须报错 不能截断成别的槽位
+    #[test]
+    fn test_operand_limits() {
+        // 300 个局部变量: 第 257 个槽位放不进 u8
+        let lets: String = (0..300)
+            .map(|i| format!("let {} = {}; ", var(i), i))
+            .collect();
+        let input = format!("fn() {{ {}{} }}()", lets, var(0));
+        let msg = compile_error(&input);
+        assert!(
+            msg.contains("local index 256 does not fit in OpSetLocal"),
+            "{}",
+            msg
+        );
+
+        // 7 万个常量: 常量池下标放不进 u16
+        let consts: String = (0..70_000).map(|_| "1; ").collect();
+        let msg = compile_error(&format!("{}5", consts));
+        assert!(
+            msg.contains("constant index 65536 does not fit in OpConstant"),
+            "{}",
+            msg
+        );
+
+        // 256 个参数
+        let args = vec!["1"; 256].join(", ");
+        let msg = compile_error(&format!("fn() {{ 1 }}({})", args));
+        assert!(
+            msg.contains("argument count 256 does not fit in OpCall"),
+            "{}",
+            msg
+        );
+
+        // 捕获 256 个自由变量
+        let lets: String = (0..256)
+            .map(|i| format!("let {} = {}; ", var(i), i))
+            .collect();
+        // 每条语句读一个变量 一条加法链会超过解析器的嵌套上限
+        let reads: String = (0..256).map(|i| format!("{}; ", var(i))).collect();
+        let msg = compile_error(&format!("fn() {{ {}fn() {{ {}0 }} }}()()", lets, reads));
+        assert!(
+            msg.contains("free variable count 256 does not fit in OpClosure"),
+            "{}",
+            msg
+        );
+
+        // if 分支超过 64KB 跳转地址放不进 u16
+        let body: String = (0..20_000).map(|_| "1; ").collect();
+        let msg = compile_error(&format!("if (true) {{ {} }}", body));
+        assert!(
+            msg.contains("jump target") && msg.contains("does not fit in OpJumpNotTruthy"),
+            "{}",
+            msg
+        );
+    }
+}